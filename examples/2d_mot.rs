@@ -1,175 +1,60 @@
 //! A 2D+ mot configuration, loaded directly from oven.
+//!
+//! The simulation itself is built by the [Mot2DPlugin].
 
-use atomecs::atom::{Atom, Force, Mass};
-use atomecs::atom::{Position, Velocity};
-use atomecs::initiate::NewlyCreated;
-use atomecs::integrator::Timestep;
-use atomecs::laser::gaussian::GaussianBeam;
-use atomecs::laser::LaserPlugin;
-use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
-use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
-use atomecs::laser_cooling::{CoolingLight, LaserCoolingPlugin};
-use atomecs::magnetic::quadrupole::QuadrupoleField3D;
-use atomecs::shapes::Cuboid;
-use atomecs::sim_region::{SimulationRegionPlugin, SimulationVolume, VolumeType};
 use atomecs::species::Strontium88_461;
 use atomecs_demos::atoms::{add_meshes_to_atoms, EmissiveColorConfig};
 use atomecs_demos::camera::{control_camera, DemoCamera};
+use atomecs_demos::controls::ControlsPlugin;
+use atomecs_demos::experiment::controls_ui;
+use atomecs_demos::hud::HudPlugin;
+use atomecs_demos::mot_2d::{ExperimentConfiguration, Mot2DPlugin};
+use atomecs_demos::presets::{presets_ui, Presets};
+use atomecs_demos::stepping::{PresentationStage, SteppingPlugin};
 use atomecs_demos::{add_atomecs_watermark, BevyAtomECSPlugin};
 use bevy::prelude::*;
-use nalgebra::Vector3;
-use rand_distr::{Distribution, Normal};
-
-const BEAM_NUMBER: usize = 6;
+use bevy_egui::{egui, EguiContext, EguiPlugin};
 
 fn main() {
     let mut app = App::new();
-    app.add_plugin(atomecs::integrator::IntegrationPlugin);
-    app.add_plugin(atomecs::initiate::InitiatePlugin);
-    app.add_plugin(atomecs::magnetic::MagneticsPlugin);
-    app.add_plugin(LaserPlugin::<{ BEAM_NUMBER }>);
-    app.add_plugin(LaserCoolingPlugin::<Strontium88_461, { BEAM_NUMBER }>::default());
-    app.add_plugin(SimulationRegionPlugin);
+    app.add_plugin(Mot2DPlugin);
     app.add_plugin(BevyAtomECSPlugin);
     app.add_system(atomecs::output::console_output::console_output);
     app.add_plugins(DefaultPlugins);
-    app.add_system(atomecs::bevy_bridge::copy_positions);
-    app.add_system(add_meshes_to_atoms::<Strontium88_461>);
-    app.add_startup_system(setup_world);
-    app.add_system(create_atoms);
+    app.add_plugin(EguiPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(ControlsPlugin);
+    app.add_plugin(HudPlugin);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<Strontium88_461>);
     app.add_startup_system(setup_camera);
-    app.add_system(control_camera);
+    app.add_system_to_stage(PresentationStage, control_camera);
     app.add_startup_system(add_atomecs_watermark);
     app.insert_resource(atomecs::bevy_bridge::Scale { 0: 7e1 });
-    app.insert_resource(Timestep { delta: 2.0e-5 });
-    app.insert_resource(EmissionForceOption::On(EmissionForceConfiguration {
-        explicit_threshold: 5,
-    }));
     app.insert_resource(EmissiveColorConfig { factor: 8.0 });
-    app.add_system(
-        atomecs_demos::atoms::update_emissive_color::<Strontium88_461>
-            .after(atomecs::laser_cooling::LaserCoolingSystems::CalculateActualPhotonsScattered),
+    // Runs after the simulation steps, so reads the photons scattered in the final step of each frame.
+    app.add_system_to_stage(
+        PresentationStage,
+        atomecs_demos::atoms::update_emissive_color::<Strontium88_461>,
     );
-    app.insert_resource(ScatteringFluctuationsOption::On);
+    app.init_resource::<ExperimentConfiguration>();
+    app.insert_resource(Presets::<ExperimentConfiguration>::new("2d_mot"));
+    app.add_system_to_stage(PresentationStage, experiment_controls);
     app.run();
 }
 
-pub fn setup_world(mut commands: Commands) {
-    // Create magnetic field.
-    commands
-        .spawn(QuadrupoleField3D::gauss_per_cm(27.0, Vector3::z()))
-        .insert(Position::default());
-
-    // Push beam along z
-    let push_beam_radius = 1e-3;
-    let push_beam_power = 0.020;
-    let push_beam_detuning = -103.0;
-
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: push_beam_radius,
-            power: push_beam_power,
-            direction: Vector3::z(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            push_beam_detuning,
-            -1,
-        ));
-
-    // Create cooling lasers.
-    let detuning = -40.0;
-    let power = 0.23;
-    let radius = 17.0e-3; //33.0e-3 / (2.0 * 2.0_f64.sqrt()); // 33mm 1/e^2 diameter
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(1.0, 1.0, 0.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(detuning, 1));
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(1.0, -1.0, 0.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(detuning, 1));
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(-1.0, 1.0, 0.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(detuning, 1));
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(-1.0, -1.0, 0.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(detuning, 1));
-
-    // Use a simulation bound so that atoms that escape the capture region are deleted from the simulation.
-    commands
-        .spawn(Position {
-            pos: Vector3::new(0.0, 0.0, 0.0),
-        })
-        .insert(Cuboid {
-            half_width: Vector3::new(0.1, 0.01, 0.01),
-        })
-        .insert(SimulationVolume {
-            volume_type: VolumeType::Inclusive,
-        });
-
-    // The simulation bound also now includes a small pipe to capture the 2D MOT output properly.
-    commands
-        .spawn(Position {
-            pos: Vector3::new(0.0, 0.0, 0.1),
-        })
-        .insert(Cuboid {
-            half_width: Vector3::new(0.01, 0.01, 0.1),
-        })
-        .insert(SimulationVolume {
-            volume_type: VolumeType::Inclusive,
-        });
-}
-
-fn create_atoms(mut commands: Commands) {
-    let vel_dist = Normal::new(0.0, 2.0).unwrap();
-    let mut rng = rand::thread_rng();
-
-    // Add atoms
-    commands
-        .spawn(Position {
-            pos: Vector3::new(-0.08, 0.0, 0.0),
-        })
-        .insert(Velocity {
-            vel: Vector3::new(
-                vel_dist.sample(&mut rng) * 5.0 + 50.0,
-                vel_dist.sample(&mut rng) * 2.0,
-                vel_dist.sample(&mut rng) * 2.0,
-            ),
-        })
-        .insert(Force::default())
-        .insert(Mass { value: 88.0 })
-        .insert(Strontium88_461)
-        .insert(Atom)
-        .insert(NewlyCreated);
+/// Sliders for the beams and field, and the saved presets, such as those found by the `optimise`
+/// runner.
+fn experiment_controls(
+    mut egui_context: ResMut<EguiContext>,
+    mut config: ResMut<ExperimentConfiguration>,
+    mut presets: ResMut<Presets<ExperimentConfiguration>>,
+) {
+    egui::Window::new("2D MOT").show(egui_context.ctx_mut(), |ui| {
+        controls_ui(ui, &mut config);
+        ui.separator();
+        presets_ui(ui, &mut config, &mut presets);
+    });
 }
 
 fn setup_camera(mut commands: Commands) {
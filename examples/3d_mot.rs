@@ -1,60 +1,34 @@
-//! # Doppler Sweep
+//! # Doppler Limit
 //!
 //! Simulate a cloud of atoms in a 3D MOT to measure the Doppler temperature limit for laser cooling.
 //!
-//! The Doppler Limit depends on temperature, see eg https://journals.aps.org/prl/abstract/10.1103/PhysRevLett.61.169.
+//! The Doppler limit ħΓ/2k_B is the lowest temperature reached by Doppler cooling, at a detuning of
+//! -Γ/2, see eg https://journals.aps.org/prl/abstract/10.1103/PhysRevLett.61.169.
 //!
-//! Some parameters of the simulation can be set by writing a configuration file called `doppler.json`. This file
-//! allows the user to control parameters, eg detuning. If the file is not written, a default detuning of 0.5 Gamma
-//! is used, which corresponds to the minimum Doppler temperature.
+//! Parameters of the simulation, eg the detuning, are read from the [DopplerConfiguration] in
+//! `assets/doppler.json`, or the defaults are used if it is missing. To sweep the detuning and
+//! compare the minimum temperature with the Doppler limit, run the `doppler_sweep` binary with
+//! `assets/doppler_validation.json`.
 //!
-//! cargo build --example doppler_limit --target wasm32-unknown-unknown --release
-//! wasm-bindgen --out-dir target/web target/wasm32-unknown-unknown/release/examples/doppler_limit.wasm --target web
+//! cargo build --example 3d_mot --target wasm32-unknown-unknown --release
+//! wasm-bindgen --out-dir target/web target/wasm32-unknown-unknown/release/examples/3d_mot.wasm --target web
 
 extern crate atomecs;
-extern crate nalgebra;
-use atomecs::atom::{Atom, Force, Mass, Position, Velocity};
-use atomecs::initiate::NewlyCreated;
-use atomecs::integrator::Timestep;
-use atomecs::laser::gaussian::GaussianBeam;
-use atomecs::laser::LaserPlugin;
-use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
-use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
-use atomecs::laser_cooling::{CoolingLight, LaserCoolingPlugin};
-use atomecs::magnetic::quadrupole::QuadrupoleField3D;
 use atomecs::species::Rubidium87_780D2;
 use atomecs_demos::atoms::add_meshes_to_atoms;
+use atomecs_demos::controls::ControlsPlugin;
+use atomecs_demos::doppler::{DopplerConfiguration, DopplerPlugin};
+use atomecs_demos::hud::HudPlugin;
+use atomecs_demos::stepping::{PresentationStage, SteppingPlugin};
 use atomecs_demos::BevyAtomECSPlugin;
 use bevy::prelude::*;
-use nalgebra::Vector3;
-use rand_distr::{Distribution, Normal};
-
-const BEAM_NUMBER: usize = 6;
-
-pub struct DopperSimulationConfiguration {
-    /// Detuning of laser beams, in units of MHz.
-    pub detuning: f64,
-    /// Number of simulation steps to evolve for.
-    pub number_of_steps: i32,
-}
-impl Default for DopperSimulationConfiguration {
-    fn default() -> Self {
-        DopperSimulationConfiguration {
-            detuning: -8.0,
-            number_of_steps: 5000,
-        }
-    }
-}
+use bevy_egui::EguiPlugin;
 
 fn main() {
     let mut app = App::new();
-    app.add_plugin(atomecs::integrator::IntegrationPlugin);
-    app.add_plugin(atomecs::initiate::InitiatePlugin);
-    app.add_plugin(atomecs::magnetic::MagneticsPlugin);
-    app.add_plugin(LaserPlugin::<{ BEAM_NUMBER }>);
-    app.add_plugin(LaserCoolingPlugin::<Rubidium87_780D2, { BEAM_NUMBER }>::default());
+    app.add_plugin(DopplerPlugin);
+    app.insert_resource(load_configuration());
     app.add_plugin(BevyAtomECSPlugin);
-    app.add_system(add_meshes_to_atoms::<Rubidium87_780D2>);
     app.add_system(atomecs::output::console_output::console_output);
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
         window: WindowDescriptor {
@@ -64,138 +38,34 @@ fn main() {
         },
         ..default()
     }));
-    app.add_system(atomecs::bevy_bridge::copy_positions);
-    app.add_startup_system(setup_world);
-    app.add_startup_system(create_atoms);
+    app.add_plugin(EguiPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(ControlsPlugin);
+    app.add_plugin(HudPlugin);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<Rubidium87_780D2>);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
     app.add_startup_system(setup_camera);
     //app.add_startup_system(add_atomecs_watermark);
     app.insert_resource(atomecs::bevy_bridge::Scale { 0: 1e3 });
-    app.insert_resource(Timestep { delta: 2.0e-5 });
-    app.insert_resource(EmissionForceOption::On(EmissionForceConfiguration {
-        explicit_threshold: 5,
-    }));
-    app.insert_resource(ScatteringFluctuationsOption::On);
     app.run();
 }
 
-pub fn setup_world(mut commands: Commands) {
-    let configuration = DopperSimulationConfiguration::default();
-
-    // Create magnetic field.
-    commands
-        .spawn(QuadrupoleField3D::gauss_per_cm(0.001 * 18.2, Vector3::z()))
-        .insert(Position {
-            pos: Vector3::new(0.0, 0.0, 0.0),
-        });
-
-    // Create cooling lasers.
-    let detuning = configuration.detuning;
-    let power = 0.02;
-    let radius = 66.7e-3 / (2.0_f64.sqrt());
-    let beam_centre = Vector3::new(0.0, 0.0, 0.0);
-
-    commands
-        .spawn(GaussianBeam {
-            intersection: beam_centre,
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, 0.0, 1.0),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Rubidium87_780D2>(
-            detuning, -1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: beam_centre,
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, 0.0, -1.0),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Rubidium87_780D2>(
-            detuning, -1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: beam_centre,
-            e_radius: radius,
-            power,
-            direction: Vector3::new(-1.0, 0.0, 0.0),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Rubidium87_780D2>(
-            detuning, 1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: beam_centre,
-            e_radius: radius,
-            power,
-            direction: Vector3::new(1.0, 0.0, 0.0),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Rubidium87_780D2>(
-            detuning, 1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: beam_centre,
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, 1.0, 0.0),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Rubidium87_780D2>(
-            detuning, 1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: beam_centre,
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, -1.0, 0.0),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Rubidium87_780D2>(
-            detuning, 1,
-        ));
+/// Reads `assets/doppler.json`, or uses the default configuration if there is no such file.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_configuration() -> DopplerConfiguration {
+    let path = std::path::Path::new("assets/doppler.json");
+    if !path.exists() {
+        return DopplerConfiguration::default();
+    }
+    DopplerConfiguration::load(path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e))
 }
 
-fn create_atoms(mut commands: Commands) {
-    let vel_dist = Normal::new(0.0, 0.42).unwrap();
-    let pos_dist = Normal::new(0.0, 1.2e-4).unwrap();
-    let mut rng = rand::thread_rng();
-
-    // Add atoms
-    for _ in 0..1000 {
-        commands
-            .spawn(Position {
-                pos: Vector3::new(
-                    pos_dist.sample(&mut rng),
-                    pos_dist.sample(&mut rng) - 0.002,
-                    pos_dist.sample(&mut rng),
-                ),
-            })
-            .insert(Velocity {
-                vel: Vector3::new(
-                    vel_dist.sample(&mut rng),
-                    vel_dist.sample(&mut rng) + 3.5,
-                    vel_dist.sample(&mut rng),
-                ),
-            })
-            .insert(Force::default())
-            .insert(Mass { value: 87.0 })
-            .insert(Rubidium87_780D2)
-            .insert(Atom)
-            .insert(NewlyCreated);
-    }
+/// There is no file system on the web, so the configuration is built into the demo.
+#[cfg(target_arch = "wasm32")]
+fn load_configuration() -> DopplerConfiguration {
+    DopplerConfiguration::from_json(include_str!("../assets/doppler.json"))
+        .expect("Could not parse doppler.json.")
 }
 
 fn setup_camera(mut commands: Commands) {
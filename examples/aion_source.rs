@@ -1,31 +1,28 @@
 //! A 2D+ mot configuration, loaded directly from oven.
+//!
+//! The simulation itself is built by the [AionSourcePlugin].
 
-use atomecs::atom::{Atom, Force, Mass};
-use atomecs::atom::{Position, Velocity};
-use atomecs::initiate::NewlyCreated;
-use atomecs::integrator::Timestep;
-use atomecs::laser::gaussian::GaussianBeam;
-use atomecs::laser::LaserPlugin;
-use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
-use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
-use atomecs::laser_cooling::{CoolingLight, LaserCoolingPlugin};
-use atomecs::magnetic::quadrupole::QuadrupoleField2D;
-use atomecs::magnetic::uniform::UniformMagneticField;
-use atomecs::shapes::Cuboid;
-use atomecs::sim_region::{SimulationRegionPlugin, SimulationVolume, VolumeType};
 use atomecs::species::Strontium88_461;
+use atomecs_demos::aion_source::{AionSourcePlugin, ExperimentConfiguration};
 use atomecs_demos::atoms::{add_meshes_to_atoms, EmissiveColorConfig, MaterialColorConfig};
 use atomecs_demos::camera::{control_camera, DemoCamera};
+use atomecs_demos::capture::{capture_ui, CapturePlugin, Captures};
+use atomecs_demos::controls::ControlsPlugin;
+use atomecs_demos::experiment::{controls_ui, ExperimentControlsAppExt};
+use atomecs_demos::hud::HudPlugin;
 use atomecs_demos::lasers::add_meshes_to_lasers;
+use atomecs_demos::live_sweep::{live_sweep_ui, LiveSweep, LiveSweepPlugin};
+use atomecs_demos::presets::{presets_ui, Presets};
+use atomecs_demos::scene::Species;
+use atomecs_demos::share::{share_ui, SharePage, SharePlugin};
+use atomecs_demos::snapshot::{SnapshotPlugin, SnapshotSchedule};
+use atomecs_demos::stepping::{PresentationStage, SimulationClock, SteppingPlugin};
+use atomecs_demos::trajectory::TrajectoryRecorderPlugin;
 use atomecs_demos::BevyAtomECSPlugin;
 use bevy::prelude::*;
 use bevy::render::camera::{CameraProjection, Projection, Viewport};
 use bevy::window::WindowMode;
 use bevy_egui::{egui, EguiContext, EguiPlugin};
-use nalgebra::{Unit, Vector3};
-use rand_distr::{Distribution, Normal};
-
-const BEAM_NUMBER: usize = 6;
 
 fn main() {
     let mut app = App::new();
@@ -37,176 +34,41 @@ fn main() {
         ..default()
     }));
     app.add_plugin(EguiPlugin);
-    app.add_plugin(atomecs::integrator::IntegrationPlugin);
-    app.add_plugin(atomecs::initiate::InitiatePlugin);
-    app.add_plugin(atomecs::magnetic::MagneticsPlugin);
-    app.add_plugin(LaserPlugin::<{ BEAM_NUMBER }>);
-    app.add_plugin(LaserCoolingPlugin::<Strontium88_461, { BEAM_NUMBER }>::default());
-    app.add_plugin(SimulationRegionPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(ControlsPlugin);
+    app.add_plugin(HudPlugin);
+    app.add_plugin(AionSourcePlugin);
     app.add_plugin(BevyAtomECSPlugin);
     app.add_system(atomecs::output::console_output::console_output);
-    app.add_system(atomecs::bevy_bridge::copy_positions);
-    app.add_startup_system(setup_world);
-    app.add_system(add_meshes_to_atoms::<Strontium88_461>);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<Strontium88_461>);
     // app.add_system(atomecs_demos::atoms::update_emissive_color::<Strontium88_461>);
-    app.add_system(add_meshes_to_lasers::<Strontium88_461>);
-    app.add_system(create_atoms);
-    app.add_system(control_camera);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_lasers::<Strontium88_461>);
+    app.add_system_to_stage(PresentationStage, control_camera);
     app.add_startup_system(setup_camera);
     // app.add_startup_system(atomecs_demos::add_atomecs_watermark);
     app.add_startup_system(spawn_cad);
     app.insert_resource(atomecs::bevy_bridge::Scale { 0: 7e1 });
-    app.insert_resource(Timestep { delta: 2.0e-5 });
-    app.insert_resource(EmissionForceOption::On(EmissionForceConfiguration {
-        explicit_threshold: 5,
-    }));
     app.insert_resource(EmissiveColorConfig { factor: 8.0 });
     app.insert_resource(MaterialColorConfig { factor: 1.0 });
-    app.insert_resource(ScatteringFluctuationsOption::On);
     app.init_resource::<ExperimentConfiguration>();
-    app.add_system(experiment_controls);
-    app.add_system(update_cooling_beams);
-    app.add_system(update_push_beam);
-    app.add_system(update_magnetic_fields);
-    app.add_system(update_cad);
-    app.run();
-}
-
-pub fn setup_world(mut commands: Commands) {
-    // Create magnetic field.
-    commands
-        .spawn(QuadrupoleField2D::gauss_per_cm(
-            27.0, // value overridden below.
-            Vector3::x_axis(),
-            Unit::new_normalize(Vector3::new(0.0, 1.0, 1.0)),
-        ))
-        .insert(UniformMagneticField::gauss(Vector3::new(0.0, 0.0, 0.0)))
-        .insert(Position::default());
-
-    // Push beam along z
-    let push_beam_radius = 4e-3;
-    let push_beam_power = 0.020;
-    let push_beam_detuning = -103.0;
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: push_beam_radius,
-            power: push_beam_power,
-            direction: Vector3::x(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            push_beam_detuning,
-            -1,
-        ))
-        .insert(PushBeam::default());
-
-    // Create cooling lasers.
-    let detuning = -40.0;
-    let power = 0.23;
-    let radius = 17.0e-3; //33.0e-3 / (2.0 * 2.0_f64.sqrt()); // 33mm 1/e^2 diameter
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, 1.0, 1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(detuning, 1))
-        .insert(MOTBeam::default());
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, -1.0, -1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(detuning, 1))
-        .insert(MOTBeam::default());
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, 1.0, -1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            detuning, -1,
-        ))
-        .insert(MOTBeam::default());
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: radius,
-            power,
-            direction: Vector3::new(0.0, -1.0, 1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            detuning, -1,
-        ))
-        .insert(MOTBeam::default());
-
-    // Use a simulation bound so that atoms that escape the capture region are deleted from the simulation.
-    commands
-        .spawn(Position {
-            pos: Vector3::new(0.0, 0.0, 0.0),
-        })
-        .insert(Cuboid {
-            half_width: Vector3::new(0.02, 0.1, 0.02),
-        })
-        .insert(SimulationVolume {
-            volume_type: VolumeType::Inclusive,
-        });
-
-    // The simulation bound also now includes a small pipe to capture the 2D MOT output properly.
-    commands
-        .spawn(Position {
-            pos: Vector3::new(0.05, 0.0, 0.0),
-        })
-        .insert(Cuboid {
-            half_width: Vector3::new(0.05, 0.01, 0.01),
-        })
-        .insert(SimulationVolume {
-            volume_type: VolumeType::Inclusive,
-        });
-}
-
-fn create_atoms(mut commands: Commands) {
-    let dist = Normal::new(0.0, 1.0).unwrap();
-    let mut rng = rand::thread_rng();
-
-    // Add atoms
-    for _ in 0..3 {
-        commands
-            .spawn(Position {
-                pos: Vector3::new(
-                    0.001 * dist.sample(&mut rng),
-                    -0.08,
-                    0.001 * dist.sample(&mut rng),
-                ),
-            })
-            .insert(Velocity {
-                vel: Vector3::new(
-                    dist.sample(&mut rng) * 10.0,
-                    dist.sample(&mut rng) * 30.0 + 95.0,
-                    dist.sample(&mut rng) * 10.0,
-                ),
-            })
-            .insert(Force::default())
-            .insert(Mass { value: 88.0 })
-            .insert(Strontium88_461)
-            .insert(Atom)
-            .insert(NewlyCreated);
+    app.insert_resource(Presets::<ExperimentConfiguration>::new("aion_source"));
+    app.add_plugin(SharePlugin::<ExperimentConfiguration>::new("aion_source"));
+    app.add_plugin(LiveSweepPlugin::<ExperimentConfiguration>::default());
+    app.add_plugin(CapturePlugin);
+    if let Some(schedule) = SnapshotSchedule::from_args()
+        .unwrap_or_else(|e| panic!("Could not read snapshot options: {}", e))
+    {
+        app.add_plugin(SnapshotPlugin::<Strontium88_461>::new(schedule));
     }
+    if let Some(recorder) = TrajectoryRecorderPlugin::from_args(Species::Strontium88_461)
+        .unwrap_or_else(|e| panic!("Could not read recording options: {}", e))
+    {
+        app.add_plugin(recorder);
+    }
+    app.add_system_to_stage(PresentationStage, experiment_controls);
+    app.add_control_system::<ExperimentConfiguration, _>(update_cad);
+    app.run();
 }
 
 fn setup_camera(mut commands: Commands) {
@@ -273,44 +135,26 @@ fn spawn_cad(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(CAD);
 }
 
-#[derive(Resource)]
-pub struct ExperimentConfiguration {
-    pub cooling_beam_detuning: f64,
-    pub cooling_beam_power: f64,
-    pub push_beam_power: f64,
-    pub push_beam_detuning: f64,
-    pub bias_field_x: f64,
-    pub bias_field_y: f64,
-    pub bias_field_z: f64,
-    pub quad_gradient: f64,
-    pub show_cad: bool,
-}
-impl Default for ExperimentConfiguration {
-    fn default() -> Self {
-        ExperimentConfiguration {
-            cooling_beam_detuning: -40.0,
-            cooling_beam_power: 230.0,
-            push_beam_power: 20.0,
-            push_beam_detuning: -103.0,
-            bias_field_x: 0.0,
-            bias_field_y: 0.0,
-            bias_field_z: 0.0,
-            quad_gradient: 27.0,
-            show_cad: true,
-        }
-    }
+/// Tabs of the side panel.
+#[derive(Default, PartialEq)]
+enum PanelTab {
+    #[default]
+    Controls,
+    Sweep,
 }
 
-#[derive(Component, Default)]
-pub struct MOTBeam;
-
-#[derive(Component, Default)]
-pub struct PushBeam;
-
+#[allow(clippy::too_many_arguments)]
 fn experiment_controls(
     mut egui_context: ResMut<EguiContext>,
     mut config: ResMut<ExperimentConfiguration>,
-    mut camera_query: Query<(&mut Camera, &mut Projection)>,
+    mut presets: ResMut<Presets<ExperimentConfiguration>>,
+    mut share: ResMut<SharePage>,
+    mut sweep: ResMut<LiveSweep<ExperimentConfiguration>>,
+    mut captures: ResMut<Captures>,
+    clock: Res<SimulationClock>,
+    mut tab: Local<PanelTab>,
+    demo_cameras: Query<&DemoCamera>,
+    mut camera_query: Query<(&mut Camera, &mut Projection), With<DemoCamera>>,
     mut windows: ResMut<Windows>,
 ) {
     let rect = egui::SidePanel::right("right")
@@ -332,22 +176,25 @@ fn experiment_controls(
             ui.add_space(0.1);
             ui.separator();
             ui.add_space(0.1);
-            ui.label("Cooling Beams:");
-            ui.add(egui::Slider::new(&mut config.cooling_beam_detuning, -120.0..=-15.0).text("Cooling beam detuning (MHz)"));
-            ui.add(egui::Slider::new(&mut config.cooling_beam_power, 0.0..=230.0).text("Cooling beam power (mW)"));
-            ui.add_space(0.1);
-            ui.label("Push Beam:");
-            ui.add(egui::Slider::new(&mut config.push_beam_detuning, -400.0..=100.0).text("Push beam detuning (MHz)"));
-            ui.add(egui::Slider::new(&mut config.push_beam_power, 0.0..=30.0).text("Push beam power (mW)"));
-            ui.add_space(0.1);
-            ui.label("Magnetic fields:");
-            ui.add(egui::Slider::new(&mut config.quad_gradient, 0.0..=80.0).text("Quadrupole gradient (G/cm)"));
-            ui.add(egui::Slider::new(&mut config.bias_field_x, -30.0..=30.0).text("Bias field, x (G)"));
-            ui.add(egui::Slider::new(&mut config.bias_field_y, -30.0..=30.0).text("Bias field, y (G)"));
-            ui.add(egui::Slider::new(&mut config.bias_field_z, -30.0..=30.0).text("Bias field, z (G)"));
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut *tab, PanelTab::Controls, "Controls");
+                ui.selectable_value(&mut *tab, PanelTab::Sweep, "Sweep");
+            });
+            ui.separator();
+            match *tab {
+                PanelTab::Controls => {
+                    ui.add_enabled_ui(!sweep.is_running(), |ui| {
+                        controls_ui(ui, &mut config);
+                        ui.separator();
+                        presets_ui(ui, &mut config, &mut presets);
+                    });
+                    share_ui(ui, &*config, demo_cameras.iter().next(), &mut share);
+                    ui.collapsing("Capture", |ui| capture_ui(ui, &mut captures));
+                }
+                PanelTab::Sweep => live_sweep_ui(ui, &mut sweep, &mut config, &clock),
+            }
             ui.add_space(1.0);
             ui.separator();
-            ui.add(egui::Checkbox::new(&mut config.show_cad, "Show CAD?"));
             if ui.add(egui::Button::new("Fullscreen mode")).clicked() {
                 for window in windows.iter_mut() {
                     window.set_mode(WindowMode::BorderlessFullscreen);
@@ -367,47 +214,6 @@ fn experiment_controls(
     }
 }
 
-fn update_cooling_beams(
-    mut query: Query<(&mut CoolingLight, &mut GaussianBeam), With<MOTBeam>>,
-    config: Res<ExperimentConfiguration>,
-) {
-    for (mut light, mut gaussian) in query.iter_mut() {
-        let wavelength =
-            CoolingLight::for_transition::<Strontium88_461>(config.cooling_beam_detuning, 1)
-                .wavelength;
-        light.wavelength = wavelength;
-        gaussian.power = 1e-3 * config.cooling_beam_power;
-    }
-}
-
-fn update_push_beam(
-    mut query: Query<(&mut CoolingLight, &mut GaussianBeam), With<PushBeam>>,
-    config: Res<ExperimentConfiguration>,
-) {
-    for (mut light, mut gaussian) in query.iter_mut() {
-        let wavelength =
-            CoolingLight::for_transition::<Strontium88_461>(config.push_beam_detuning, 1)
-                .wavelength;
-        light.wavelength = wavelength;
-        gaussian.power = 1e-3 * config.push_beam_power;
-    }
-}
-
-fn update_magnetic_fields(
-    mut query: Query<(&mut QuadrupoleField2D, &mut UniformMagneticField)>,
-    config: Res<ExperimentConfiguration>,
-) {
-    for (mut quad, mut uniform) in query.iter_mut() {
-        quad.gradient = 0.01 * config.quad_gradient;
-        uniform.field = UniformMagneticField::gauss(Vector3::new(
-            config.bias_field_x,
-            config.bias_field_y,
-            config.bias_field_z,
-        ))
-        .field;
-    }
-}
-
 fn update_cad(mut query: Query<&mut Visibility, With<CAD>>, config: Res<ExperimentConfiguration>) {
     for mut visibility in query.iter_mut() {
         visibility.is_visible = config.show_cad;
@@ -0,0 +1,101 @@
+//! Replays the atoms of a recorded trajectory, without running the physics.
+//!
+//! cargo run --release --example replay -- <trajectory.traj> [--scale <s>]
+//!
+//! Trajectories are recorded headless with the `record` binary, or with `--record <file>` in the
+//! AION source demo. The timeline along the bottom plays, steps and scrubs through the replay,
+//! which can also be paused with the space bar and stepped with the arrow keys. Bookmarked moments
+//! and camera views are saved next to the trajectory, in `<trajectory.traj>.bookmarks.json`. F12 saves
+//! a screenshot of the view to `captures/`.
+
+use atomecs::bevy_bridge::Scale;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::species::{Rubidium87_780D2, Strontium88_461};
+use atomecs_demos::atoms::add_meshes_to_atoms;
+use atomecs_demos::camera::{control_camera, DemoCamera};
+use atomecs_demos::capture::CapturePlugin;
+use atomecs_demos::hud::HudPlugin;
+use atomecs_demos::scene::Species;
+use atomecs_demos::stepping::{PresentationStage, SteppingPlugin};
+use atomecs_demos::timeline::TimelinePlugin;
+use atomecs_demos::trajectory::{Trajectory, TrajectoryReplayPlugin};
+use atomecs_demos::{add_atomecs_watermark, BevyAtomECSPlugin};
+use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 && !(args.len() == 4 && args[2] == "--scale") {
+        eprintln!("Usage: replay <trajectory.traj> [--scale <s>]");
+        std::process::exit(1);
+    }
+    let trajectory = Trajectory::load(&args[1])
+        .unwrap_or_else(|e| panic!("Could not load trajectory {}: {}", args[1], e));
+    let scale = match args.get(3) {
+        Some(scale) => scale
+            .parse()
+            .unwrap_or_else(|_| panic!("Could not parse scale {}", scale)),
+        None => 7e1,
+    };
+    let bookmarks = format!("{}.bookmarks.json", args[1]);
+    match trajectory.species {
+        Species::Strontium88_461 => run::<Strontium88_461>(trajectory, &bookmarks, scale),
+        Species::Rubidium87_780D2 => run::<Rubidium87_780D2>(trajectory, &bookmarks, scale),
+    }
+}
+
+fn run<T>(trajectory: Trajectory, bookmarks: &str, scale: f64)
+where
+    T: AtomicTransition + Component + Default + Copy,
+{
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        window: WindowDescriptor {
+            title: "Replay".to_string(),
+            ..default()
+        },
+        ..default()
+    }));
+    app.add_plugin(EguiPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(HudPlugin);
+    app.add_plugin(TrajectoryReplayPlugin::new(trajectory));
+    app.add_plugin(TimelinePlugin::new(Some(bookmarks.into())));
+    app.add_plugin(BevyAtomECSPlugin);
+    app.add_plugin(CapturePlugin);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<T>);
+    app.add_system_to_stage(PresentationStage, control_camera);
+    app.insert_resource(Scale { 0: scale });
+    app.add_startup_system(setup_camera);
+    app.add_startup_system(add_atomecs_watermark);
+    app.run();
+}
+
+fn setup_camera(mut commands: Commands) {
+    commands
+        .spawn(Camera3dBundle {
+            projection: OrthographicProjection {
+                scale: 0.03,
+                near: -10.0,
+                ..default()
+            }
+            .into(),
+            transform: Transform::from_xyz(4.0, 4.0, 3.5).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        })
+        .insert(DemoCamera::default());
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: 30000.0,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 2.0, 0.0),
+            rotation: Quat::from_rotation_y(2.2) * Quat::from_rotation_x(-1.2),
+            ..default()
+        },
+        ..default()
+    });
+}
@@ -0,0 +1,81 @@
+//! Loads an experiment from a scene description file and renders it.
+//!
+//! cargo run --example scene_viewer -- assets/scenes/aion_source.json [--seed <n>]
+//!
+//! On wasm the scene file cannot be chosen, and the 3D MOT scene is shown.
+
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::species::{Rubidium87_780D2, Strontium88_461};
+use atomecs_demos::atoms::add_meshes_to_atoms;
+use atomecs_demos::camera::control_camera;
+use atomecs_demos::controls::ControlsPlugin;
+use atomecs_demos::hud::HudPlugin;
+use atomecs_demos::lasers::add_meshes_to_lasers;
+use atomecs_demos::scene::{
+    spawn_scene_camera, SceneDescription, ScenePlugin, Species, BEAM_NUMBER,
+};
+use atomecs_demos::sequence::SequenceTimelinePlugin;
+use atomecs_demos::stepping::{PresentationStage, SteppingPlugin};
+use atomecs_demos::{add_atomecs_watermark, BevyAtomECSPlugin};
+use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
+
+fn main() {
+    let scene = load_scene();
+    match scene.species {
+        Species::Strontium88_461 => run::<Strontium88_461>(scene),
+        Species::Rubidium87_780D2 => run::<Rubidium87_780D2>(scene),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_scene() -> SceneDescription {
+    // The first argument that is not a `--seed <n>` option.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut path = "assets/scenes/3d_mot.json".to_string();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--seed" {
+            i += 2;
+        } else {
+            path = args[i].clone();
+            break;
+        }
+    }
+    SceneDescription::load(path)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_scene() -> SceneDescription {
+    SceneDescription::from_json(include_str!("../assets/scenes/3d_mot.json"))
+        .expect("Could not parse scene file.")
+}
+
+fn run<T>(scene: SceneDescription)
+where
+    T: AtomicTransition + Component + Default + Copy,
+{
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        window: WindowDescriptor {
+            title: scene.name.clone(),
+            fit_canvas_to_parent: true,
+            ..default()
+        },
+        ..default()
+    }));
+    app.add_plugin(EguiPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(ControlsPlugin);
+    app.add_plugin(HudPlugin);
+    app.add_plugin(ScenePlugin::<T, { BEAM_NUMBER }>::new(scene));
+    app.add_plugin(SequenceTimelinePlugin);
+    app.add_plugin(BevyAtomECSPlugin);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<T>);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_lasers::<T>);
+    app.add_system_to_stage(PresentationStage, control_camera);
+    app.add_startup_system(spawn_scene_camera);
+    app.add_startup_system(add_atomecs_watermark);
+    app.run();
+}
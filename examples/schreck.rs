@@ -1,11 +1,8 @@
 //! The Sr PAL experiment.
 
-use atomecs::atom::{Atom, Force, Mass};
-use atomecs::atom::{Position, Velocity};
+use atomecs::atom::Position;
 use atomecs::bevy_bridge::Scale;
-use atomecs::initiate::NewlyCreated;
 use atomecs::integrator::Timestep;
-use atomecs::laser::gaussian::GaussianBeam;
 use atomecs::laser::LaserPlugin;
 use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
 use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
@@ -15,36 +12,56 @@ use atomecs::shapes::Cuboid;
 use atomecs::sim_region::{SimulationRegionPlugin, SimulationVolume, VolumeType};
 use atomecs::species::Strontium88_461;
 use atomecs_demos::atoms::{add_meshes_to_atoms, EmissiveColorConfig, MaterialColorConfig};
+use atomecs_demos::beams::{
+    e_radius_from_e2_diameter, SingleBeam, TransverseCooling, ZeemanSlowerBeam,
+};
 use atomecs_demos::camera::{control_camera, DemoCamera};
+use atomecs_demos::coils::{CoilAssembly, CoilPlugin, FieldDefinition};
+use atomecs_demos::controls::{ControlsPlugin, SetupAppExt};
+use atomecs_demos::experiment::{controls_ui, ExperimentControlsAppExt};
+use atomecs_demos::experiment_controls;
+use atomecs_demos::fields::{FieldGridPlugin, MagneticFieldGrid};
+use atomecs_demos::hud::HudPlugin;
 use atomecs_demos::lasers::add_meshes_to_lasers;
+use atomecs_demos::presets::{presets_ui, Presets};
+use atomecs_demos::share::{share_ui, SharePage, SharePlugin};
+use atomecs_demos::sources::{AtomWeight, Oven, SourcePlugin};
+use atomecs_demos::stepping::{PresentationStage, SteppingPlugin};
 use atomecs_demos::BevyAtomECSPlugin;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 use nalgebra::Vector3;
-use rand_distr::{Distribution, Normal};
-extern crate serde;
 
 const BEAM_NUMBER: usize = 22;
 
 fn main() {
     let mut app = App::new();
-    app.add_plugins(DefaultPlugins);
+    app.add_plugins(DefaultPlugins.set(AssetPlugin {
+        watch_for_changes: cfg!(feature = "hot_reload"),
+        ..default()
+    }));
     app.add_plugin(EguiPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(ControlsPlugin);
+    app.add_plugin(HudPlugin);
     app.add_plugin(atomecs::integrator::IntegrationPlugin);
     app.add_plugin(atomecs::initiate::InitiatePlugin);
     app.add_plugin(atomecs::magnetic::MagneticsPlugin);
     app.add_plugin(LaserPlugin::<{ BEAM_NUMBER }>);
     app.add_plugin(LaserCoolingPlugin::<Strontium88_461, { BEAM_NUMBER }>::default());
     app.add_plugin(SimulationRegionPlugin);
+    app.add_plugin(FieldGridPlugin);
+    app.add_plugin(CoilPlugin);
     app.add_plugin(BevyAtomECSPlugin);
     app.add_system(atomecs::output::console_output::console_output);
-    app.add_system(atomecs::bevy_bridge::copy_positions);
-    app.add_startup_system(setup_world);
-    app.add_system(add_meshes_to_atoms::<Strontium88_461>);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    app.add_setup_system(setup_world);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<Strontium88_461>);
     //app.add_system(atomecs_demos::atoms::update_emissive_color::<Strontium88_461>);
-    app.add_system(add_meshes_to_lasers::<Strontium88_461>);
-    app.add_system(create_atoms);
-    app.add_system(control_camera);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_lasers::<Strontium88_461>);
+    app.add_plugin(SourcePlugin::<Strontium88_461>::default());
+    app.insert_resource(AtomWeight(1e6));
+    app.add_system_to_stage(PresentationStage, control_camera);
     app.add_startup_system(setup_camera);
     //app.add_startup_system(add_atomecs_watermark);
     app.add_startup_system(spawn_cad);
@@ -57,36 +74,42 @@ fn main() {
     app.insert_resource(MaterialColorConfig { factor: 1.0 });
     app.insert_resource(ScatteringFluctuationsOption::On);
     app.init_resource::<ExperimentConfiguration>();
-    app.add_system(experiment_controls);
-    app.add_system(update_transverse_cooling);
-    app.add_system(update_zeeman_slower);
+    app.insert_resource(Presets::<ExperimentConfiguration>::new("schreck"));
+    app.add_plugin(SharePlugin::<ExperimentConfiguration>::new("schreck"));
+    app.add_system_to_stage(PresentationStage, experiment_controls);
+    app.add_control_system::<ExperimentConfiguration, _>(update_transverse_cooling);
+    app.add_control_system::<ExperimentConfiguration, _>(update_zeeman_slower);
+    app.add_control_system::<ExperimentConfiguration, _>(update_slower_field);
     app.run();
 }
 
-pub fn setup_world(mut commands: Commands) {
-    // Create magnetic field.
-    let grid: PrecalculatedMagneticFieldGrid = serde_json::from_str(SLOWER_FIELD)
-        .expect("Could not load magnetic field grid from json file.");
-    commands.spawn(grid);
+pub fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // Strontium oven, collimated by a capillary array. Atoms faster than the slower capture velocity are not simulated.
+    commands
+        .spawn(
+            Oven::new(88.0, 800.0, 2.2e11, Vector3::x())
+                .aperture_radius(2e-3)
+                .capillaries(0.02, 1.5e-4)
+                .max_speed(600.0),
+        )
+        .insert(Position {
+            pos: Vector3::new(-1.9, 0.0, 0.0),
+        });
+
+    // Create magnetic field. It is swapped for the coils that reproduce it by `update_slower_field`.
+    commands
+        .spawn(asset_server.load::<MagneticFieldGrid, _>("fields/slower.field.json"))
+        .insert(SlowerField);
 
     // Zeeman slowing beam along x
     let zeeman_slower_e2_diameter = 25e-3;
     let zeeman_slower_power = 0.07;
     let zeeman_slower_detuning = -450.0;
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: zeeman_slower_e2_diameter / 2.0 / 2.0_f64.sqrt(),
-            power: zeeman_slower_power,
-            direction: -Vector3::x(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            zeeman_slower_detuning,
-            -1,
-        ))
-        .insert(ZeemanSlowingBeam);
+    ZeemanSlowerBeam::against(Vector3::x())
+        .e_radius(e_radius_from_e2_diameter(zeeman_slower_e2_diameter))
+        .power(zeeman_slower_power)
+        .detuning(zeeman_slower_detuning)
+        .spawn::<Strontium88_461, _>(&mut commands, ZeemanSlowingBeam);
 
     // Transverse cooling region.
     let tc_detuning = -18.0;
@@ -95,120 +118,32 @@ pub fn setup_world(mut commands: Commands) {
     let tc_pos = -1.7;
     let tc_stride = 0.03;
     for i in 0..2 {
-        commands
-            .spawn(GaussianBeam {
-                intersection: Vector3::new(tc_pos + i as f64 * tc_stride, 0.0, 0.0),
-                e_radius: tc_diameter / 2.0 / 2.0_f64.sqrt(),
-                power: tc_power,
-                direction: Vector3::new(0.0, 0.0, 1.0).normalize(),
-                rayleigh_range: f64::INFINITY,
-                ellipticity: 0.0,
-            })
-            .insert(CoolingLight::for_transition::<Strontium88_461>(
-                tc_detuning,
-                1,
-            ))
-            .insert(TransverseCoolingBeam);
-        commands
-            .spawn(GaussianBeam {
-                intersection: Vector3::new(tc_pos + i as f64 * tc_stride, 0.0, 0.0),
-                e_radius: tc_diameter / 2.0 / 2.0_f64.sqrt(),
-                power: tc_power,
-                direction: Vector3::new(0.0, 0.0, -1.0).normalize(),
-                rayleigh_range: f64::INFINITY,
-                ellipticity: 0.0,
-            })
-            .insert(CoolingLight::for_transition::<Strontium88_461>(
-                tc_detuning,
-                1,
-            ))
-            .insert(TransverseCoolingBeam);
-        commands
-            .spawn(GaussianBeam {
-                intersection: Vector3::new(tc_pos + i as f64 * tc_stride, 0.0, 0.0),
-                e_radius: tc_diameter / 2.0 / 2.0_f64.sqrt(),
-                power: tc_power,
-                direction: Vector3::new(0.0, 1.0, 0.0).normalize(),
-                rayleigh_range: f64::INFINITY,
-                ellipticity: 0.0,
-            })
-            .insert(CoolingLight::for_transition::<Strontium88_461>(
-                tc_detuning,
-                1,
-            ))
-            .insert(TransverseCoolingBeam);
-        commands
-            .spawn(GaussianBeam {
-                intersection: Vector3::new(tc_pos + i as f64 * tc_stride, 0.0, 0.0),
-                e_radius: tc_diameter / 2.0 / 2.0_f64.sqrt(),
-                power: tc_power,
-                direction: Vector3::new(0.0, -1.0, 0.0).normalize(),
-                rayleigh_range: f64::INFINITY,
-                ellipticity: 0.0,
-            })
-            .insert(CoolingLight::for_transition::<Strontium88_461>(
-                tc_detuning,
-                1,
-            ))
-            .insert(TransverseCoolingBeam);
+        TransverseCooling::at(
+            Vector3::new(tc_pos + i as f64 * tc_stride, 0.0, 0.0),
+            Vector3::x(),
+        )
+        .e_radius(e_radius_from_e2_diameter(tc_diameter))
+        .power(tc_power)
+        .detuning(tc_detuning)
+        .spawn::<Strontium88_461, _>(&mut commands, TransverseCoolingBeam);
     }
 
     // MOT region
     let blue_mot_detuning = -25.0;
     let blue_mot_power = 0.0105;
     let blue_mot_e2_diameter = 22.0e-3; //33.0e-3 / (2.0 * 2.0_f64.sqrt()); // 33mm 1/e^2 diameter
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: blue_mot_e2_diameter / 2.0 / 2.0_f64.sqrt(),
-            power: blue_mot_power,
-            direction: Vector3::new(1.0, 0.0, 1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            blue_mot_detuning,
-            1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: blue_mot_e2_diameter / 2.0 / 2.0_f64.sqrt(),
-            power: blue_mot_power,
-            direction: Vector3::new(1.0, 0.0, -1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            blue_mot_detuning,
-            1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: blue_mot_e2_diameter / 2.0 / 2.0_f64.sqrt(),
-            power: blue_mot_power,
-            direction: Vector3::new(-1.0, 0.0, -1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            blue_mot_detuning,
-            -1,
-        ));
-    commands
-        .spawn(GaussianBeam {
-            intersection: Vector3::new(0.0, 0.0, 0.0),
-            e_radius: blue_mot_e2_diameter / 2.0 / 2.0_f64.sqrt(),
-            power: blue_mot_power,
-            direction: Vector3::new(-1.0, 0.0, 1.0).normalize(),
-            rayleigh_range: f64::INFINITY,
-            ellipticity: 0.0,
-        })
-        .insert(CoolingLight::for_transition::<Strontium88_461>(
-            blue_mot_detuning,
-            -1,
-        ));
+    for (direction, polarization) in [
+        (Vector3::new(1.0, 0.0, 1.0), 1),
+        (Vector3::new(1.0, 0.0, -1.0), 1),
+        (Vector3::new(-1.0, 0.0, -1.0), -1),
+        (Vector3::new(-1.0, 0.0, 1.0), -1),
+    ] {
+        SingleBeam::along(direction, polarization)
+            .e_radius(e_radius_from_e2_diameter(blue_mot_e2_diameter))
+            .power(blue_mot_power)
+            .detuning(blue_mot_detuning)
+            .spawn::<Strontium88_461, _>(&mut commands, BlueMOTBeam);
+    }
 
     // Define simulation bounds
     //  1. Zeeman slower pipe
@@ -224,36 +159,6 @@ pub fn setup_world(mut commands: Commands) {
         });
 }
 
-fn create_atoms(mut commands: Commands) {
-    let dist = Normal::new(0.0, 1.0).unwrap();
-    let mut rng = rand::thread_rng();
-
-    let oven_position = -1.9; //m
-                              // Add atoms
-    for _ in 0..3 {
-        commands
-            .spawn(Position {
-                pos: Vector3::new(
-                    oven_position,
-                    0.001 * dist.sample(&mut rng),
-                    0.001 * dist.sample(&mut rng),
-                ),
-            })
-            .insert(Velocity {
-                vel: Vector3::new(
-                    dist.sample(&mut rng) * 80.0 + 350.0,
-                    dist.sample(&mut rng) * 5.0,
-                    dist.sample(&mut rng) * 5.0,
-                ),
-            })
-            .insert(Force::default())
-            .insert(Mass { value: 88.0 })
-            .insert(Strontium88_461)
-            .insert(Atom)
-            .insert(NewlyCreated);
-    }
-}
-
 fn setup_camera(mut commands: Commands, scale: Res<Scale>) {
     // set up the camera
     let look_at_target = Vec3::new(-1.0, 0.0, 0.0);
@@ -322,27 +227,27 @@ fn spawn_cad(mut commands: Commands, asset_server: Res<AssetServer>) {
     });
 }
 
-//yeah its messy, but so is loading raw json via bevy asset lib right now.
-static SLOWER_FIELD: &str = "{\"extent_spatial\":[1.4,0.1,0.1],\"extent_cells\":[100,1,1],\"position\":[-0.6,0,0],\"grid\":[[0,0,0],[-0.00010356086113661874,0,0],[-0.00012131415161718194,0,0],[-5.3259871441689581E-5,0,0],[8.8390279697289322E-5,0,0],[0.00028248096720701575,0,0],[0.00059618220775245544,0,0],[0.0010902402249203598,0,0],[0.0013788955775487766,0,0],[0.0018677685950413221,0,0],[0.003386332460406539,0,0],[0.0066049382716049368,0,0],[0.011197929710326406,0,0],[0.016485182402537775,0,0],[0.021948657063441273,0,0],[0.028529927372902587,0,0],[0.035079994929398055,0,0],[0.03978910006090907,0,0],[0.041496619083395941,0,0],[0.041206110693714007,0,0],[0.040275945868232368,0,0],[0.039806049475470971,0,0],[0.0392482853223594,0,0],[0.038576111075039762,0,0],[0.037939839162979654,0,0],[0.037388889404193971,0,0],[0.036840539874822095,0,0],[0.036219383921863253,0,0],[0.0354419761331301,0,0],[0.034516414012587877,0,0],[0.033588251662631838,0,0],[0.03280424590770474,0,0],[0.0321792344421668,0,0],[0.031629629629629633,0,0],[0.031080024817092468,0,0],[0.030494949494949495,0,0],[0.029909090909090909,0,0],[0.029323232323232327,0,0],[0.028737373737373739,0,0],[0.028151515151515153,0,0],[0.027565656565656568,0,0],[0.026979848479695433,0,0],[0.026425939839162978,0,0],[0.025879831886871985,0,0],[0.025266117969821675,0,0],[0.024515401953418486,0,0],[0.023641246667264425,0,0],[0.022717687022247784,0,0],[0.02181818181818182,0,0],[0.020939393939393938,0,0],[0.020060606060606063,0,0],[0.019181818181818178,0,0],[0.018303030303030304,0,0],[0.017424242424242422,0,0],[0.016545454545454547,0,0],[0.015666666666666662,0,0],[0.014787878787878789,0,0],[0.013909090909090916,0,0],[0.013030303030303034,0,0],[0.012208883035023233,0,0],[0.011413362272866405,0,0],[0.010493030498846236,0,0],[0.0093000425641992825,0,0],[0.0078343350864012087,0,0],[0.0062750193497056062,0,0],[0.0048057475066963975,0,0],[0.0033148148148148156,0,0],[0.0018667859082612719,0,0],[0.00072558407253846236,0,0],[0.00015717783899602084,0,0],[9.4712557675520967E-5,0,0],[0.00013235610878708495,0,0],[-8.039068369646871E-5,0,0],[-0.00011370051911833379,0,0],[-0.00041223375474982267,0,0],[-0.0017677602470990736,0,0],[-0.0047691381728724976,0,0],[-0.0089821673525377153,0,0],[-0.013652836909035225,0,0],[-0.018536119278696511,0,0],[-0.025158216178724331,0,0],[-0.030755822689707009,0,0],[-0.031856213394015648,0,0],[-0.025674242681894964,0,0],[-0.015363107660628206,0,0],[-0.0060504607342684695,0,0],[-0.0016689288559505863,0,0],[-0.00027436903469961536,0,0],[-0.00015912208504801175,0,0],[0.00012212318058660361,0,0],[0.00029451540195341865,0,0],[0.00015488009366185232,0,0],[4.8438677150042879E-6,0,0],[0,0,0],[0,0,0],[0,0,0],[0,0,0],[0,0,0],[0,0,0],[0,0,0]]}";
-
 // User interface stuff
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone)]
 pub struct ZeemanSlowingBeam;
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone)]
 pub struct TransverseCoolingBeam;
 
-#[derive(Resource)]
-pub struct ExperimentConfiguration {
-    pub zeeman_slower_detuning: f64,
-    pub transverse_cooling_detuning: f64,
-}
-impl Default for ExperimentConfiguration {
-    fn default() -> Self {
-        ExperimentConfiguration {
-            zeeman_slower_detuning: -450.0,
-            transverse_cooling_detuning: -18.0,
+#[derive(Component, Default, Clone)]
+pub struct BlueMOTBeam;
+
+/// The magnetic field of the Zeeman slower and MOT.
+#[derive(Component)]
+pub struct SlowerField;
+
+experiment_controls! {
+    pub struct ExperimentConfiguration {
+        "" {
+            zeeman_slower_detuning: f64 = -450.0 => slider(-500.0..=-30.0, "Zeeman slower detuning", "MHz"),
+            transverse_cooling_detuning: f64 = -18.0 => slider(-200.0..=-10.0, "Transverse cooling detuning", "MHz"),
+            slower_coils: bool = false => checkbox("Simulate slower coils instead of field map?"),
         }
     }
 }
@@ -350,6 +255,9 @@ impl Default for ExperimentConfiguration {
 fn experiment_controls(
     mut egui_context: ResMut<EguiContext>,
     mut config: ResMut<ExperimentConfiguration>,
+    mut presets: ResMut<Presets<ExperimentConfiguration>>,
+    mut share: ResMut<SharePage>,
+    demo_cameras: Query<&DemoCamera>,
 ) {
     egui::TopBottomPanel::bottom("bottom")
         .resizable(true)
@@ -360,14 +268,10 @@ fn experiment_controls(
                 "https://github.com/TeamAtomECS/AtomECS/",
             ));
 
-            ui.add(
-                egui::Slider::new(&mut config.zeeman_slower_detuning, -500.0..=-30.0)
-                    .text("Zeeman slower detuning (MHz)"),
-            );
-            ui.add(
-                egui::Slider::new(&mut config.transverse_cooling_detuning, -200.0..=-10.0)
-                    .text("Transverse cooling detuning (MHz)"),
-            );
+            controls_ui(ui, &mut config);
+            ui.separator();
+            presets_ui(ui, &mut config, &mut presets);
+            share_ui(ui, &*config, demo_cameras.iter().next(), &mut share);
             ui.allocate_rect(ui.available_rect_before_wrap(), egui::Sense::hover());
         });
 }
@@ -395,3 +299,34 @@ fn update_transverse_cooling(
         light.wavelength = wavelength;
     }
 }
+
+/// Uses either the field map of the slower, or the coils that reproduce it. The coils are rendered,
+/// and their currents can be changed in the coils window.
+fn update_slower_field(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<(Entity, Option<&Handle<FieldDefinition>>), With<SlowerField>>,
+    config: Res<ExperimentConfiguration>,
+) {
+    for (entity, coils) in query.iter() {
+        match (config.slower_coils, coils.is_some()) {
+            (true, false) => {
+                commands
+                    .entity(entity)
+                    .remove::<Handle<MagneticFieldGrid>>()
+                    .remove::<PrecalculatedMagneticFieldGrid>()
+                    .insert(asset_server.load::<FieldDefinition, _>("fields/slower.coils.json"));
+            }
+            (false, true) => {
+                commands
+                    .entity(entity)
+                    .remove::<Handle<FieldDefinition>>()
+                    .remove::<CoilAssembly>()
+                    .remove::<PrecalculatedMagneticFieldGrid>()
+                    .insert(asset_server.load::<MagneticFieldGrid, _>("fields/slower.field.json"))
+                    .despawn_descendants();
+            }
+            _ => {}
+        }
+    }
+}
@@ -15,8 +15,13 @@ use atomecs::magnetic::top::UniformFieldRotator;
 use atomecs::species::Rubidium87_780D2;
 use atomecs_demos::atoms::add_meshes_to_atoms;
 use atomecs_demos::camera::{control_camera, DemoCamera};
+use atomecs_demos::controls::{ControlsPlugin, SetupAppExt};
+use atomecs_demos::hud::HudPlugin;
+use atomecs_demos::random::SimulationRng;
+use atomecs_demos::stepping::{PresentationStage, SimulationSpeed, SteppingPlugin};
 use atomecs_demos::{add_atomecs_watermark, BevyAtomECSPlugin};
 use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
 use nalgebra::Vector3;
 use rand_distr::{Distribution, Normal};
 
@@ -28,14 +33,20 @@ fn main() {
     app.add_plugin(atomecs::magnetic::MagneticsPlugin);
     app.add_system(atomecs::output::console_output::console_output);
     app.add_plugins(DefaultPlugins);
-    app.add_system(atomecs::bevy_bridge::copy_positions);
-    app.add_system(control_camera);
-    app.add_system(add_meshes_to_atoms::<Rubidium87_780D2>);
+    app.add_plugin(EguiPlugin);
+    app.add_plugin(SteppingPlugin);
+    app.add_plugin(ControlsPlugin);
+    app.add_plugin(HudPlugin);
+    // Atoms in the trap are slow, so run the simulation faster than the other demos.
+    app.insert_resource(SimulationSpeed::with_target_ratio(0.05));
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    app.add_system_to_stage(PresentationStage, control_camera);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<Rubidium87_780D2>);
     app.add_startup_system(add_atomecs_watermark);
     app.add_plugin(BevyAtomECSPlugin);
     app.add_startup_system(setup);
     app.insert_resource(atomecs::bevy_bridge::Scale { 0: 1e4 });
-    app.add_startup_system(setup_atoms);
+    app.add_setup_system(setup_atoms);
 
     // Create magnetic field.
     app.world
@@ -57,7 +68,7 @@ fn main() {
     app.run();
 }
 
-fn setup_atoms(mut commands: Commands) {
+fn setup_atoms(mut commands: Commands, mut rng: ResMut<SimulationRng>) {
     let p_dist = Normal::new(0.0, 50e-6).unwrap();
     let v_dist = Normal::new(0.0, 0.004).unwrap(); // ~100nK
 
@@ -65,18 +76,18 @@ fn setup_atoms(mut commands: Commands) {
         commands
             .spawn(Position {
                 pos: Vector3::new(
-                    p_dist.sample(&mut rand::thread_rng()),
-                    p_dist.sample(&mut rand::thread_rng()),
-                    0.35 * p_dist.sample(&mut rand::thread_rng()), //TOP traps have tighter confinement along quadrupole axis
+                    p_dist.sample(&mut *rng),
+                    p_dist.sample(&mut *rng),
+                    0.35 * p_dist.sample(&mut *rng), //TOP traps have tighter confinement along quadrupole axis
                 ),
             })
             .insert(Atom)
             .insert(Force::default())
             .insert(Velocity {
                 vel: Vector3::new(
-                    v_dist.sample(&mut rand::thread_rng()),
-                    v_dist.sample(&mut rand::thread_rng()),
-                    v_dist.sample(&mut rand::thread_rng()),
+                    v_dist.sample(&mut *rng),
+                    v_dist.sample(&mut *rng),
+                    v_dist.sample(&mut *rng),
                 ),
             })
             .insert(NewlyCreated)
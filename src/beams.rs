@@ -0,0 +1,400 @@
+//! Builders for common configurations of cooling beams.
+//!
+//! Each builder spawns its `GaussianBeam` + `CoolingLight` entities with a shared marker
+//! component, and returns the spawned entities so they can be controlled at runtime.
+
+use atomecs::laser::gaussian::GaussianBeam;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::laser_cooling::CoolingLight;
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+use crate::geometry::perpendicular_basis;
+
+/// Properties shared by all beams spawned by a builder.
+#[derive(Clone)]
+pub struct BeamParameters {
+    /// A point all beams pass through, in units of m.
+    pub intersection: Vector3<f64>,
+    /// 1/e radius of each beam, in units of m.
+    pub e_radius: f64,
+    /// Power of each beam, in units of W.
+    pub power: f64,
+    /// Detuning from the cooling transition, in units of MHz.
+    pub detuning: f64,
+    /// Rayleigh range of each beam, in units of m.
+    pub rayleigh_range: f64,
+}
+impl Default for BeamParameters {
+    fn default() -> Self {
+        BeamParameters {
+            intersection: Vector3::zeros(),
+            e_radius: 1e-2,
+            power: 0.01,
+            detuning: 0.0,
+            rayleigh_range: f64::INFINITY,
+        }
+    }
+}
+
+impl BeamParameters {
+    fn spawn<T, M>(
+        &self,
+        commands: &mut Commands,
+        direction: Vector3<f64>,
+        polarization: i32,
+        marker: &M,
+    ) -> Entity
+    where
+        T: AtomicTransition,
+        M: Component + Clone,
+    {
+        commands
+            .spawn(GaussianBeam {
+                intersection: self.intersection,
+                e_radius: self.e_radius,
+                power: self.power,
+                direction: direction.normalize(),
+                rayleigh_range: self.rayleigh_range,
+                ellipticity: 0.0,
+            })
+            .insert(CoolingLight::for_transition::<T>(
+                self.detuning,
+                polarization,
+            ))
+            .insert(marker.clone())
+            .id()
+    }
+}
+
+/// Converts a 1/e^2 diameter into the 1/e radius used by `GaussianBeam`.
+pub fn e_radius_from_e2_diameter(e2_diameter: f64) -> f64 {
+    e2_diameter / 2.0 / 2.0_f64.sqrt()
+}
+
+macro_rules! beam_parameter_setters {
+    () => {
+        /// Sets the point all beams pass through, in units of m.
+        pub fn at(mut self, intersection: Vector3<f64>) -> Self {
+            self.parameters.intersection = intersection;
+            self
+        }
+        /// Sets the 1/e radius of each beam, in units of m.
+        pub fn e_radius(mut self, e_radius: f64) -> Self {
+            self.parameters.e_radius = e_radius;
+            self
+        }
+        /// Sets the power of each beam, in units of W.
+        pub fn power(mut self, power: f64) -> Self {
+            self.parameters.power = power;
+            self
+        }
+        /// Sets the detuning of each beam, in units of MHz.
+        pub fn detuning(mut self, detuning: f64) -> Self {
+            self.parameters.detuning = detuning;
+            self
+        }
+        /// Sets the Rayleigh range of each beam, in units of m.
+        pub fn rayleigh_range(mut self, rayleigh_range: f64) -> Self {
+            self.parameters.rayleigh_range = rayleigh_range;
+            self
+        }
+    };
+}
+
+/// Counter-propagating pairs of beams for a magneto-optical trap.
+#[derive(Clone)]
+pub struct MotBeams {
+    pub parameters: BeamParameters,
+    /// Direction of each pair of beams, and the polarization of both beams in the pair.
+    pub pairs: Vec<(Vector3<f64>, i32)>,
+}
+
+impl MotBeams {
+    /// Three orthogonal pairs of beams for a 3D MOT, with a quadrupole field along `axis`.
+    ///
+    /// Beams along the quadrupole axis have the given `polarization`, and the radial beams have the opposite.
+    pub fn three_d(axis: Vector3<f64>, polarization: i32) -> Self {
+        let axis = axis.normalize();
+        let (u, v) = perpendicular_basis(&axis);
+        MotBeams {
+            parameters: BeamParameters::default(),
+            pairs: vec![(axis, polarization), (u, -polarization), (v, -polarization)],
+        }
+    }
+
+    /// Two orthogonal pairs of beams perpendicular to `axis`, for a 2D MOT.
+    ///
+    /// The first pair propagates along `direction` and the second along `axis` x `direction`, with
+    /// the given polarizations.
+    pub fn two_d(axis: Vector3<f64>, direction: Vector3<f64>, polarizations: [i32; 2]) -> Self {
+        let direction = direction.normalize();
+        MotBeams {
+            parameters: BeamParameters::default(),
+            pairs: vec![
+                (direction, polarizations[0]),
+                (axis.normalize().cross(&direction), polarizations[1]),
+            ],
+        }
+    }
+
+    beam_parameter_setters!();
+
+    /// Spawns both beams of each pair, and returns the spawned entities.
+    pub fn spawn<T, M>(&self, commands: &mut Commands, marker: M) -> Vec<Entity>
+    where
+        T: AtomicTransition,
+        M: Component + Clone,
+    {
+        let mut entities = Vec::new();
+        for (direction, polarization) in self.pairs.iter() {
+            for sign in [1.0, -1.0] {
+                entities.push(self.parameters.spawn::<T, M>(
+                    commands,
+                    sign * *direction,
+                    *polarization,
+                    &marker,
+                ));
+            }
+        }
+        entities
+    }
+}
+
+/// Two orthogonal pairs of beams that cool an atomic beam transverse to its direction of travel.
+#[derive(Clone)]
+pub struct TransverseCooling {
+    beams: MotBeams,
+}
+
+impl TransverseCooling {
+    /// Transverse cooling of an atomic beam travelling along `beam_axis`, centred on `position`.
+    ///
+    /// All beams have polarization +1.
+    pub fn at(position: Vector3<f64>, beam_axis: Vector3<f64>) -> Self {
+        let beam_axis = beam_axis.normalize();
+        let trial = if beam_axis.z.abs() < 0.9 {
+            Vector3::z()
+        } else {
+            Vector3::x()
+        };
+        let direction = trial - beam_axis * trial.dot(&beam_axis);
+        let mut beams = MotBeams::two_d(beam_axis, direction, [1, 1]);
+        beams.parameters.intersection = position;
+        TransverseCooling { beams }
+    }
+
+    /// Sets the 1/e radius of each beam, in units of m.
+    pub fn e_radius(mut self, e_radius: f64) -> Self {
+        self.beams = self.beams.e_radius(e_radius);
+        self
+    }
+    /// Sets the power of each beam, in units of W.
+    pub fn power(mut self, power: f64) -> Self {
+        self.beams = self.beams.power(power);
+        self
+    }
+    /// Sets the detuning of each beam, in units of MHz.
+    pub fn detuning(mut self, detuning: f64) -> Self {
+        self.beams = self.beams.detuning(detuning);
+        self
+    }
+
+    /// Spawns the four beams, and returns the spawned entities.
+    pub fn spawn<T, M>(&self, commands: &mut Commands, marker: M) -> Vec<Entity>
+    where
+        T: AtomicTransition,
+        M: Component + Clone,
+    {
+        self.beams.spawn::<T, M>(commands, marker)
+    }
+}
+
+/// A single beam, for example a push beam.
+#[derive(Clone)]
+pub struct SingleBeam {
+    pub parameters: BeamParameters,
+    pub direction: Vector3<f64>,
+    pub polarization: i32,
+}
+
+impl SingleBeam {
+    pub fn along(direction: Vector3<f64>, polarization: i32) -> Self {
+        SingleBeam {
+            parameters: BeamParameters::default(),
+            direction,
+            polarization,
+        }
+    }
+
+    beam_parameter_setters!();
+
+    /// Spawns the beam, and returns the spawned entity.
+    pub fn spawn<T, M>(&self, commands: &mut Commands, marker: M) -> Entity
+    where
+        T: AtomicTransition,
+        M: Component + Clone,
+    {
+        self.parameters
+            .spawn::<T, M>(commands, self.direction, self.polarization, &marker)
+    }
+}
+
+/// A beam that counter-propagates against an atomic beam to slow it.
+#[derive(Clone)]
+pub struct ZeemanSlowerBeam {
+    beam: SingleBeam,
+}
+
+impl ZeemanSlowerBeam {
+    /// Slowing beam for atoms travelling along `atom_direction`.
+    ///
+    /// The beam propagates against the atoms with polarization -1.
+    pub fn against(atom_direction: Vector3<f64>) -> Self {
+        ZeemanSlowerBeam {
+            beam: SingleBeam::along(-atom_direction, -1),
+        }
+    }
+
+    /// Sets the polarization of the beam.
+    pub fn polarization(mut self, polarization: i32) -> Self {
+        self.beam.polarization = polarization;
+        self
+    }
+    /// Sets the 1/e radius of the beam, in units of m.
+    pub fn e_radius(mut self, e_radius: f64) -> Self {
+        self.beam = self.beam.e_radius(e_radius);
+        self
+    }
+    /// Sets the power of the beam, in units of W.
+    pub fn power(mut self, power: f64) -> Self {
+        self.beam = self.beam.power(power);
+        self
+    }
+    /// Sets the detuning of the beam, in units of MHz.
+    pub fn detuning(mut self, detuning: f64) -> Self {
+        self.beam = self.beam.detuning(detuning);
+        self
+    }
+
+    /// Spawns the beam, and returns the spawned entity.
+    pub fn spawn<T, M>(&self, commands: &mut Commands, marker: M) -> Entity
+    where
+        T: AtomicTransition,
+        M: Component + Clone,
+    {
+        self.beam.spawn::<T, M>(commands, marker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use atomecs::species::Strontium88_461;
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+
+    #[derive(Component, Clone)]
+    struct Marker;
+
+    /// Spawns beams into an empty world, and returns the direction and polarization of each.
+    fn spawned(spawn: impl FnOnce(&mut Commands)) -> Vec<(Vector3<f64>, i32)> {
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        spawn(&mut Commands::new(&mut queue, &world));
+        queue.apply(&mut world);
+        world
+            .query_filtered::<(&GaussianBeam, &CoolingLight), With<Marker>>()
+            .iter(&world)
+            .map(|(beam, light)| (beam.direction, light.polarization))
+            .collect()
+    }
+
+    /// Checks that the beams match the expected directions and polarizations, in any order.
+    fn assert_beams(beams: Vec<(Vector3<f64>, i32)>, expected: &[([f64; 3], i32)]) {
+        assert_eq!(beams.len(), expected.len());
+        for (direction, polarization) in expected {
+            let direction = Vector3::new(direction[0], direction[1], direction[2]).normalize();
+            assert!(
+                beams
+                    .iter()
+                    .any(|(d, p)| (d - direction).norm() < 1e-12 && p == polarization),
+                "No beam along {:?} with polarization {} in {:?}",
+                direction,
+                polarization,
+                beams
+            );
+        }
+    }
+
+    #[test]
+    fn two_d_matches_2d_mot_beams() {
+        let beams = spawned(|commands| {
+            MotBeams::two_d(Vector3::z(), Vector3::new(1.0, 1.0, 0.0), [1, 1])
+                .spawn::<Strontium88_461, _>(commands, Marker);
+        });
+        assert_beams(
+            beams,
+            &[
+                ([1.0, 1.0, 0.0], 1),
+                ([1.0, -1.0, 0.0], 1),
+                ([-1.0, 1.0, 0.0], 1),
+                ([-1.0, -1.0, 0.0], 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn two_d_matches_aion_source_beams() {
+        let beams = spawned(|commands| {
+            MotBeams::two_d(Vector3::x(), Vector3::new(0.0, 1.0, 1.0), [1, -1])
+                .spawn::<Strontium88_461, _>(commands, Marker);
+        });
+        assert_beams(
+            beams,
+            &[
+                ([0.0, 1.0, 1.0], 1),
+                ([0.0, -1.0, -1.0], 1),
+                ([0.0, 1.0, -1.0], -1),
+                ([0.0, -1.0, 1.0], -1),
+            ],
+        );
+    }
+
+    #[test]
+    fn transverse_cooling_matches_schreck_beams() {
+        let beams = spawned(|commands| {
+            TransverseCooling::at(Vector3::new(-1.7, 0.0, 0.0), Vector3::x())
+                .spawn::<Strontium88_461, _>(commands, Marker);
+            ZeemanSlowerBeam::against(Vector3::x()).spawn::<Strontium88_461, _>(commands, Marker);
+        });
+        assert_beams(
+            beams,
+            &[
+                ([0.0, 0.0, 1.0], 1),
+                ([0.0, 0.0, -1.0], 1),
+                ([0.0, 1.0, 0.0], 1),
+                ([0.0, -1.0, 0.0], 1),
+                ([-1.0, 0.0, 0.0], -1),
+            ],
+        );
+    }
+
+    #[test]
+    fn three_d_matches_3d_mot_beams() {
+        let beams = spawned(|commands| {
+            MotBeams::three_d(Vector3::z(), -1).spawn::<Strontium88_461, _>(commands, Marker);
+        });
+        assert_beams(
+            beams,
+            &[
+                ([0.0, 0.0, 1.0], -1),
+                ([0.0, 0.0, -1.0], -1),
+                ([1.0, 0.0, 0.0], 1),
+                ([-1.0, 0.0, 0.0], 1),
+                ([0.0, 1.0, 0.0], 1),
+                ([0.0, -1.0, 0.0], 1),
+            ],
+        );
+    }
+}
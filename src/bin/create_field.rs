@@ -0,0 +1,35 @@
+//! Calculates a magnetic field grid from a description of coils and magnets.
+//!
+//! cargo run --release --bin create_field -- <definition.coils.json> <output.field.json|output.field.bin>
+//!
+//! The definition file is a json [FieldDefinition]. The output format is chosen from the extension
+//! of the output file.
+
+use atomecs_demos::coils::FieldDefinition;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: create_field <definition.coils.json> <output.field.json|output.field.bin>");
+        std::process::exit(1);
+    }
+    let (input, output) = (&args[1], &args[2]);
+
+    let json = std::fs::read_to_string(input)
+        .unwrap_or_else(|e| panic!("Could not read field definition {}: {}", input, e));
+    let definition: FieldDefinition = serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("Could not parse field definition {}: {}", input, e));
+
+    let grid = definition.calculate();
+    let bytes = if output.ends_with(".field.bin") {
+        grid.to_binary()
+    } else if output.ends_with(".field.json") {
+        serde_json::to_vec(&grid).expect("Could not serialize magnetic field grid.")
+    } else {
+        eprintln!("Output file must end in .field.json or .field.bin");
+        std::process::exit(1);
+    };
+    std::fs::write(output, bytes)
+        .unwrap_or_else(|e| panic!("Could not write magnetic field grid {}: {}", output, e));
+    println!("Wrote {} grid points to {}.", grid.grid.len(), output);
+}
@@ -0,0 +1,99 @@
+//! Sweeps the detuning of the 3D MOT headless, and compares the minimum temperature with the
+//! Doppler limit.
+//!
+//! cargo run --release --bin doppler_sweep -- <doppler.json> [output.csv] [--workers <n>] [--seed <n>]
+//!
+//! The configuration is a json [DopplerConfiguration] with a `sweep` of detunings, such as
+//! `assets/doppler_validation.json`. By default one worker is used per CPU core.
+
+use std::fmt::Write;
+
+use atomecs::species::Rubidium87_780D2;
+use atomecs_demos::doppler::{
+    doppler_limit, minimum_temperature, run_detuning_sweep, DopplerConfiguration,
+};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || {
+        eprintln!("Usage: doppler_sweep <doppler.json> [output.csv] [--workers <n>] [--seed <n>]");
+        std::process::exit(1);
+    };
+    if args.len() < 2 {
+        usage();
+    }
+    let input = &args[1];
+    let (output, options) = match args.get(2) {
+        Some(output) if !output.starts_with("--") => (Some(output), &args[3..]),
+        _ => (None, &args[2..]),
+    };
+    let mut workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut seed = 0;
+    for option in options.chunks(2) {
+        match option {
+            [flag, value] if flag == "--workers" => {
+                workers = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse number of workers {}", value))
+            }
+            [flag, value] if flag == "--seed" => {
+                seed = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse seed {}", value))
+            }
+            _ => usage(),
+        }
+    }
+
+    let configuration = DopplerConfiguration::load(input)
+        .unwrap_or_else(|e| panic!("Could not read configuration {}: {}", input, e));
+    let total = configuration
+        .sweep
+        .as_ref()
+        .map_or(0, |sweep| sweep.detunings().len());
+    if total == 0 {
+        eprintln!("{} has no sweep of detunings.", input);
+        std::process::exit(1);
+    }
+
+    println!("Running {} detunings on {} workers.", total, workers);
+    let mut finished = 0;
+    let results = run_detuning_sweep(&configuration, seed, workers, |_, _| {
+        finished += 1;
+        println!("[{}/{}]", finished, total);
+    });
+
+    let limit = doppler_limit::<Rubidium87_780D2>();
+    let mut table = String::from("detuning,atom_number,mean_speed,temperature\n");
+    println!("detuning (MHz)  atoms  temperature (μK)");
+    for (detuning, cloud) in results.iter() {
+        println!(
+            "{:>14.2}  {:>5}  {:>16.1}",
+            detuning,
+            cloud.atom_number,
+            cloud.temperature * 1e6
+        );
+        writeln!(
+            table,
+            "{},{},{},{}",
+            detuning, cloud.atom_number, cloud.mean_speed, cloud.temperature
+        )
+        .unwrap();
+    }
+    match minimum_temperature(&results) {
+        Some((detuning, cloud)) => println!(
+            "Minimum temperature {:.1} μK at {:.2} MHz. Doppler limit ħΓ/2k_B = {:.1} μK ({:+.1}%).",
+            cloud.temperature * 1e6,
+            detuning,
+            limit * 1e6,
+            (cloud.temperature / limit - 1.0) * 100.0
+        ),
+        None => println!("Every atom escaped at every detuning."),
+    }
+
+    if let Some(output) = output {
+        std::fs::write(output, table)
+            .unwrap_or_else(|e| panic!("Could not write results {}: {}", output, e));
+        println!("Wrote {} results to {}.", results.len(), output);
+    }
+}
@@ -0,0 +1,111 @@
+//! Optimises the parameters of an experiment headless, and saves the best as a preset.
+//!
+//! cargo run --release --bin optimise -- <optimisation.json> <log.csv> [--workers <n>] [--preset <name>]
+//!
+//! The optimisation file is a json [OptimisationDescription]. Every evaluation is appended to the
+//! log as it is made. The best parameters are saved as a preset of the experiment, named
+//! `optimised` unless another name is given, which can be loaded in the control panel of the demo.
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use atomecs_demos::aion_source::{self, AionSourcePlugin};
+use atomecs_demos::experiment::ExperimentControls;
+use atomecs_demos::mot_2d::{self, Mot2DPlugin};
+use atomecs_demos::optimise::{optimise, EvaluationLog, OptimisationDescription};
+use atomecs_demos::presets::Presets;
+use atomecs_demos::sweep::BuildExperiment;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || {
+        eprintln!(
+            "Usage: optimise <optimisation.json> <log.csv> [--workers <n>] [--preset <name>]"
+        );
+        std::process::exit(1);
+    };
+    if args.len() < 3 {
+        usage();
+    }
+    let (input, log) = (&args[1], &args[2]);
+    let mut workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut preset = "optimised".to_string();
+    for option in args[3..].chunks(2) {
+        match option {
+            [flag, value] if flag == "--workers" => {
+                workers = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse number of workers {}", value))
+            }
+            [flag, value] if flag == "--preset" => preset = value.clone(),
+            _ => usage(),
+        }
+    }
+
+    let json = std::fs::read_to_string(input)
+        .unwrap_or_else(|e| panic!("Could not read optimisation {}: {}", input, e));
+    let description = OptimisationDescription::from_json(&json)
+        .unwrap_or_else(|e| panic!("Could not parse optimisation {}: {}", input, e));
+
+    match description.experiment.as_str() {
+        "aion_source" => run::<aion_source::ExperimentConfiguration>(
+            &description,
+            |app| {
+                app.add_plugin(AionSourcePlugin);
+            },
+            workers,
+            log,
+            &preset,
+        ),
+        "2d_mot" => run::<mot_2d::ExperimentConfiguration>(
+            &description,
+            |app| {
+                app.add_plugin(Mot2DPlugin);
+            },
+            workers,
+            log,
+            &preset,
+        ),
+        other => {
+            eprintln!(
+                "Unknown experiment {}. Known experiments: aion_source, 2d_mot",
+                other
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run<C: ExperimentControls>(
+    description: &OptimisationDescription,
+    build: BuildExperiment,
+    workers: usize,
+    log: &str,
+    preset: &str,
+) {
+    let file = File::create(log).unwrap_or_else(|e| panic!("Could not create log {}: {}", log, e));
+    let mut writer = EvaluationLog::new(BufWriter::new(file));
+    println!(
+        "Optimising {:?} of {} on {} workers.",
+        description.objective, description.experiment, workers
+    );
+    let optimum = optimise::<C>(description, build, workers, |evaluation| {
+        println!(
+            "{:?}: objective {:.4e}",
+            evaluation.parameters, evaluation.objective
+        );
+        writer
+            .write(evaluation)
+            .unwrap_or_else(|e| panic!("Could not write log {}: {}", log, e));
+    })
+    .unwrap_or_else(|e| panic!("Could not optimise: {}", e));
+
+    println!(
+        "Best after {} evaluations: {:?}, objective {:.4e}",
+        optimum.evaluations, optimum.parameters, optimum.objective
+    );
+    Presets::<C>::new(&description.experiment)
+        .save(preset, &optimum.config)
+        .unwrap_or_else(|e| panic!("Could not save preset {}: {}", preset, e));
+    println!("Saved preset {} of {}.", preset, description.experiment);
+}
@@ -0,0 +1,82 @@
+//! Runs an experiment headless, and records the trajectories of its atoms.
+//!
+//! cargo run --release --bin record -- <experiment> <duration> <output.traj> [--interval <steps>] [--seed <n>]
+//!
+//! The duration is the simulated time to run for, in units of s. A frame is recorded every
+//! `interval` steps, 1 by default. The trajectory can be watched with the `replay` example.
+
+use atomecs_demos::aion_source::AionSourcePlugin;
+use atomecs_demos::scene::Species;
+use atomecs_demos::stepping::{headless_app, SimulationClock};
+use atomecs_demos::sweep::BuildExperiment;
+use atomecs_demos::trajectory::{TrajectoryRecorder, TrajectoryRecorderPlugin};
+use bevy::prelude::*;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || {
+        eprintln!(
+            "Usage: record <experiment> <duration> <output.traj> [--interval <steps>] [--seed <n>]"
+        );
+        std::process::exit(1);
+    };
+    if args.len() < 4 {
+        usage();
+    }
+    let (experiment, output) = (&args[1], &args[3]);
+    let duration: f64 = args[2]
+        .parse()
+        .unwrap_or_else(|_| panic!("Could not parse duration {}", args[2]));
+    let mut interval = 1;
+    let mut seed = 0;
+    for option in args[4..].chunks(2) {
+        match option {
+            [flag, value] if flag == "--interval" => {
+                interval = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse interval {}", value))
+            }
+            [flag, value] if flag == "--seed" => {
+                seed = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse seed {}", value))
+            }
+            _ => usage(),
+        }
+    }
+
+    let (build, species): (BuildExperiment, Species) = match experiment.as_str() {
+        "aion_source" => (
+            |app: &mut App| {
+                app.add_plugin(AionSourcePlugin);
+            },
+            Species::Strontium88_461,
+        ),
+        other => {
+            eprintln!(
+                "Unknown experiment {}. Known experiments: aion_source",
+                other
+            );
+            std::process::exit(1);
+        }
+    };
+    let mut app = headless_app(seed, build);
+    app.add_plugin(TrajectoryRecorderPlugin::new(output, species, interval));
+
+    println!("Recording {} s of {} to {}.", duration, experiment, output);
+    let time = |app: &App| app.world.resource::<SimulationClock>().time;
+    let mut reported = 0;
+    while time(&app) < duration {
+        app.update();
+        let percent = (100.0 * time(&app) / duration) as u32;
+        if percent >= reported + 10 {
+            reported = percent - percent % 10;
+            println!("{}%", reported);
+        }
+    }
+    app.world
+        .resource_mut::<TrajectoryRecorder>()
+        .finish()
+        .unwrap_or_else(|e| panic!("Could not write trajectory {}: {}", output, e));
+    println!("Wrote {}.", output);
+}
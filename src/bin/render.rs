@@ -0,0 +1,177 @@
+//! Renders a scene offscreen, without a window, for publication figures.
+//!
+//! cargo run --release --bin render -- <scene.json> <time> <output.png> [--width <px>] [--height <px>]
+//!     [--msaa <samples>] [--camera <view>] [--background <transparent|opaque>] [--seed <n>]
+//!
+//! The scene is simulated for `time` seconds of simulated time, then rendered at 3840×2160 by
+//! default, with 4× multisample anti-aliasing and a transparent background. The camera starts from
+//! the scene's camera description, turned to the `top`, `side`, `front` or `default` view if one
+//! is given. The view is framed as in a default 1280×720 window, whatever the resolution, so the
+//! same command always gives the same figure.
+
+use atomecs::bevy_bridge::Scale;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::species::{Rubidium87_780D2, Strontium88_461};
+use atomecs_demos::atoms::add_meshes_to_atoms;
+use atomecs_demos::camera::DemoCamera;
+use atomecs_demos::capture::{CapturePlugin, Captures};
+use atomecs_demos::lasers::add_meshes_to_lasers;
+use atomecs_demos::scene::{
+    spawn_scene_camera, SceneDescription, ScenePlugin, Species, BEAM_NUMBER,
+};
+use atomecs_demos::stepping::{PresentationStage, SimulationSpeed, SteppingPlugin};
+use atomecs_demos::BevyAtomECSPlugin;
+use bevy::prelude::*;
+use bevy::render::camera::{Projection, ScalingMode};
+use bevy::winit::WinitPlugin;
+
+/// Options of the render, read by the [frame_camera] system.
+#[derive(Resource)]
+struct RenderOptions {
+    view: Option<String>,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || {
+        eprintln!(
+            "Usage: render <scene.json> <time> <output.png> [--width <px>] [--height <px>] [--msaa <samples>] [--camera <view>] [--background <transparent|opaque>] [--seed <n>]"
+        );
+        std::process::exit(1);
+    };
+    if args.len() < 4 {
+        usage();
+    }
+    let scene = SceneDescription::load(&args[1]);
+    let time: f64 = args[2]
+        .parse()
+        .unwrap_or_else(|_| panic!("Could not parse time {}", args[2]));
+    let output = &args[3];
+    let (mut width, mut height, mut samples) = (3840, 2160, 4);
+    let mut view = None;
+    let mut transparent = true;
+    for option in args[4..].chunks(2) {
+        match option {
+            [flag, value] if flag == "--width" => {
+                width = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse width {}", value))
+            }
+            [flag, value] if flag == "--height" => {
+                height = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse height {}", value))
+            }
+            [flag, value] if flag == "--msaa" => {
+                samples = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Could not parse samples {}", value))
+            }
+            [flag, value] if flag == "--camera" => {
+                if !DemoCamera::default().set_view(value) {
+                    eprintln!(
+                        "Unknown view {}. Views are top, side, front and default.",
+                        value
+                    );
+                    std::process::exit(1);
+                }
+                view = Some(value.clone());
+            }
+            [flag, value] if flag == "--background" => match value.as_str() {
+                "transparent" => transparent = true,
+                "opaque" => transparent = false,
+                _ => usage(),
+            },
+            // Read by the scene plugin.
+            [flag, _] if flag == "--seed" => {}
+            _ => usage(),
+        }
+    }
+    if samples != 1 && samples != 4 {
+        eprintln!("Multisampling must use 1 or 4 samples.");
+        std::process::exit(1);
+    }
+
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                add_primary_window: false,
+                exit_on_all_closed: false,
+                ..default()
+            })
+            .disable::<WinitPlugin>(),
+    );
+    app.insert_resource(Msaa { samples });
+    app.insert_resource(RenderOptions { view });
+    app.add_plugin(SteppingPlugin);
+    match scene.species {
+        Species::Strontium88_461 => add_scene::<Strontium88_461>(&mut app, scene),
+        Species::Rubidium87_780D2 => add_scene::<Rubidium87_780D2>(&mut app, scene),
+    }
+    app.add_plugin(BevyAtomECSPlugin);
+    app.add_plugin(CapturePlugin);
+    app.add_startup_system(spawn_scene_camera);
+    app.add_startup_system_to_stage(StartupStage::PostStartup, frame_camera);
+    app.add_system_to_stage(PresentationStage, atomecs::bevy_bridge::copy_positions);
+    {
+        let mut captures = app.world.resource_mut::<Captures>();
+        captures.width = width;
+        captures.height = height;
+        captures.transparent = transparent;
+    }
+
+    // Run the simulation as fast as it goes, rather than in step with real time.
+    let steps = (time / app.world.resource::<atomecs::integrator::Timestep>().delta).round();
+    {
+        let mut speed = app.world.resource_mut::<SimulationSpeed>();
+        speed.frame_budget = 1.0;
+        speed.step(steps as u32);
+    }
+    println!("Simulating {} s of {}.", time, args[1]);
+    while app.world.resource::<SimulationSpeed>().is_stepping() {
+        app.update();
+    }
+
+    println!("Rendering {}×{}.", width, height);
+    // Only a new file shows that the render succeeded.
+    let _ = std::fs::remove_file(output);
+    app.world.resource_mut::<Captures>().screenshot_to(output);
+    while !app.world.resource::<Captures>().screenshots_finished() {
+        app.update();
+    }
+    let status = &app.world.resource::<Captures>().status;
+    if !std::path::Path::new(output).exists() {
+        eprintln!("{}", status);
+        std::process::exit(1);
+    }
+    println!("{}", status);
+}
+
+fn add_scene<T>(app: &mut App, scene: SceneDescription)
+where
+    T: AtomicTransition + Component + Default + Copy,
+{
+    app.add_plugin(ScenePlugin::<T, { BEAM_NUMBER }>::new(scene));
+    app.add_system_to_stage(PresentationStage, add_meshes_to_atoms::<T>);
+    app.add_system_to_stage(PresentationStage, add_meshes_to_lasers::<T>);
+}
+
+/// Turns the camera to the chosen view, and frames it independently of the resolution.
+fn frame_camera(
+    options: Res<RenderOptions>,
+    scale: Res<Scale>,
+    mut cameras: Query<(&mut DemoCamera, &mut Transform, &mut Projection)>,
+) {
+    for (mut camera, mut transform, mut projection) in cameras.iter_mut() {
+        if let Some(view) = &options.view {
+            camera.set_view(view);
+            *transform = camera.get_transform(scale.0 as f32);
+        }
+        if let Projection::Orthographic(orthographic) = &mut *projection {
+            let height = orthographic.scale * WindowDescriptor::default().height;
+            orthographic.scaling_mode = ScalingMode::FixedVertical(height);
+            orthographic.scale = 1.0;
+        }
+    }
+}
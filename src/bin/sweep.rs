@@ -0,0 +1,62 @@
+//! Runs a sweep over the parameters of an experiment headless, and writes a table of diagnostics.
+//!
+//! cargo run --release --bin sweep -- <sweep.json> <output.csv|output.json> [--workers <n>]
+//!
+//! The sweep file is a json [SweepDescription]. By default one worker is used per CPU core.
+
+use atomecs_demos::aion_source::{self, AionSourcePlugin};
+use atomecs_demos::sweep::{run_sweep, write_results, SweepDescription, SweepResult};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 && !(args.len() == 5 && args[3] == "--workers") {
+        eprintln!("Usage: sweep <sweep.json> <output.csv|output.json> [--workers <n>]");
+        std::process::exit(1);
+    }
+    let (input, output) = (&args[1], &args[2]);
+    let workers = match args.get(4) {
+        Some(workers) => workers
+            .parse()
+            .unwrap_or_else(|_| panic!("Could not parse number of workers {}", workers)),
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let json = std::fs::read_to_string(input)
+        .unwrap_or_else(|e| panic!("Could not read sweep {}: {}", input, e));
+    let description = SweepDescription::from_json(&json)
+        .unwrap_or_else(|e| panic!("Could not parse sweep {}: {}", input, e));
+
+    let total = description.points().len();
+    println!(
+        "Running {} points of {} on {} workers.",
+        total, description.experiment, workers
+    );
+    let progress = |finished: usize, result: &SweepResult| {
+        println!(
+            "[{}/{}] {:?}: flux {:.3e} atoms/s",
+            finished, total, result.parameters, result.diagnostics.flux
+        );
+    };
+    let results = match description.experiment.as_str() {
+        "aion_source" => run_sweep::<aion_source::ExperimentConfiguration>(
+            &description,
+            |app| {
+                app.add_plugin(AionSourcePlugin);
+            },
+            workers,
+            progress,
+        ),
+        other => {
+            eprintln!(
+                "Unknown experiment {}. Known experiments: aion_source",
+                other
+            );
+            std::process::exit(1);
+        }
+    }
+    .unwrap_or_else(|e| panic!("Could not run sweep: {}", e));
+
+    write_results(output, &results)
+        .unwrap_or_else(|e| panic!("Could not write results {}: {}", output, e));
+    println!("Wrote {} results to {}.", results.len(), output);
+}
@@ -25,6 +25,25 @@ impl DemoCamera {
     pub fn new(radius: f32, target: Vec3) -> Self {
         Self { radius, target, ..default() }
     }
+
+    /// Moves the camera to a named view: `top`, `side`, `front` or `default`.
+    ///
+    /// Returns false if the view is not known.
+    pub fn set_view(&mut self, view: &str) -> bool {
+        let (orbit, delta) = match view {
+            "top" => (std::f32::consts::FRAC_PI_2, 1.4),
+            "side" => (0.0, 0.0),
+            "front" => (std::f32::consts::FRAC_PI_2, 0.0),
+            "default" => {
+                let default = DemoCamera::default();
+                (default.orbit, default.delta)
+            }
+            _ => return false,
+        };
+        self.orbit = orbit;
+        self.delta = delta;
+        true
+    }
 }
 impl Default for DemoCamera {
     fn default() -> Self {
@@ -0,0 +1,964 @@
+//! Screenshots and recordings of the demos, for figures and movies.
+//!
+//! The 3D view is captured by rendering it again, without the UI, from a copy of the [DemoCamera]
+//! into an image of the chosen resolution, which is copied back from the GPU. The UI is drawn
+//! straight to the window, which bevy cannot read back natively, so captures that include the UI
+//! are read from the canvas in the browser, at the resolution of the page. Natively they fall back
+//! to the 3D view alone.
+//!
+//! Recordings capture a frame every fixed interval of simulated time. The simulation is paused and
+//! stepped to each frame, then held until the frame has been captured, so the movie does not
+//! depend on how quickly the demo renders. Natively, screenshots are written to `captures/`, and
+//! recordings as a numbered PNG sequence or, through `ffmpeg`, as an mp4 video. In the browser,
+//! screenshots and zipped PNG sequences are offered as downloads.
+
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Mutex;
+
+use atomecs::integrator::Timestep;
+use bevy::asset::Error;
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::render::camera::{Projection, RenderTarget, ScalingMode};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::main_graph::node::CAMERA_DRIVER;
+use bevy::render::render_asset::{PrepareAssetLabel, RenderAssets};
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageDataLayout, MapMode,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::{RenderApp, RenderStage};
+use bevy_egui::egui;
+
+use crate::camera::DemoCamera;
+use crate::stepping::{PresentationStage, SimulationSpeed};
+
+/// A captured frame.
+pub enum CapturedImage {
+    /// 8-bit sRGB pixels, row by row.
+    Rgba {
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    },
+    /// A frame read from the canvas, already encoded.
+    Png(Vec<u8>),
+}
+
+impl CapturedImage {
+    pub fn to_png(&self) -> Result<Vec<u8>, Error> {
+        use image::ImageEncoder;
+        match self {
+            CapturedImage::Rgba {
+                width,
+                height,
+                data,
+            } => {
+                let mut png = Vec::new();
+                image::codecs::png::PngEncoder::new(&mut png).write_image(
+                    data,
+                    *width,
+                    *height,
+                    image::ColorType::Rgba8,
+                )?;
+                Ok(png)
+            }
+            CapturedImage::Png(png) => Ok(png.clone()),
+        }
+    }
+}
+
+/// How recordings are saved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordingFormat {
+    /// Numbered PNG files, zipped for download in the browser.
+    PngSequence,
+    /// An mp4 video, encoded by piping the frames to `ffmpeg`.
+    #[cfg(not(target_arch = "wasm32"))]
+    Mp4,
+}
+
+impl RecordingFormat {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub const ALL: &'static [RecordingFormat] =
+        &[RecordingFormat::PngSequence, RecordingFormat::Mp4];
+    #[cfg(target_arch = "wasm32")]
+    pub const ALL: &'static [RecordingFormat] = &[RecordingFormat::PngSequence];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RecordingFormat::PngSequence => "PNG sequence",
+            #[cfg(not(target_arch = "wasm32"))]
+            RecordingFormat::Mp4 => "mp4 video",
+        }
+    }
+}
+
+/// Where the frames of a recording go.
+enum Sink {
+    #[cfg(not(target_arch = "wasm32"))]
+    Directory(PathBuf),
+    /// The `ffmpeg` process is started by the first frame, which sets the size of the video.
+    #[cfg(not(target_arch = "wasm32"))]
+    Ffmpeg {
+        path: PathBuf,
+        frame_rate: u32,
+        process: Option<std::process::Child>,
+    },
+    #[cfg(target_arch = "wasm32")]
+    Zip(zip::ZipWriter<std::io::Cursor<Vec<u8>>>),
+}
+
+impl Sink {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn new(format: RecordingFormat, directory: &Path, frame_rate: u32) -> Result<Self, Error> {
+        std::fs::create_dir_all(directory)?;
+        Ok(match format {
+            RecordingFormat::PngSequence => {
+                let path = unused_path(directory, "recording", "");
+                std::fs::create_dir(&path)?;
+                Sink::Directory(path)
+            }
+            RecordingFormat::Mp4 => Sink::Ffmpeg {
+                path: unused_path(directory, "recording", "mp4"),
+                frame_rate,
+                process: None,
+            },
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn new(_format: RecordingFormat, _directory: &Path, _frame_rate: u32) -> Result<Self, Error> {
+        Ok(Sink::Zip(zip::ZipWriter::new(std::io::Cursor::new(
+            Vec::new(),
+        ))))
+    }
+
+    fn write(&mut self, index: usize, image: &CapturedImage) -> Result<(), Error> {
+        let name = format!("frame_{:05}.png", index);
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            Sink::Directory(path) => Ok(std::fs::write(path.join(name), image.to_png()?)?),
+            #[cfg(not(target_arch = "wasm32"))]
+            Sink::Ffmpeg {
+                path,
+                frame_rate,
+                process,
+            } => {
+                let (width, height, data) = match image {
+                    CapturedImage::Rgba {
+                        width,
+                        height,
+                        data,
+                    } => (width, height, data),
+                    CapturedImage::Png(_) => {
+                        return Err(Error::msg("Videos can only be made of the 3D view."))
+                    }
+                };
+                if process.is_none() {
+                    *process = Some(
+                        std::process::Command::new("ffmpeg")
+                            .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+                            .args(["-pix_fmt", "rgba", "-s"])
+                            .arg(format!("{}x{}", width, height))
+                            .arg("-r")
+                            .arg(frame_rate.to_string())
+                            .args(["-i", "-"])
+                            // H.264 in yuv420p needs even dimensions.
+                            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+                            .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                            .arg(&*path)
+                            .stdin(std::process::Stdio::piped())
+                            .spawn()
+                            .map_err(|e| Error::msg(format!("Could not run ffmpeg: {}", e)))?,
+                    );
+                }
+                let stdin = process
+                    .as_mut()
+                    .and_then(|process| process.stdin.as_mut())
+                    .ok_or_else(|| Error::msg("ffmpeg has stopped."))?;
+                Ok(stdin.write_all(data)?)
+            }
+            #[cfg(target_arch = "wasm32")]
+            Sink::Zip(zip) => {
+                let options = zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored);
+                zip.start_file(name, options)?;
+                Ok(zip.write_all(&image.to_png()?)?)
+            }
+        }
+    }
+
+    /// Finishes the recording, and returns a description of where it was saved.
+    fn finish(self) -> Result<String, Error> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            Sink::Directory(path) => Ok(path.display().to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Sink::Ffmpeg { path, process, .. } => {
+                if let Some(mut process) = process {
+                    // Closing stdin tells ffmpeg that the video has ended.
+                    drop(process.stdin.take());
+                    let status = process.wait()?;
+                    if !status.success() {
+                        return Err(Error::msg(format!("ffmpeg exited with {}", status)));
+                    }
+                }
+                Ok(path.display().to_string())
+            }
+            #[cfg(target_arch = "wasm32")]
+            Sink::Zip(mut zip) => {
+                let bytes = zip.finish()?.into_inner();
+                download("recording.zip", &bytes, "application/zip")?;
+                Ok("recording.zip".to_string())
+            }
+        }
+    }
+}
+
+/// The first `<name>_<n>.<extension>` in the directory that does not exist yet.
+#[cfg(not(target_arch = "wasm32"))]
+fn unused_path(directory: &Path, name: &str, extension: &str) -> PathBuf {
+    (0..)
+        .map(|n| {
+            directory
+                .join(format!("{}_{:03}", name, n))
+                .with_extension(extension)
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Saves a screenshot to the path, or a new file in the capture directory, or downloads it in the
+/// browser.
+fn save_screenshot(
+    directory: &Path,
+    path: Option<&Path>,
+    image: &CapturedImage,
+) -> Result<String, Error> {
+    let png = image.to_png()?;
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                std::fs::create_dir_all(directory)?;
+                unused_path(directory, "screenshot", "png")
+            }
+        };
+        std::fs::write(&path, png)?;
+        Ok(path.display().to_string())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (directory, path);
+        download("screenshot.png", &png, "image/png")?;
+        Ok("screenshot.png".to_string())
+    }
+}
+
+/// Offers the bytes as a file download in the browser.
+#[cfg(target_arch = "wasm32")]
+fn download(name: &str, bytes: &[u8], mime: &str) -> Result<(), Error> {
+    use crate::presets::js_error;
+    use wasm_bindgen::JsCast;
+
+    let parts = js_sys::Array::new();
+    parts.push(&js_sys::Uint8Array::from(bytes));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_(mime);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| Error::msg("The page is not available."))?;
+    let link: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| Error::msg("Could not create a download link."))?;
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+    web_sys::Url::revoke_object_url(&url).map_err(js_error)
+}
+
+/// Reads the page's canvas, including the UI, as a PNG.
+#[cfg(target_arch = "wasm32")]
+fn capture_canvas() -> Option<CapturedImage> {
+    use wasm_bindgen::JsCast;
+
+    let canvas: web_sys::HtmlCanvasElement = web_sys::window()?
+        .document()?
+        .query_selector("canvas")
+        .ok()??
+        .dyn_into()
+        .ok()?;
+    let url = canvas.to_data_url_with_type("image/png").ok()?;
+    decode_base64(url.split_once(',')?.1).map(CapturedImage::Png)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        buffer = buffer << 6 | value(c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+enum Purpose {
+    /// A screenshot, saved to the path if there is one.
+    Screenshot(Option<PathBuf>),
+    Frame,
+}
+
+/// What a capture is read from.
+#[derive(Clone)]
+enum Source {
+    /// An image rendered by a capture camera.
+    Scene(Handle<Image>),
+    /// The canvas of the page, including the UI.
+    Window,
+}
+
+struct PendingCapture {
+    id: u64,
+    purpose: Purpose,
+    source: Source,
+    camera: Option<Entity>,
+    /// Frames since the capture was requested.
+    frames: u32,
+    /// Whether a copy has been requested from the render world, and not yet answered.
+    copying: bool,
+}
+
+/// Frames to render a new capture camera before its image is copied.
+const FRAMES_BEFORE_COPY: u32 = 2;
+/// Frames after which a capture that could not be copied is abandoned.
+const FRAMES_BEFORE_GIVING_UP: u32 = 60;
+
+struct Recording {
+    sink: Sink,
+    /// Simulation steps between frames.
+    steps: u32,
+    frames: usize,
+    /// Whether a frame has been requested and not yet captured.
+    capturing: bool,
+    was_paused: bool,
+}
+
+/// Settings and state of screenshots and recordings.
+#[derive(Resource)]
+pub struct Captures {
+    /// Width of captures of the 3D view, in pixels.
+    pub width: u32,
+    /// Height of captures of the 3D view, in pixels.
+    pub height: u32,
+    /// Capture the whole page, including the UI, at the resolution of the page.
+    ///
+    /// Only supported in the browser.
+    pub include_ui: bool,
+    /// Leave the background of the 3D view transparent, rather than the clear color.
+    pub transparent: bool,
+    /// Simulated time between recorded frames, in units of s.
+    pub interval: f64,
+    pub format: RecordingFormat,
+    /// Frame rate of encoded videos, in frames per second.
+    pub frame_rate: u32,
+    /// Directory that captures are written to natively.
+    pub directory: PathBuf,
+    /// Description of the last capture saved, or of the last error.
+    pub status: String,
+    screenshots: Vec<Option<PathBuf>>,
+    start_requested: bool,
+    stop_requested: bool,
+    pending: Vec<PendingCapture>,
+    recording: Option<Recording>,
+    next_id: u64,
+    receiver: Mutex<Receiver<(u64, Option<CapturedImage>)>>,
+}
+
+impl Captures {
+    fn new(receiver: Receiver<(u64, Option<CapturedImage>)>) -> Self {
+        Captures {
+            width: 1920,
+            height: 1080,
+            include_ui: false,
+            transparent: false,
+            interval: 1e-4,
+            format: RecordingFormat::PngSequence,
+            frame_rate: 30,
+            directory: PathBuf::from("captures"),
+            status: String::new(),
+            screenshots: Vec::new(),
+            start_requested: false,
+            stop_requested: false,
+            pending: Vec::new(),
+            recording: None,
+            next_id: 0,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Saves the next frame as a PNG.
+    pub fn screenshot(&mut self) {
+        self.screenshots.push(None);
+    }
+
+    /// Saves the next frame as a PNG at the path.
+    pub fn screenshot_to(&mut self, path: impl Into<PathBuf>) {
+        self.screenshots.push(Some(path.into()));
+    }
+
+    /// Whether every requested screenshot has been saved, or has failed.
+    pub fn screenshots_finished(&self) -> bool {
+        self.screenshots.is_empty()
+            && !self
+                .pending
+                .iter()
+                .any(|pending| matches!(pending.purpose, Purpose::Screenshot(_)))
+    }
+
+    /// Starts recording a frame every [Captures::interval] of simulated time.
+    pub fn start_recording(&mut self) {
+        self.start_requested = true;
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.stop_requested = true;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some() || self.start_requested
+    }
+
+    /// Number of frames recorded so far.
+    pub fn recorded_frames(&self) -> usize {
+        self.recording
+            .as_ref()
+            .map_or(0, |recording| recording.frames)
+    }
+
+    fn stop(&mut self, speed: &mut SimulationSpeed) {
+        if let Some(recording) = self.recording.take() {
+            let frames = recording.frames;
+            self.status = match recording.sink.finish() {
+                Ok(path) => format!("Recorded {} frames to {}.", frames, path),
+                Err(e) => format!("Could not finish recording: {}", e),
+            };
+            info!("{}", self.status);
+            if !recording.was_paused {
+                speed.resume();
+            }
+        }
+    }
+}
+
+/// Captures waiting to be copied from the render world, extracted each frame.
+#[derive(Resource, Clone, Default, ExtractResource)]
+struct CopyRequests(Vec<(u64, Source)>);
+
+/// Sends captured images from the render world back to the main world.
+///
+/// `None` asks for the capture to be retried, when the image was not ready to be copied.
+#[derive(Resource)]
+struct CaptureSender(Mutex<Sender<(u64, Option<CapturedImage>)>>);
+
+struct CopyBuffer {
+    id: u64,
+    buffer: Buffer,
+    texture: Handle<Image>,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    /// Whether the buffer could be mapped, once it has been copied into and mapping has started.
+    mapped: Option<Mutex<Receiver<bool>>>,
+}
+
+/// Buffers that requested images are copied into, kept until they have been read back.
+#[derive(Resource, Default)]
+struct CopyBuffers(Vec<CopyBuffer>);
+
+/// Renders a capture of the 3D view, from the same viewpoint as the camera.
+fn spawn_capture_camera(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    captures: &Captures,
+    camera: (&Transform, &Projection),
+) -> (Entity, Handle<Image>) {
+    let (width, height) = (captures.width.max(1), captures.height.max(1));
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("capture"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    let handle = images.add(image);
+
+    let (transform, projection) = camera;
+    let mut projection = projection.clone();
+    if let Projection::Orthographic(orthographic) = &mut projection {
+        if let ScalingMode::WindowSize = orthographic.scaling_mode {
+            // Keep the visible height of the scene, rather than the size of its pixels.
+            orthographic.scale *= (orthographic.top - orthographic.bottom) / height as f32;
+        }
+    }
+    let entity = commands
+        .spawn(Camera3dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(handle.clone()),
+                priority: -1,
+                ..default()
+            },
+            camera_3d: Camera3d {
+                clear_color: if captures.transparent {
+                    ClearColorConfig::Custom(Color::NONE)
+                } else {
+                    ClearColorConfig::Default
+                },
+                ..default()
+            },
+            projection,
+            transform: *transform,
+            ..default()
+        })
+        .insert(UiCameraConfig { show_ui: false })
+        .id();
+    (entity, handle)
+}
+
+fn receive_captures(
+    mut commands: Commands,
+    mut captures: ResMut<Captures>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    let received: Vec<_> = captures.receiver.lock().unwrap().try_iter().collect();
+    for (id, image) in received {
+        let index = match captures.pending.iter().position(|pending| pending.id == id) {
+            Some(index) => index,
+            None => continue,
+        };
+        let image = match image {
+            Some(image) => image,
+            None => {
+                captures.pending[index].copying = false;
+                continue;
+            }
+        };
+        let pending = captures.pending.remove(index);
+        if let Some(camera) = pending.camera {
+            commands.entity(camera).despawn();
+        }
+        match pending.purpose {
+            Purpose::Screenshot(path) => {
+                captures.status =
+                    match save_screenshot(&captures.directory, path.as_deref(), &image) {
+                        Ok(path) => format!("Saved {}.", path),
+                        Err(e) => format!("Could not save screenshot: {}", e),
+                    };
+                info!("{}", captures.status);
+            }
+            Purpose::Frame => {
+                let recording = match captures.recording.as_mut() {
+                    Some(recording) => recording,
+                    None => continue,
+                };
+                match recording.sink.write(recording.frames, &image) {
+                    Ok(()) => {
+                        recording.frames += 1;
+                        recording.capturing = false;
+                        speed.step(recording.steps);
+                    }
+                    Err(e) => {
+                        captures.stop(&mut speed);
+                        captures.status = format!("Could not record frame: {}", e);
+                        error!("{}", captures.status);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn update_captures(
+    mut commands: Commands,
+    mut captures: ResMut<Captures>,
+    mut copies: ResMut<CopyRequests>,
+    mut images: ResMut<Assets<Image>>,
+    mut speed: ResMut<SimulationSpeed>,
+    timestep: Res<Timestep>,
+    cameras: Query<(&Transform, &Projection), With<DemoCamera>>,
+) {
+    let captures = &mut *captures;
+    if captures.stop_requested {
+        captures.stop_requested = false;
+        captures.start_requested = false;
+        captures.stop(&mut speed);
+    }
+    if captures.start_requested && captures.recording.is_none() {
+        captures.start_requested = false;
+        match Sink::new(captures.format, &captures.directory, captures.frame_rate) {
+            Ok(sink) => {
+                captures.recording = Some(Recording {
+                    sink,
+                    steps: (captures.interval / timestep.delta).round().max(1.0) as u32,
+                    frames: 0,
+                    capturing: false,
+                    was_paused: speed.is_paused(),
+                });
+                speed.pause();
+                captures.status = "Recording...".to_string();
+            }
+            Err(e) => captures.status = format!("Could not start recording: {}", e),
+        }
+    }
+
+    let mut requests = Vec::new();
+    requests.extend(captures.screenshots.drain(..).map(Purpose::Screenshot));
+    if let Some(recording) = captures.recording.as_mut() {
+        if !recording.capturing && !speed.is_stepping() {
+            recording.capturing = true;
+            requests.push(Purpose::Frame);
+        }
+    }
+    for purpose in requests {
+        let id = captures.next_id;
+        captures.next_id += 1;
+        let capture = if captures.include_ui && cfg!(target_arch = "wasm32") {
+            PendingCapture {
+                id,
+                purpose,
+                source: Source::Window,
+                camera: None,
+                // The canvas is read after this frame has been drawn.
+                frames: FRAMES_BEFORE_COPY,
+                copying: false,
+            }
+        } else {
+            let camera = match cameras.iter().next() {
+                Some(camera) => camera,
+                None => {
+                    captures.status = "There is no camera to capture.".to_string();
+                    continue;
+                }
+            };
+            let (camera, image) =
+                spawn_capture_camera(&mut commands, &mut images, captures, camera);
+            PendingCapture {
+                id,
+                purpose,
+                source: Source::Scene(image),
+                camera: Some(camera),
+                frames: 0,
+                copying: false,
+            }
+        };
+        captures.pending.push(capture);
+    }
+
+    copies.0.clear();
+    let mut abandoned = Vec::new();
+    for pending in captures.pending.iter_mut() {
+        if pending.frames >= FRAMES_BEFORE_COPY + FRAMES_BEFORE_GIVING_UP {
+            abandoned.push(pending.id);
+        } else if pending.frames >= FRAMES_BEFORE_COPY && !pending.copying {
+            pending.copying = true;
+            copies.0.push((pending.id, pending.source.clone()));
+        }
+        pending.frames += 1;
+    }
+    for id in abandoned {
+        warn!("Gave up on capture {}, which could not be copied.", id);
+        if let Some(index) = captures.pending.iter().position(|pending| pending.id == id) {
+            let pending = captures.pending.remove(index);
+            if let Some(camera) = pending.camera {
+                commands.entity(camera).despawn();
+            }
+            if let Purpose::Frame = pending.purpose {
+                if let Some(recording) = captures.recording.as_mut() {
+                    recording.capturing = false;
+                }
+            }
+        }
+    }
+}
+
+/// Takes a screenshot when F12 is pressed.
+fn screenshot_shortcut(keys: Res<Input<KeyCode>>, mut captures: ResMut<Captures>) {
+    if keys.just_pressed(KeyCode::F12) {
+        captures.screenshot();
+    }
+}
+
+/// Creates buffers that the requested images can be copied into.
+fn prepare_copy_buffers(
+    requests: Res<CopyRequests>,
+    images: Res<RenderAssets<Image>>,
+    device: Res<RenderDevice>,
+    mut buffers: ResMut<CopyBuffers>,
+    sender: Res<CaptureSender>,
+) {
+    for (id, source) in requests.0.iter() {
+        let texture = match source {
+            Source::Scene(texture) => texture,
+            Source::Window => continue,
+        };
+        let image = match images.get(texture) {
+            Some(image) => image,
+            None => {
+                let _ = sender.0.lock().unwrap().send((*id, None));
+                continue;
+            }
+        };
+        let (width, height) = (image.size.x as u32, image.size.y as u32);
+        let padded_bytes_per_row = RenderDevice::align_copy_bytes_per_row(width as usize * 4);
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("capture"),
+            size: (padded_bytes_per_row * height as usize) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        buffers.0.push(CopyBuffer {
+            id: *id,
+            buffer,
+            texture: texture.clone(),
+            width,
+            height,
+            padded_bytes_per_row: padded_bytes_per_row as u32,
+            mapped: None,
+        });
+    }
+}
+
+/// Copies the requested images into their buffers, once the cameras have rendered.
+struct CopyNode;
+
+impl Node for CopyNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let images = world.resource::<RenderAssets<Image>>();
+        for copy in world.resource::<CopyBuffers>().0.iter() {
+            if copy.mapped.is_some() {
+                continue;
+            }
+            let image = match images.get(&copy.texture) {
+                Some(image) => image,
+                None => continue,
+            };
+            render_context.command_encoder.copy_texture_to_buffer(
+                image.texture.as_image_copy(),
+                ImageCopyBuffer {
+                    buffer: &copy.buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(copy.padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                Extent3d {
+                    width: copy.width,
+                    height: copy.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reads the copied images back from the GPU, and sends them to the main world.
+///
+/// Natively the buffers are mapped straight away. WebGL cannot wait for the GPU, so in the browser
+/// a buffer may take a few frames to be mapped.
+fn read_copy_buffers(
+    requests: Res<CopyRequests>,
+    mut buffers: ResMut<CopyBuffers>,
+    device: Res<RenderDevice>,
+    sender: Res<CaptureSender>,
+) {
+    for copy in buffers.0.iter_mut().filter(|copy| copy.mapped.is_none()) {
+        let (mapped_sender, mapped) = channel();
+        device.map_buffer(&copy.buffer.slice(..), MapMode::Read, move |result| {
+            let _ = mapped_sender.send(result.is_ok());
+        });
+        copy.mapped = Some(Mutex::new(mapped));
+    }
+    device.poll(wgpu::Maintain::Wait);
+
+    let sender = sender.0.lock().unwrap();
+    buffers.0.retain(|copy| {
+        let mapped = match &copy.mapped {
+            Some(mapped) => mapped.lock().unwrap().try_recv(),
+            None => return true,
+        };
+        let image = match mapped {
+            Ok(true) => {
+                let row = copy.width as usize * 4;
+                let mut data = Vec::with_capacity(row * copy.height as usize);
+                for padded_row in copy
+                    .buffer
+                    .slice(..)
+                    .get_mapped_range()
+                    .chunks(copy.padded_bytes_per_row as usize)
+                {
+                    data.extend_from_slice(&padded_row[..row]);
+                }
+                copy.buffer.unmap();
+                Some(CapturedImage::Rgba {
+                    width: copy.width,
+                    height: copy.height,
+                    data,
+                })
+            }
+            Ok(false) | Err(TryRecvError::Disconnected) => None,
+            Err(TryRecvError::Empty) => return true,
+        };
+        let _ = sender.send((copy.id, image));
+        false
+    });
+    for (id, source) in requests.0.iter() {
+        if let Source::Window = source {
+            #[cfg(target_arch = "wasm32")]
+            let image = capture_canvas();
+            #[cfg(not(target_arch = "wasm32"))]
+            let image = None;
+            let _ = sender.send((*id, image));
+        }
+    }
+}
+
+/// Widgets to take screenshots and record the demo.
+pub fn capture_ui(ui: &mut egui::Ui, captures: &mut Captures) {
+    let recording = captures.is_recording();
+    ui.add_enabled_ui(!recording, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Resolution");
+            ui.add(egui::DragValue::new(&mut captures.width).clamp_range(16..=8192));
+            ui.label("×");
+            ui.add(egui::DragValue::new(&mut captures.height).clamp_range(16..=8192));
+        });
+        ui.checkbox(&mut captures.transparent, "Transparent background");
+        ui.add_enabled(
+            cfg!(target_arch = "wasm32"),
+            egui::Checkbox::new(&mut captures.include_ui, "Include UI"),
+        )
+        .on_hover_text(
+            "Captures the whole page, at its own resolution. Only available in the browser.",
+        );
+        ui.horizontal(|ui| {
+            ui.label("Record every");
+            ui.add(
+                egui::DragValue::new(&mut captures.interval)
+                    .speed(1e-5)
+                    .clamp_range(1e-6..=1e-1)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("recording_format")
+                .selected_text(captures.format.label())
+                .show_ui(ui, |ui| {
+                    for format in RecordingFormat::ALL {
+                        ui.selectable_value(&mut captures.format, *format, format.label());
+                    }
+                });
+            #[cfg(not(target_arch = "wasm32"))]
+            if captures.format == RecordingFormat::Mp4 {
+                ui.add(
+                    egui::DragValue::new(&mut captures.frame_rate)
+                        .clamp_range(1..=120)
+                        .suffix(" fps"),
+                );
+            }
+        });
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Screenshot").on_hover_text("F12").clicked() {
+            captures.screenshot();
+        }
+        if recording {
+            let label = format!("Stop recording ({} frames)", captures.recorded_frames());
+            if ui.button(label).clicked() {
+                captures.stop_recording();
+            }
+        } else if ui.button("Start recording").clicked() {
+            captures.start_recording();
+        }
+    });
+    if !captures.status.is_empty() {
+        ui.label(&captures.status);
+    }
+}
+
+/// Adds screenshots and recordings of the 3D view, controlled by the [Captures] resource.
+///
+/// Must be added after the `DefaultPlugins`, which set up rendering.
+pub struct CapturePlugin;
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = channel();
+        app.insert_resource(Captures::new(receiver));
+        app.init_resource::<CopyRequests>();
+        app.add_plugin(ExtractResourcePlugin::<CopyRequests>::default());
+        app.add_system_to_stage(PresentationStage, receive_captures);
+        app.add_system_to_stage(PresentationStage, update_captures.after(receive_captures));
+        app.add_system_to_stage(
+            PresentationStage,
+            screenshot_shortcut.before(update_captures),
+        );
+
+        let render_app = match app.get_sub_app_mut(RenderApp) {
+            Ok(render_app) => render_app,
+            Err(_) => return,
+        };
+        render_app.insert_resource(CaptureSender(Mutex::new(sender)));
+        render_app.init_resource::<CopyBuffers>();
+        render_app.add_system_to_stage(
+            RenderStage::Prepare,
+            prepare_copy_buffers.after(PrepareAssetLabel::AssetPrepare),
+        );
+        render_app.add_system_to_stage(RenderStage::Cleanup, read_copy_buffers);
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node("capture_copy", CopyNode);
+        graph.add_node_edge(CAMERA_DRIVER, "capture_copy").unwrap();
+    }
+}
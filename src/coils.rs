@@ -0,0 +1,728 @@
+//! Magnetic fields of coils and permanent magnets, calculated using the Biot-Savart law.
+//!
+//! Each [FieldSource] is broken down into straight [CurrentSegment]s, for which the Biot-Savart
+//! integral has an exact solution. A [FieldDefinition] combines a list of sources with the grid on
+//! which the field is sampled, and is used by the `create_field` binary to write
+//! [MagneticFieldGrid]s:
+//!
+//! cargo run --release --bin create_field -- assets/fields/slower.coils.json slower.field.json
+
+use atomecs::bevy_bridge::Scale;
+use bevy::asset::{AssetLoader, BoxedFuture, Error, LoadContext, LoadedAsset};
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy_egui::{egui, EguiContext};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::fields::MagneticFieldGrid;
+use crate::geometry::perpendicular_basis;
+use crate::meshes::MeshBuilder;
+use crate::stepping::{add_presentation_stage, PresentationStage};
+
+/// Permeability of free space, in units of T m / A.
+pub const MU0: f64 = 4.0e-7 * std::f64::consts::PI;
+
+/// A straight wire carrying a current.
+#[derive(Clone, Copy, Debug)]
+pub struct CurrentSegment {
+    /// Start of the segment, in units of m.
+    pub start: Vector3<f64>,
+    /// End of the segment, in units of m.
+    pub end: Vector3<f64>,
+    /// Current flowing from start to end, in units of A.
+    pub current: f64,
+}
+
+impl CurrentSegment {
+    /// Magnetic field of the segment at `pos`, in units of T.
+    pub fn field(&self, pos: &Vector3<f64>) -> Vector3<f64> {
+        let a = self.start - pos;
+        let b = self.end - pos;
+        let a_norm = a.norm();
+        let b_norm = b.norm();
+        let denominator = a_norm * b_norm * (a_norm * b_norm + a.dot(&b));
+        // Points on the line of the wire have no well-defined field.
+        if denominator <= f64::EPSILON * a_norm * b_norm * a_norm * b_norm {
+            return Vector3::zeros();
+        }
+        MU0 * self.current / (4.0 * std::f64::consts::PI) * a.cross(&b) * (a_norm + b_norm)
+            / denominator
+    }
+}
+
+/// Current loops and permanent magnets that produce a magnetic field.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum FieldSource {
+    Loop(CircularLoop),
+    Solenoid(Solenoid),
+    Rectangle(RectangularCoil),
+    Magnet(PermanentMagnetBar),
+}
+
+/// A circular coil of wire.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CircularLoop {
+    /// Centre of the loop, in units of m.
+    pub centre: [f64; 3],
+    /// Normal to the plane of the loop. Positive current circulates anticlockwise about the normal.
+    pub normal: [f64; 3],
+    /// Radius of the loop, in units of m.
+    pub radius: f64,
+    /// Number of turns of wire.
+    #[serde(default = "default_turns")]
+    pub turns: f64,
+    /// Current in each turn, in units of A.
+    pub current: f64,
+}
+
+/// A solenoid made of sections of equal length, each with its own number of turns.
+///
+/// A negative number of turns corresponds to a section wound in the opposite sense.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Solenoid {
+    /// Centre of the first end of the solenoid, in units of m.
+    pub start: [f64; 3],
+    /// Direction of the solenoid axis, pointing from the first end into the solenoid.
+    pub axis: [f64; 3],
+    /// Radius of the windings, in units of m.
+    pub radius: f64,
+    /// Length of each section, in units of m.
+    pub section_length: f64,
+    /// Number of turns in each section.
+    pub turns: Vec<f64>,
+    /// Current in each turn, in units of A.
+    pub current: f64,
+}
+
+/// A rectangular coil of wire.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RectangularCoil {
+    /// Centre of the coil, in units of m.
+    pub centre: [f64; 3],
+    /// Normal to the plane of the coil. Positive current circulates anticlockwise about the normal.
+    pub normal: [f64; 3],
+    /// Direction along which the coil has size `width`. Must be perpendicular to `normal`.
+    pub width_axis: [f64; 3],
+    /// Width of the coil, in units of m.
+    pub width: f64,
+    /// Height of the coil, in units of m.
+    pub height: f64,
+    /// Number of turns of wire.
+    #[serde(default = "default_turns")]
+    pub turns: f64,
+    /// Current in each turn, in units of A.
+    pub current: f64,
+}
+
+/// A uniformly magnetised cuboid.
+///
+/// The magnet is modelled by its equivalent surface current, which flows around the faces parallel
+/// to the magnetisation.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PermanentMagnetBar {
+    /// Centre of the magnet, in units of m.
+    pub centre: [f64; 3],
+    /// Direction of magnetisation.
+    pub magnetisation_axis: [f64; 3],
+    /// Direction along which the magnet has size `width`. Must be perpendicular to `magnetisation_axis`.
+    pub width_axis: [f64; 3],
+    /// Size of the magnet along the magnetisation axis, in units of m.
+    pub length: f64,
+    /// Width of the magnet, in units of m.
+    pub width: f64,
+    /// Height of the magnet, in units of m.
+    pub height: f64,
+    /// Remanent field of the magnet material, in units of T.
+    pub remanence: f64,
+}
+
+fn default_turns() -> f64 {
+    1.0
+}
+
+/// Number of straight segments used to approximate a circle.
+const SEGMENTS_PER_LOOP: usize = 128;
+
+/// Number of current loops used to approximate each solenoid section.
+const LOOPS_PER_SECTION: usize = 8;
+
+/// Number of current loops used to approximate the surface current of a magnet.
+const LOOPS_PER_MAGNET: usize = 32;
+
+fn vector(v: [f64; 3]) -> Vector3<f64> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn circle_segments(
+    centre: Vector3<f64>,
+    normal: Vector3<f64>,
+    radius: f64,
+    current: f64,
+) -> Vec<CurrentSegment> {
+    let normal = normal.normalize();
+    let (u, v) = perpendicular_basis(&normal);
+    let point = |i: usize| {
+        let theta = 2.0 * std::f64::consts::PI * i as f64 / SEGMENTS_PER_LOOP as f64;
+        centre + radius * (theta.cos() * u + theta.sin() * v)
+    };
+    (0..SEGMENTS_PER_LOOP)
+        .map(|i| CurrentSegment {
+            start: point(i),
+            end: point(i + 1),
+            current,
+        })
+        .collect()
+}
+
+fn rectangle_segments(
+    centre: Vector3<f64>,
+    normal: Vector3<f64>,
+    width_axis: Vector3<f64>,
+    width: f64,
+    height: f64,
+    current: f64,
+) -> Vec<CurrentSegment> {
+    let normal = normal.normalize();
+    let u = width_axis.normalize() * width / 2.0;
+    let v = normal.cross(&width_axis.normalize()) * height / 2.0;
+    let corners = [
+        centre + u + v,
+        centre - u + v,
+        centre - u - v,
+        centre + u - v,
+    ];
+    (0..4)
+        .map(|i| CurrentSegment {
+            start: corners[i],
+            end: corners[(i + 1) % 4],
+            current,
+        })
+        .collect()
+}
+
+impl FieldSource {
+    /// Straight current segments that approximate the source.
+    pub fn segments(&self) -> Vec<CurrentSegment> {
+        match self {
+            FieldSource::Loop(coil) => circle_segments(
+                vector(coil.centre),
+                vector(coil.normal),
+                coil.radius,
+                coil.turns * coil.current,
+            ),
+            FieldSource::Solenoid(solenoid) => {
+                let axis = vector(solenoid.axis).normalize();
+                let step = solenoid.section_length / LOOPS_PER_SECTION as f64;
+                let mut segments = Vec::new();
+                for (i, turns) in solenoid.turns.iter().enumerate() {
+                    for j in 0..LOOPS_PER_SECTION {
+                        let distance = i as f64 * solenoid.section_length + (j as f64 + 0.5) * step;
+                        segments.extend(circle_segments(
+                            vector(solenoid.start) + distance * axis,
+                            axis,
+                            solenoid.radius,
+                            turns * solenoid.current / LOOPS_PER_SECTION as f64,
+                        ));
+                    }
+                }
+                segments
+            }
+            FieldSource::Rectangle(coil) => rectangle_segments(
+                vector(coil.centre),
+                vector(coil.normal),
+                vector(coil.width_axis),
+                coil.width,
+                coil.height,
+                coil.turns * coil.current,
+            ),
+            FieldSource::Magnet(magnet) => {
+                let axis = vector(magnet.magnetisation_axis).normalize();
+                let step = magnet.length / LOOPS_PER_MAGNET as f64;
+                // Surface current density is M = B_r / mu0, so each slice carries M * step.
+                let current = magnet.remanence / MU0 * step;
+                let mut segments = Vec::new();
+                for i in 0..LOOPS_PER_MAGNET {
+                    let distance = -magnet.length / 2.0 + (i as f64 + 0.5) * step;
+                    segments.extend(rectangle_segments(
+                        vector(magnet.centre) + distance * axis,
+                        axis,
+                        vector(magnet.width_axis),
+                        magnet.width,
+                        magnet.height,
+                        current,
+                    ));
+                }
+                segments
+            }
+        }
+    }
+}
+
+/// Total magnetic field of a collection of segments at `pos`, in units of T.
+pub fn field_of_segments(segments: &[CurrentSegment], pos: &Vector3<f64>) -> Vector3<f64> {
+    segments.iter().fold(Vector3::zeros(), |field, segment| {
+        field + segment.field(pos)
+    })
+}
+
+/// Regular grid on which a field is sampled.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GridSpecification {
+    /// Size of the grid, in units of m.
+    pub extent_spatial: [f64; 3],
+    /// Number of points along each axis.
+    pub extent_cells: [i32; 3],
+    /// Position of the grid centre, in units of m.
+    pub position: [f64; 3],
+}
+
+impl GridSpecification {
+    /// Position of the grid point with the given indices.
+    ///
+    /// Points span the full extent of the grid, including both edges. Axes with a single point are
+    /// sampled at the grid centre.
+    pub fn point(&self, index: [i32; 3]) -> Vector3<f64> {
+        let mut pos = vector(self.position);
+        for axis in 0..3 {
+            let n = self.extent_cells[axis];
+            if n > 1 {
+                pos[axis] +=
+                    self.extent_spatial[axis] * (index[axis] as f64 / (n - 1) as f64 - 0.5);
+            }
+        }
+        pos
+    }
+}
+
+/// Sources of magnetic field and the grid on which the field is calculated.
+#[derive(Serialize, Deserialize, Clone, TypeUuid)]
+#[uuid = "0d9c6b2e-58a4-4f7e-a1c3-2b8e9f4d7a61"]
+pub struct FieldDefinition {
+    pub grid: GridSpecification,
+    pub sources: Vec<FieldSource>,
+}
+
+impl FieldDefinition {
+    /// Calculates the field of all sources on the grid.
+    pub fn calculate(&self) -> MagneticFieldGrid {
+        let segments: Vec<CurrentSegment> = self
+            .sources
+            .iter()
+            .flat_map(|source| source.segments())
+            .collect();
+        let [nx, ny, nz] = self.grid.extent_cells;
+        let mut grid = Vec::with_capacity((nx * ny * nz).max(0) as usize);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let pos = self.grid.point([i, j, k]);
+                    grid.push(field_of_segments(&segments, &pos));
+                }
+            }
+        }
+        MagneticFieldGrid {
+            extent_spatial: vector(self.grid.extent_spatial),
+            extent_cells: Vector3::new(nx, ny, nz),
+            position: vector(self.grid.position),
+            grid,
+        }
+    }
+}
+
+/// Coils and magnets that are simulated and rendered together.
+///
+/// The field of all sources is calculated on the grid whenever the assembly changes, and inserted
+/// on the same entity as a `PrecalculatedMagneticFieldGrid`. The sources are rendered by child
+/// entities: loops as tubes, solenoids as helices and magnets as boxes, with arrows that show the
+/// direction of the current or magnetisation. The entity requires a [SpatialBundle] for its children
+/// to be visible.
+#[derive(Component, Clone)]
+pub struct CoilAssembly {
+    pub definition: FieldDefinition,
+}
+
+#[derive(Resource)]
+pub struct CoilConfiguration {
+    pub show_coils: bool,
+}
+impl Default for CoilConfiguration {
+    fn default() -> Self {
+        CoilConfiguration { show_coils: true }
+    }
+}
+
+/// Number of turns of a solenoid represented by each turn of the rendered helix.
+const TURNS_PER_RENDERED_TURN: f64 = 16.0;
+
+/// Maximum number of turns of the rendered helix in each solenoid section.
+const MAX_RENDERED_TURNS: f64 = 12.0;
+
+fn render_vector(v: Vector3<f64>, scale: f64) -> Vec3 {
+    Vec3::new(
+        (v[0] * scale) as f32,
+        (v[1] * scale) as f32,
+        (v[2] * scale) as f32,
+    )
+}
+
+fn circle_path(centre: Vec3, u: Vec3, v: Vec3, radius: f32) -> Vec<Vec3> {
+    (0..=SEGMENTS_PER_LOOP)
+        .map(|i| {
+            let theta = 2.0 * std::f32::consts::PI * i as f32 / SEGMENTS_PER_LOOP as f32;
+            centre + radius * (theta.cos() * u + theta.sin() * v)
+        })
+        .collect()
+}
+
+fn f32_basis(normal: &Vector3<f64>) -> (Vec3, Vec3, Vec3) {
+    let normal = normal.normalize();
+    let (u, v) = perpendicular_basis(&normal);
+    (
+        render_vector(normal, 1.0),
+        render_vector(u, 1.0),
+        render_vector(v, 1.0),
+    )
+}
+
+impl FieldSource {
+    /// Builds meshes of the wires (or magnet) and of the arrows showing the direction of current.
+    fn build_meshes(&self, scale: f64, wire: &mut MeshBuilder, arrows: &mut MeshBuilder) {
+        let s = scale as f32;
+        match self {
+            FieldSource::Loop(coil) => {
+                let (normal, u, v) = f32_basis(&vector(coil.normal));
+                let centre = render_vector(vector(coil.centre), scale);
+                let radius = coil.radius as f32 * s;
+                wire.tube(&circle_path(centre, u, v, radius), 0.05 * radius);
+                let sign = (coil.turns * coil.current).signum() as f32;
+                arrows.arrow(
+                    centre + radius * u + 0.15 * radius * normal,
+                    sign * v,
+                    0.5 * radius,
+                    0.03 * radius,
+                );
+            }
+            FieldSource::Solenoid(solenoid) => {
+                let (axis, u, v) = f32_basis(&vector(solenoid.axis));
+                let start = render_vector(vector(solenoid.start), scale);
+                let radius = solenoid.radius as f32 * s;
+                let length = solenoid.section_length as f32 * s;
+                let wire_radius = 0.25 * length / MAX_RENDERED_TURNS as f32;
+                for (i, turns) in solenoid.turns.iter().enumerate() {
+                    if turns.abs() < 0.5 {
+                        continue;
+                    }
+                    let rendered_turns = (turns.abs() / TURNS_PER_RENDERED_TURN)
+                        .ceil()
+                        .min(MAX_RENDERED_TURNS) as usize;
+                    let points = rendered_turns * SEGMENTS_PER_LOOP / 4;
+                    let section_start = start + i as f32 * length * axis;
+                    let helix: Vec<Vec3> = (0..=points)
+                        .map(|j| {
+                            let fraction = j as f32 / points as f32;
+                            let theta =
+                                2.0 * std::f32::consts::PI * rendered_turns as f32 * fraction;
+                            section_start
+                                + fraction * length * axis
+                                + radius * (theta.cos() * u + theta.sin() * v)
+                        })
+                        .collect();
+                    wire.tube(&helix, wire_radius);
+                    let sign = (turns * solenoid.current).signum() as f32;
+                    arrows.arrow(
+                        section_start + 0.5 * length * axis + 1.3 * radius * u,
+                        sign * v,
+                        0.6 * radius,
+                        0.04 * radius,
+                    );
+                }
+            }
+            FieldSource::Rectangle(coil) => {
+                let normal = vector(coil.normal).normalize();
+                let width_axis = vector(coil.width_axis).normalize();
+                let u = render_vector(width_axis, 1.0) * 0.5 * coil.width as f32 * s;
+                let v =
+                    render_vector(normal.cross(&width_axis), 1.0) * 0.5 * coil.height as f32 * s;
+                let centre = render_vector(vector(coil.centre), scale);
+                let corners = [
+                    centre + u + v,
+                    centre - u + v,
+                    centre - u - v,
+                    centre + u - v,
+                    centre + u + v,
+                ];
+                let size = u.length().min(v.length());
+                wire.tube(&corners, 0.05 * size);
+                let sign = (coil.turns * coil.current).signum() as f32;
+                arrows.arrow(
+                    centre + v + 0.15 * size * render_vector(normal, 1.0),
+                    -sign * u,
+                    0.5 * size,
+                    0.03 * size,
+                );
+            }
+            FieldSource::Magnet(magnet) => {
+                let axis = vector(magnet.magnetisation_axis).normalize();
+                let width_axis = vector(magnet.width_axis).normalize();
+                let height_axis = axis.cross(&width_axis);
+                let centre = render_vector(vector(magnet.centre), scale);
+                let a = render_vector(axis, 0.5 * magnet.length * scale);
+                let w = render_vector(width_axis, 0.5 * magnet.width * scale);
+                let h = render_vector(height_axis, 0.5 * magnet.height * scale);
+                // Render the magnet as a solid box, with one quad per face.
+                for (normal, p, q) in [(a, w, h), (w, h, a), (h, a, w)] {
+                    for sign in [1.0f32, -1.0] {
+                        let n = sign * normal;
+                        let (p, q) = if sign > 0.0 { (p, q) } else { (q, p) };
+                        let c = centre + n;
+                        wire.quad([c - p - q, c + p - q, c + p + q, c - p + q], n.normalize());
+                    }
+                }
+                let sign = magnet.remanence.signum() as f32;
+                let length = a.length();
+                arrows.arrow(
+                    centre - 1.4 * sign * a,
+                    sign * a,
+                    2.8 * length,
+                    0.05 * w.length().min(h.length()),
+                );
+            }
+        }
+    }
+}
+
+/// Updates the field and meshes of [CoilAssembly]s when they change.
+pub fn update_coil_assemblies(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    scale: Res<Scale>,
+    query: Query<(Entity, &CoilAssembly), Changed<CoilAssembly>>,
+) {
+    for (entity, assembly) in query.iter() {
+        commands
+            .entity(entity)
+            .insert(assembly.definition.calculate().to_component())
+            .despawn_descendants();
+
+        let mut wire = MeshBuilder::default();
+        let mut arrows = MeshBuilder::default();
+        for source in assembly.definition.sources.iter() {
+            source.build_meshes(scale.0, &mut wire, &mut arrows);
+        }
+        let wire_material = StandardMaterial {
+            base_color: Color::rgb(0.72, 0.45, 0.2),
+            metallic: 0.8,
+            perceptual_roughness: 0.4,
+            ..default()
+        };
+        let arrow_material = StandardMaterial {
+            base_color: Color::YELLOW,
+            unlit: true,
+            ..default()
+        };
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: meshes.add(wire.build()),
+                material: materials.add(wire_material),
+                ..default()
+            });
+            parent
+                .spawn(PbrBundle {
+                    mesh: meshes.add(arrows.build()),
+                    material: materials.add(arrow_material),
+                    ..default()
+                })
+                .insert(NotShadowCaster);
+        });
+    }
+}
+
+/// Shows or hides [CoilAssembly]s.
+pub fn update_coil_visibility(
+    mut query: Query<&mut Visibility, With<CoilAssembly>>,
+    config: Res<CoilConfiguration>,
+) {
+    for mut visibility in query.iter_mut() {
+        visibility.is_visible = config.show_coils;
+    }
+}
+
+/// Window used to change the currents of coils, and the remanence of magnets, while the simulation runs.
+pub fn coil_controls(
+    mut egui_context: ResMut<EguiContext>,
+    mut config: ResMut<CoilConfiguration>,
+    mut query: Query<&mut CoilAssembly>,
+) {
+    egui::Window::new("Coils")
+        .default_open(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut config.show_coils, "Show coils?"));
+            for (i, mut assembly) in query.iter_mut().enumerate() {
+                let mut definition = assembly.definition.clone();
+                for (j, source) in definition.sources.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let (label, value, suffix) = match source {
+                            FieldSource::Loop(coil) => ("Loop", &mut coil.current, " A"),
+                            FieldSource::Solenoid(solenoid) => {
+                                ("Solenoid", &mut solenoid.current, " A")
+                            }
+                            FieldSource::Rectangle(coil) => ("Rectangle", &mut coil.current, " A"),
+                            FieldSource::Magnet(magnet) => ("Magnet", &mut magnet.remanence, " T"),
+                        };
+                        ui.label(format!("{} {}.{}", label, i + 1, j + 1));
+                        ui.add(egui::DragValue::new(value).speed(0.01).suffix(suffix));
+                    });
+                }
+                // Only mutate the assembly when a value changes, so the field is not recalculated every frame.
+                let changed = definition
+                    .sources
+                    .iter()
+                    .zip(assembly.definition.sources.iter())
+                    .any(|(a, b)| a.strength() != b.strength());
+                if changed {
+                    assembly.definition = definition;
+                }
+            }
+        });
+}
+
+impl FieldSource {
+    /// The current (or remanence) that can be changed from the user interface.
+    fn strength(&self) -> f64 {
+        match self {
+            FieldSource::Loop(coil) => coil.current,
+            FieldSource::Solenoid(solenoid) => solenoid.current,
+            FieldSource::Rectangle(coil) => coil.current,
+            FieldSource::Magnet(magnet) => magnet.remanence,
+        }
+    }
+}
+
+/// Loads [FieldDefinition]s from `.coils.json` files.
+#[derive(Default)]
+pub struct FieldDefinitionLoader;
+impl AssetLoader for FieldDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let definition: FieldDefinition = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(definition));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["coils.json"]
+    }
+}
+
+/// Creates a [CoilAssembly] for entities with a `Handle<FieldDefinition>` once the definition has loaded.
+///
+/// Entities given a handle to a definition that has already loaded, for example when a demo is
+/// reset, receive no asset event, so are set up when the handle is added.
+pub fn spawn_coil_assemblies(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<FieldDefinition>>,
+    definitions: Res<Assets<FieldDefinition>>,
+    query: Query<(Entity, &Handle<FieldDefinition>)>,
+    added: Query<(Entity, &Handle<FieldDefinition>), Added<Handle<FieldDefinition>>>,
+) {
+    let mut assemblies = Vec::new();
+    for (entity, handle) in added.iter() {
+        if let Some(definition) = definitions.get(handle) {
+            assemblies.push((entity, definition));
+        }
+    }
+    for event in events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            if let Some(definition) = definitions.get(handle) {
+                for (entity, _) in query.iter().filter(|(_, h)| *h == handle) {
+                    assemblies.push((entity, definition));
+                }
+            }
+        }
+    }
+    for (entity, definition) in assemblies {
+        commands
+            .entity(entity)
+            .insert(CoilAssembly {
+                definition: definition.clone(),
+            })
+            .insert(SpatialBundle::default());
+    }
+}
+
+pub struct CoilPlugin;
+impl Plugin for CoilPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<FieldDefinition>();
+        app.init_asset_loader::<FieldDefinitionLoader>();
+        app.init_resource::<CoilConfiguration>();
+        add_presentation_stage(app);
+        app.add_system_to_stage(PresentationStage, spawn_coil_assemblies);
+        app.add_system_to_stage(PresentationStage, update_coil_assemblies);
+        app.add_system_to_stage(PresentationStage, update_coil_visibility);
+        app.add_system_to_stage(PresentationStage, coil_controls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_of_source(source: &FieldSource, pos: &Vector3<f64>) -> Vector3<f64> {
+        field_of_segments(&source.segments(), pos)
+    }
+
+    #[test]
+    fn field_of_long_wire() {
+        // Near the middle of a long wire, the field is mu0 I / (2 pi d).
+        let wire = CurrentSegment {
+            start: Vector3::new(0.0, 0.0, -100.0),
+            end: Vector3::new(0.0, 0.0, 100.0),
+            current: 2.0,
+        };
+        let field = wire.field(&Vector3::new(0.01, 0.0, 0.0));
+        let expected = MU0 * 2.0 / (2.0 * std::f64::consts::PI * 0.01);
+        assert!((field.y - expected).abs() < 1e-6 * expected);
+        assert!(field.x.abs() < 1e-12 && field.z.abs() < 1e-12);
+        assert_eq!(wire.field(&Vector3::new(0.0, 0.0, 200.0)), Vector3::zeros());
+    }
+
+    #[test]
+    fn field_at_centre_of_loop() {
+        let (radius, current, turns) = (0.05, 3.0, 10.0);
+        let coil = FieldSource::Loop(CircularLoop {
+            centre: [0.1, -0.2, 0.3],
+            normal: [0.0, 0.0, 1.0],
+            radius,
+            turns,
+            current,
+        });
+        let field = field_of_source(&coil, &Vector3::new(0.1, -0.2, 0.3));
+        let expected = MU0 * turns * current / (2.0 * radius);
+        // The loop is approximated by a polygon of SEGMENTS_PER_LOOP sides.
+        assert!((field.z - expected).abs() < 1e-3 * expected);
+        assert!(field.x.abs() < 1e-9 * expected && field.y.abs() < 1e-9 * expected);
+    }
+
+    #[test]
+    fn slower_coils_reproduce_field_map() {
+        let directory = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/fields");
+        let read = |name: &str| std::fs::read_to_string(directory.join(name)).unwrap();
+        let definition: FieldDefinition = serde_json::from_str(&read("slower.coils.json")).unwrap();
+        let map: MagneticFieldGrid = serde_json::from_str(&read("slower.field.json")).unwrap();
+        let calculated = definition.calculate();
+        assert_eq!(calculated.extent_cells, map.extent_cells);
+        for (calculated, expected) in calculated.grid.iter().zip(map.grid.iter()) {
+            // Within 5 G everywhere along the slower.
+            assert!((calculated - expected).norm() < 5e-4);
+        }
+    }
+}
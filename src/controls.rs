@@ -0,0 +1,192 @@
+//! A control bar to pause, step and reset the simulation.
+//!
+//! Demos register the systems that build their experiment with [SetupAppExt::add_setup_system].
+//! These run once when the app starts, and again whenever a [ResetSimulation] event is sent, after
+//! the atoms and all entities spawned by the previous setup have been despawned. The
+//! [SimulationClock] is set back to zero and the [SimulationRng] is reseeded, so a reset run
+//! repeats the first.
+
+use std::collections::HashSet;
+
+use atomecs::atom::Atom;
+use bevy::ecs::event::ManualEventReader;
+use bevy::ecs::schedule::IntoSystemDescriptor;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::random::SimulationRng;
+use crate::stepping::{
+    add_presentation_stage, PresentationStage, SimulationClock, SimulationSpeed,
+};
+
+/// Send to despawn all atoms and run the setup systems again.
+pub struct ResetSimulation;
+
+/// Sent each time the setup systems have run, once their entities have been spawned.
+pub struct SetupFinished;
+
+/// Systems that build the experiment, and the entities they spawned.
+#[derive(Resource)]
+struct SetupSystems {
+    stage: SystemStage,
+    spawned: Vec<Entity>,
+    has_run: bool,
+}
+
+pub trait SetupAppExt {
+    /// Adds a system that builds the experiment. It runs when the app starts, and again when the
+    /// simulation is reset.
+    ///
+    /// Entities spawned by setup systems are despawned on reset, so should not be spawned elsewhere.
+    fn add_setup_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self;
+}
+
+impl SetupAppExt for App {
+    fn add_setup_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self {
+        if !self.world.contains_resource::<SetupSystems>() {
+            self.insert_resource(SetupSystems {
+                stage: SystemStage::single_threaded(),
+                spawned: Vec::new(),
+                has_run: false,
+            });
+            self.add_event::<ResetSimulation>();
+            self.add_event::<SetupFinished>();
+            self.add_system_to_stage(CoreStage::First, run_setup_systems);
+        }
+        self.world
+            .resource_mut::<SetupSystems>()
+            .stage
+            .add_system(system);
+        self
+    }
+}
+
+/// Runs the setup systems on the first frame, and again whenever the simulation is reset.
+fn run_setup_systems(world: &mut World, mut reader: Local<ManualEventReader<ResetSimulation>>) {
+    let reset = reader
+        .iter(world.resource::<Events<ResetSimulation>>())
+        .count()
+        > 0;
+    let ran = world.resource_scope(|world, mut setup: Mut<SetupSystems>| {
+        if setup.has_run && !reset {
+            return false;
+        }
+        if setup.has_run {
+            let atoms: Vec<Entity> = world
+                .query_filtered::<Entity, With<Atom>>()
+                .iter(world)
+                .collect();
+            for entity in setup.spawned.drain(..).chain(atoms) {
+                // Children of a previously despawned entity are already gone.
+                if world.get_entity(entity).is_some() {
+                    world.entity_mut(entity).despawn_recursive();
+                }
+            }
+            if let Some(mut clock) = world.get_resource_mut::<SimulationClock>() {
+                *clock = SimulationClock::default();
+            }
+            if let Some(mut rng) = world.get_resource_mut::<SimulationRng>() {
+                rng.reseed();
+            }
+        }
+        let before: HashSet<Entity> = world.iter_entities().collect();
+        setup.stage.run(world);
+        setup.spawned = world
+            .iter_entities()
+            .filter(|entity| !before.contains(entity))
+            .collect();
+        setup.has_run = true;
+        true
+    });
+    if ran {
+        world.send_event(SetupFinished);
+    }
+}
+
+/// State of the widgets in the control bar.
+#[derive(Resource)]
+pub struct ControlBarState {
+    /// Number of steps run by the step button.
+    pub step_count: u32,
+}
+impl Default for ControlBarState {
+    fn default() -> Self {
+        ControlBarState { step_count: 100 }
+    }
+}
+
+/// A bar along the bottom of the window to pause, step and reset the simulation, and to set its speed.
+pub fn control_bar(
+    mut egui_context: ResMut<EguiContext>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut state: ResMut<ControlBarState>,
+    mut reset: EventWriter<ResetSimulation>,
+) {
+    egui::TopBottomPanel::bottom("control_bar").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            if speed.is_paused() {
+                if ui.button("Resume").clicked() {
+                    speed.resume();
+                }
+            } else if ui.button("Pause").clicked() {
+                speed.pause();
+            }
+            if ui.button("Step").clicked() {
+                speed.step(state.step_count);
+            }
+            ui.add(
+                egui::DragValue::new(&mut state.step_count)
+                    .clamp_range(1..=100_000)
+                    .suffix(" steps"),
+            );
+            if ui.button("Reset").clicked() {
+                reset.send(ResetSimulation);
+            }
+            ui.separator();
+            speed_slider(ui, &mut speed, "Simulation speed");
+            ui.label(format!(
+                "{:.2e} x real time, {} steps per frame",
+                speed.measured_ratio, speed.steps_last_frame
+            ));
+        });
+    });
+}
+
+/// A logarithmic slider for the target ratio of simulated to real time.
+pub fn speed_slider(ui: &mut egui::Ui, speed: &mut SimulationSpeed, label: &str) {
+    ui.add(
+        egui::Slider::new(&mut speed.target_ratio, 1e-6..=1.0)
+            .logarithmic(true)
+            .text(label),
+    );
+}
+
+/// Toggles pause with the space bar.
+pub fn keyboard_controls(
+    keys: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        if speed.is_paused() {
+            speed.resume();
+        } else {
+            speed.pause();
+        }
+    }
+}
+
+/// Adds the control bar. Requires the `SteppingPlugin` and `EguiPlugin`.
+pub struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.init_resource::<ControlBarState>();
+        app.add_event::<ResetSimulation>();
+        app.add_system_to_stage(PresentationStage, control_bar);
+        app.add_system_to_stage(PresentationStage, keyboard_controls);
+    }
+}
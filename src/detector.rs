@@ -0,0 +1,156 @@
+//! Detectors that count atoms crossing a plane, to measure the flux from a source.
+
+use std::collections::{HashMap, VecDeque};
+
+use atomecs::atom::{Atom, Position, Velocity};
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+/// Number of velocities kept by a [Detector] unless set with [Detector::with_window].
+pub const DEFAULT_WINDOW: usize = 100_000;
+
+/// A disc that counts atoms crossing it along its normal.
+///
+/// The disc is centred on the `Position` of the entity. Atoms are only counted when they cross
+/// from behind the disc to in front of it.
+#[derive(Component, Clone)]
+pub struct Detector {
+    /// Normal of the disc.
+    pub normal: Vector3<f64>,
+    /// Radius of the disc, in units of m.
+    pub radius: f64,
+    /// Whether detected atoms are removed from the simulation.
+    pub absorb: bool,
+    /// Number of simulated atoms detected.
+    pub detected: u64,
+    /// Number of the most recent velocities kept.
+    pub window: usize,
+    /// Velocity along the normal of the most recently detected atoms, in units of m/s.
+    velocities: VecDeque<f64>,
+    /// Sum of the velocities along the normal of all detected atoms, in units of m/s.
+    velocity_sum: f64,
+    /// Distance in front of the disc of each atom on the previous step.
+    distances: HashMap<Entity, f64>,
+}
+
+impl Detector {
+    pub fn new(normal: Vector3<f64>, radius: f64) -> Self {
+        Detector {
+            normal: normal.normalize(),
+            radius,
+            absorb: true,
+            detected: 0,
+            window: DEFAULT_WINDOW,
+            velocities: VecDeque::new(),
+            velocity_sum: 0.0,
+            distances: HashMap::new(),
+        }
+    }
+
+    /// Counts atoms without removing them from the simulation.
+    pub fn passive(mut self) -> Self {
+        self.absorb = false;
+        self
+    }
+
+    /// Keeps the velocities of the last `window` atoms detected.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Mean velocity along the normal of detected atoms, in units of m/s.
+    pub fn mean_velocity(&self) -> f64 {
+        if self.detected > 0 {
+            self.velocity_sum / self.detected as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Velocities of the last `n` atoms detected, oldest first, or as many of them as are still
+    /// in the window.
+    pub fn recent(&self, n: u64) -> impl Iterator<Item = &f64> {
+        let n = (n.min(self.velocities.len() as u64)) as usize;
+        self.velocities.range(self.velocities.len() - n..)
+    }
+
+    fn record(&mut self, velocity: f64) {
+        self.detected += 1;
+        self.velocity_sum += velocity;
+        if self.velocities.len() >= self.window {
+            self.velocities.pop_front();
+        }
+        if self.window > 0 {
+            self.velocities.push_back(velocity);
+        }
+    }
+}
+
+/// Mean of the values, or zero if there are none.
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+/// Counts the atoms that crossed each [Detector] during the last step.
+pub fn detect_atoms(
+    mut commands: Commands,
+    mut detectors: Query<(&Position, &mut Detector)>,
+    atoms: Query<(Entity, &Position, &Velocity), With<Atom>>,
+) {
+    for (detector_position, mut detector) in detectors.iter_mut() {
+        let mut distances = HashMap::with_capacity(detector.distances.len());
+        for (entity, position, velocity) in atoms.iter() {
+            let offset = position.pos - detector_position.pos;
+            let distance = offset.dot(&detector.normal);
+            let crossed = distance >= 0.0
+                && matches!(detector.distances.get(&entity), Some(previous) if *previous < 0.0);
+            let radial = (offset - distance * detector.normal).norm();
+            if crossed && radial <= detector.radius {
+                let normal = detector.normal;
+                detector.record(velocity.vel.dot(&normal));
+                if detector.absorb {
+                    commands.entity(entity).despawn();
+                    continue;
+                }
+            }
+            distances.insert(entity, distance);
+        }
+        detector.distances = distances;
+    }
+}
+
+/// Counts atoms crossing [Detector]s on every simulation step.
+pub struct DetectorPlugin;
+impl Plugin for DetectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(detect_atoms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_totals_beyond_the_window() {
+        let mut detector = Detector::new(Vector3::x(), 1.0).with_window(3);
+        for velocity in 1..=5 {
+            detector.record(velocity as f64);
+        }
+        assert_eq!(detector.detected, 5);
+        assert_eq!(detector.mean_velocity(), 3.0);
+        assert_eq!(
+            detector.recent(2).copied().collect::<Vec<_>>(),
+            vec![4.0, 5.0]
+        );
+        assert_eq!(
+            detector.recent(10).copied().collect::<Vec<_>>(),
+            vec![3.0, 4.0, 5.0]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! A cloud of atoms cooled in a 3D MOT, used to measure the Doppler temperature limit.
+//!
+//! The simulation is built here, so that it can be run both by the `3d_mot` demo and headless,
+//! where a sweep of the detuning finds the minimum temperature. For a two-level atom at low
+//! intensity, the minimum is the Doppler limit ħΓ/2k_B, reached at a detuning of -Γ/2.
+//!
+//! Parameters are read from a json [DopplerConfiguration]: `assets/doppler.json` for the demo, and
+//! `assets/doppler_validation.json`, with weaker beams and a sweep, for the validation test.
+
+use std::path::Path;
+
+use atomecs::atom::{Atom, Force, Mass, Position, Velocity};
+use atomecs::constant::{BOLTZCONST, HBAR};
+use atomecs::initiate::NewlyCreated;
+use atomecs::integrator::Timestep;
+use atomecs::laser::LaserPlugin;
+use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
+use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::laser_cooling::LaserCoolingPlugin;
+use atomecs::magnetic::quadrupole::QuadrupoleField3D;
+use atomecs::species::Rubidium87_780D2;
+use bevy::asset::Error;
+use bevy::prelude::*;
+use nalgebra::Vector3;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::beams::MotBeams;
+use crate::controls::SetupAppExt;
+use crate::random::SimulationRng;
+use crate::statistics::CloudStatistics;
+use crate::stepping::headless_app;
+use crate::sweep::run_parallel;
+
+pub const BEAM_NUMBER: usize = 6;
+
+/// Atoms further than this from the centre of the MOT have escaped, and are not included in the
+/// temperature, in units of m.
+const TRAPPED_RADIUS: f64 = 0.01;
+
+/// Detunings of a sweep, evenly spaced from `start` to `end`, in units of MHz.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DetuningSweep {
+    pub start: f64,
+    pub end: f64,
+    pub points: usize,
+}
+
+impl DetuningSweep {
+    pub fn detunings(&self) -> Vec<f64> {
+        match self.points {
+            0 => Vec::new(),
+            1 => vec![self.start],
+            n => (0..n)
+                .map(|i| self.start + (self.end - self.start) * i as f64 / (n - 1) as f64)
+                .collect(),
+        }
+    }
+}
+
+/// Parameters of the Doppler cooling simulation.
+#[derive(Serialize, Deserialize, Clone, Debug, Resource)]
+#[serde(default)]
+pub struct DopplerConfiguration {
+    /// Detuning of laser beams, in units of MHz.
+    pub detuning: f64,
+    /// Power of each laser beam, in units of W.
+    pub power: f64,
+    /// Number of simulation steps to evolve for, when run headless.
+    pub number_of_steps: u32,
+    /// Number of atoms in the cloud.
+    pub atom_number: usize,
+    /// Speed at which the cloud is launched into the MOT, in units of m/s.
+    pub launch_speed: f64,
+    /// Detunings to sweep over when measuring the minimum temperature.
+    pub sweep: Option<DetuningSweep>,
+}
+impl Default for DopplerConfiguration {
+    fn default() -> Self {
+        DopplerConfiguration {
+            detuning: -8.0,
+            power: 0.02,
+            number_of_steps: 5000,
+            atom_number: 1000,
+            launch_speed: 3.5,
+            sweep: None,
+        }
+    }
+}
+
+impl DopplerConfiguration {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(Self::from_json(&json)?)
+    }
+}
+
+/// The Doppler temperature limit ħΓ/2k_B of the transition, in units of K.
+pub fn doppler_limit<T: AtomicTransition>() -> f64 {
+    HBAR * T::gamma() / (2.0 * BOLTZCONST)
+}
+
+pub fn setup_world(mut commands: Commands, configuration: Res<DopplerConfiguration>) {
+    // Create magnetic field.
+    commands
+        .spawn(QuadrupoleField3D::gauss_per_cm(0.001 * 18.2, Vector3::z()))
+        .insert(Position {
+            pos: Vector3::new(0.0, 0.0, 0.0),
+        });
+
+    // Create cooling lasers.
+    let radius = 66.7e-3 / (2.0_f64.sqrt());
+    let beam_centre = Vector3::new(0.0, 0.0, 0.0);
+
+    MotBeams::three_d(Vector3::z(), -1)
+        .at(beam_centre)
+        .e_radius(radius)
+        .power(configuration.power)
+        .detuning(configuration.detuning)
+        .spawn::<Rubidium87_780D2, _>(&mut commands, MOTBeam);
+}
+
+#[derive(Component, Clone)]
+pub struct MOTBeam;
+
+pub fn create_atoms(
+    mut commands: Commands,
+    mut rng: ResMut<SimulationRng>,
+    configuration: Res<DopplerConfiguration>,
+) {
+    let vel_dist = Normal::new(0.0, 0.42).unwrap();
+    let pos_dist = Normal::new(0.0, 1.2e-4).unwrap();
+
+    // Add atoms
+    for _ in 0..configuration.atom_number {
+        commands
+            .spawn(Position {
+                pos: Vector3::new(
+                    pos_dist.sample(&mut *rng),
+                    pos_dist.sample(&mut *rng) - 0.002,
+                    pos_dist.sample(&mut *rng),
+                ),
+            })
+            .insert(Velocity {
+                vel: Vector3::new(
+                    vel_dist.sample(&mut *rng),
+                    vel_dist.sample(&mut *rng) + configuration.launch_speed,
+                    vel_dist.sample(&mut *rng),
+                ),
+            })
+            .insert(Force::default())
+            .insert(Mass { value: 87.0 })
+            .insert(Rubidium87_780D2)
+            .insert(Atom)
+            .insert(NewlyCreated);
+    }
+}
+
+/// Builds the Doppler cooling simulation, set up by the [DopplerConfiguration] resource. Does not
+/// add any rendering.
+pub struct DopplerPlugin;
+impl Plugin for DopplerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(atomecs::integrator::IntegrationPlugin);
+        app.add_plugin(atomecs::initiate::InitiatePlugin);
+        app.add_plugin(atomecs::magnetic::MagneticsPlugin);
+        app.add_plugin(LaserPlugin::<{ BEAM_NUMBER }>);
+        app.add_plugin(LaserCoolingPlugin::<Rubidium87_780D2, { BEAM_NUMBER }>::default());
+        app.init_resource::<DopplerConfiguration>();
+        app.insert_resource(Timestep { delta: 2.0e-5 });
+        app.insert_resource(EmissionForceOption::On(EmissionForceConfiguration {
+            explicit_threshold: 5,
+        }));
+        app.insert_resource(ScatteringFluctuationsOption::On);
+        app.add_setup_system(setup_world);
+        app.add_setup_system(create_atoms);
+    }
+}
+
+/// Runs the simulation headless for [DopplerConfiguration::number_of_steps], and measures the
+/// atoms still trapped in the MOT.
+pub fn measure_cloud(configuration: DopplerConfiguration, seed: u64) -> CloudStatistics {
+    let steps = configuration.number_of_steps;
+    let mut app = headless_app(seed, |app| {
+        app.add_plugin(DopplerPlugin);
+    });
+    app.insert_resource(configuration);
+    for _ in 0..steps {
+        app.update();
+    }
+    let mut atoms = app
+        .world
+        .query_filtered::<(&Position, &Velocity, &Mass), With<Atom>>();
+    CloudStatistics::from_atoms(
+        atoms
+            .iter(&app.world)
+            .filter(|(position, _, _)| position.pos.norm() < TRAPPED_RADIUS)
+            .map(|(_, velocity, mass)| (velocity, mass)),
+    )
+}
+
+/// Measures the cloud at each detuning of the configuration's sweep, spread over `workers`
+/// threads.
+///
+/// Results are returned in the order of the sweep, from `start` to `end`. Each point is seeded with `seed` plus its index, and
+/// `progress` is called with the index of each point as it finishes.
+pub fn run_detuning_sweep(
+    configuration: &DopplerConfiguration,
+    seed: u64,
+    workers: usize,
+    progress: impl FnMut(usize, &CloudStatistics),
+) -> Vec<(f64, CloudStatistics)> {
+    let detunings = configuration
+        .sweep
+        .as_ref()
+        .map_or_else(Vec::new, |sweep| sweep.detunings());
+    let results = run_parallel(
+        &detunings,
+        workers,
+        |index, detuning| {
+            let point = DopplerConfiguration {
+                detuning: *detuning,
+                ..configuration.clone()
+            };
+            measure_cloud(point, seed + index as u64)
+        },
+        progress,
+    );
+    detunings.into_iter().zip(results).collect()
+}
+
+/// The point of a sweep with the lowest temperature, ignoring points where every atom escaped.
+pub fn minimum_temperature(results: &[(f64, CloudStatistics)]) -> Option<(f64, CloudStatistics)> {
+    results
+        .iter()
+        .filter(|(_, cloud)| cloud.atom_number > 0)
+        .min_by(|(_, a), (_, b)| a.temperature.total_cmp(&b.temperature))
+        .copied()
+}
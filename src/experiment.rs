@@ -0,0 +1,254 @@
+//! Control panels for the parameters of an experiment.
+//!
+//! The [crate::experiment_controls] macro declares a resource holding the parameters, together with the
+//! egui widgets that edit them, so adding a control is a single line:
+//!
+//! ```ignore
+//! experiment_controls! {
+//!     pub struct ExperimentConfiguration {
+//!         "Cooling Beams" {
+//!             cooling_beam_detuning: f64 = -40.0 => slider(-120.0..=-15.0, "Cooling beam detuning", "MHz"),
+//!             cooling_beam_power: f64 = 230.0 => slider(0.0..=230.0, "Cooling beam power", "mW"),
+//!         }
+//!         "" {
+//!             show_cad: bool = true => checkbox("Show CAD?"),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Systems that apply the parameters to the simulation are added with
+//! [ExperimentControlsAppExt::add_control_system], and only run when a parameter is changed or
+//! the experiment has been set up again.
+
+use bevy::asset::Error;
+use bevy::ecs::schedule::{IntoSystemDescriptor, ShouldRun};
+use bevy::prelude::*;
+pub use bevy_egui::egui;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+use crate::controls::SetupFinished;
+use crate::stepping::{add_presentation_stage, PresentationStage};
+
+/// A resource of experiment parameters that can be edited in a control panel, and saved as a preset.
+///
+/// Implemented by the [crate::experiment_controls] macro.
+pub trait ExperimentControls: Resource + Clone + Default + Serialize + DeserializeOwned {
+    /// Draws a widget for each parameter, and returns true if any were changed.
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool;
+}
+
+/// Draws the controls of the experiment, only marking the resource changed when a value is edited.
+pub fn controls_ui<C: ExperimentControls>(ui: &mut egui::Ui, config: &mut ResMut<C>) -> bool {
+    let mut edited = C::clone(config);
+    let changed = edited.ui(ui);
+    if changed {
+        **config = edited;
+    }
+    changed
+}
+
+/// Returns a copy of the experiment parameters, with the named numeric fields set to new values.
+pub fn with_parameters<C: ExperimentControls>(
+    config: &C,
+    values: &[(String, f64)],
+) -> Result<C, Error> {
+    let mut fields = match serde_json::to_value(config)? {
+        Value::Object(fields) => fields,
+        _ => return Err(Error::msg("Parameters are not a struct.")),
+    };
+    for (name, value) in values {
+        match fields.get(name) {
+            Some(Value::Number(_)) => {
+                let number = Number::from_f64(*value)
+                    .ok_or_else(|| Error::msg(format!("Invalid value {} for {}.", value, name)))?;
+                fields.insert(name.clone(), Value::Number(number));
+            }
+            _ => return Err(Error::msg(format!("No numeric parameter named {}.", name))),
+        }
+    }
+    Ok(serde_json::from_value(Value::Object(fields))?)
+}
+
+/// Run criteria for systems that apply the parameters of the experiment.
+fn controls_changed<C: ExperimentControls>(
+    config: Res<C>,
+    mut setup: EventReader<SetupFinished>,
+) -> ShouldRun {
+    // Entities built by the setup systems start with their own values, so are updated too.
+    if setup.iter().count() > 0 || config.is_changed() {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+pub trait ExperimentControlsAppExt {
+    /// Adds a system that applies the parameters `C` to the simulation.
+    ///
+    /// The system runs in the [PresentationStage] when the parameters change, including on the
+    /// first frame, and after the simulation is reset.
+    fn add_control_system<C: ExperimentControls + FromWorld, Params>(
+        &mut self,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self;
+}
+
+impl ExperimentControlsAppExt for App {
+    fn add_control_system<C: ExperimentControls + FromWorld, Params>(
+        &mut self,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self {
+        add_presentation_stage(self);
+        self.init_resource::<C>();
+        self.add_event::<SetupFinished>();
+        self.add_system_to_stage(
+            PresentationStage,
+            system.with_run_criteria(controls_changed::<C>),
+        )
+    }
+}
+
+/// A slider for a value in the given range. The unit is shown after the label.
+pub fn slider(
+    ui: &mut egui::Ui,
+    value: &mut f64,
+    range: std::ops::RangeInclusive<f64>,
+    label: &str,
+    unit: &str,
+) -> bool {
+    ui.add(egui::Slider::new(value, range).text(with_unit(label, unit)))
+        .changed()
+}
+
+/// A slider with a logarithmic scale, for values spanning several orders of magnitude.
+pub fn log_slider(
+    ui: &mut egui::Ui,
+    value: &mut f64,
+    range: std::ops::RangeInclusive<f64>,
+    label: &str,
+    unit: &str,
+) -> bool {
+    ui.add(
+        egui::Slider::new(value, range)
+            .logarithmic(true)
+            .text(with_unit(label, unit)),
+    )
+    .changed()
+}
+
+pub fn checkbox(ui: &mut egui::Ui, value: &mut bool, label: &str) -> bool {
+    ui.checkbox(value, label).changed()
+}
+
+/// Heading of a group of parameters, which is omitted if empty.
+pub fn heading(ui: &mut egui::Ui, heading: &str) {
+    if !heading.is_empty() {
+        ui.add_space(0.1);
+        ui.label(heading);
+    }
+}
+
+fn with_unit(label: &str, unit: &str) -> String {
+    if unit.is_empty() {
+        label.to_string()
+    } else {
+        format!("{} ({})", label, unit)
+    }
+}
+
+/// Declares a resource of experiment parameters, and implements [ExperimentControls] for it.
+///
+/// Parameters are listed in groups, each drawn under its heading, or without one if the heading
+/// is empty. Each parameter gives its type, default value and the widget used to edit it, which is
+/// one of the functions in this module, called with the remaining arguments:
+///
+/// - `slider(range, label, unit)` and `log_slider(range, label, unit)` for `f64` values.
+/// - `checkbox(label)` for `bool` values.
+#[macro_export]
+macro_rules! experiment_controls {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $group:literal {
+                    $(
+                        $(#[$field_meta:meta])*
+                        $field:ident : $ty:ty = $default:expr => $widget:ident ( $($args:tt)* )
+                    ),* $(,)?
+                }
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(bevy::prelude::Resource, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        // Presets saved before a parameter was added still load, with its default value.
+        #[serde(default)]
+        $vis struct $name {
+            $($(
+                $(#[$field_meta])*
+                pub $field: $ty,
+            )*)*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name {
+                    $($($field: $default,)*)*
+                }
+            }
+        }
+
+        impl $crate::experiment::ExperimentControls for $name {
+            fn ui(&mut self, ui: &mut $crate::experiment::egui::Ui) -> bool {
+                let mut changed = false;
+                $(
+                    $crate::experiment::heading(ui, $group);
+                    $(
+                        changed |= $crate::experiment::$widget(ui, &mut self.$field, $($args)*);
+                    )*
+                )*
+                changed
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::experiment_controls! {
+        struct TestControls {
+            "Beams" {
+                power: f64 = 1.0 => slider(0.0..=2.0, "Power", "W"),
+                detuning: f64 = -10.0 => slider(-20.0..=0.0, "Detuning", "MHz"),
+                show_beams: bool = true => checkbox("Show beams?"),
+            }
+        }
+    }
+
+    #[test]
+    fn with_parameters_sets_numeric_fields() {
+        let values = [("detuning".to_string(), -5.0), ("power".to_string(), 0.5)];
+        let config = with_parameters(&TestControls::default(), &values).unwrap();
+        assert_eq!(
+            config,
+            TestControls {
+                power: 0.5,
+                detuning: -5.0,
+                show_beams: true,
+            }
+        );
+    }
+
+    #[test]
+    fn with_parameters_rejects_unknown_and_non_numeric_fields() {
+        let config = TestControls::default();
+        assert!(with_parameters(&config, &[("frequency".to_string(), 1.0)]).is_err());
+        assert!(with_parameters(&config, &[("show_beams".to_string(), 1.0)]).is_err());
+        assert!(with_parameters(&config, &[("power".to_string(), f64::NAN)]).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Magnetic field grids loaded as bevy assets.
+//!
+//! Field maps are stored under `assets/fields`, either as json (`.field.json`) or in a compact binary
+//! format (`.field.bin`). Entities holding a `Handle<MagneticFieldGrid>` are given the corresponding
+//! [PrecalculatedMagneticFieldGrid] once the asset has loaded, and again whenever it is modified.
+//! Enable the `hot_reload` feature and set `AssetPlugin::watch_for_changes` to swap field maps
+//! while a demo is running.
+
+use std::convert::TryInto;
+
+use atomecs::magnetic::grid::PrecalculatedMagneticFieldGrid;
+use bevy::asset::{AssetLoader, BoxedFuture, Error, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::stepping::{add_presentation_stage, PresentationStage};
+
+/// Magic bytes at the start of a binary field grid.
+const BINARY_MAGIC: &[u8; 8] = b"AFGRID01";
+
+/// A magnetic field sampled on a regular grid.
+///
+/// Has the same json representation as [PrecalculatedMagneticFieldGrid].
+#[derive(Serialize, Deserialize, Clone, TypeUuid)]
+#[uuid = "6f1e2b4c-3a5d-4e8f-9b0a-7c2d1e3f4a5b"]
+pub struct MagneticFieldGrid {
+    /// Size of the grid, in units of m.
+    pub extent_spatial: Vector3<f64>,
+    /// Number of cells along each axis.
+    pub extent_cells: Vector3<i32>,
+    /// Position of the grid centre, in units of m.
+    pub position: Vector3<f64>,
+    /// Field in each cell, in units of T, with the x index varying fastest.
+    pub grid: Vec<Vector3<f64>>,
+}
+
+impl MagneticFieldGrid {
+    /// Creates the component used by AtomECS to calculate fields from the grid.
+    pub fn to_component(&self) -> PrecalculatedMagneticFieldGrid {
+        PrecalculatedMagneticFieldGrid {
+            extent_spatial: self.extent_spatial,
+            extent_cells: self.extent_cells,
+            position: self.position,
+            grid: self.grid.clone(),
+        }
+    }
+
+    /// Encodes the grid in the compact binary format.
+    ///
+    /// The format is the magic bytes `AFGRID01`, followed by the spatial extent (3 x f64), number of
+    /// cells (3 x i32), position (3 x f64) and then the field in each cell (3 x f64). All values are
+    /// little endian.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(BINARY_MAGIC.len() + 60 + 24 * self.grid.len());
+        bytes.extend_from_slice(BINARY_MAGIC);
+        for x in self.extent_spatial.iter() {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in self.extent_cells.iter() {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in self.position.iter() {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for cell in self.grid.iter() {
+            for x in cell.iter() {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a grid from the compact binary format, see [MagneticFieldGrid::to_binary].
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = BinaryReader { bytes };
+        if reader.take(BINARY_MAGIC.len())? != BINARY_MAGIC {
+            return Err(Error::msg("Not a binary magnetic field grid."));
+        }
+        let extent_spatial = Vector3::new(reader.f64()?, reader.f64()?, reader.f64()?);
+        let extent_cells = Vector3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+        let position = Vector3::new(reader.f64()?, reader.f64()?, reader.f64()?);
+        if extent_cells.iter().any(|&n| n < 0) {
+            return Err(Error::msg(
+                "Magnetic field grid has a negative number of cells.",
+            ));
+        }
+        // The header is untrusted, so check it matches the data before allocating the grid.
+        let cells = extent_cells
+            .iter()
+            .try_fold(1usize, |cells, &n| cells.checked_mul(n as usize));
+        match cells.and_then(|cells| cells.checked_mul(24)) {
+            Some(length) if length == reader.bytes.len() => {}
+            _ => {
+                return Err(Error::msg(
+                    "Number of cells does not match the size of the magnetic field grid.",
+                ))
+            }
+        }
+        let cells = reader.bytes.len() / 24;
+        let mut grid = Vec::with_capacity(cells);
+        for _ in 0..cells {
+            grid.push(Vector3::new(reader.f64()?, reader.f64()?, reader.f64()?));
+        }
+        Ok(MagneticFieldGrid {
+            extent_spatial,
+            extent_cells,
+            position,
+            grid,
+        })
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            return Err(Error::msg("Unexpected end of magnetic field grid."));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+    fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+/// Loads [MagneticFieldGrid]s from `.field.json` files.
+#[derive(Default)]
+pub struct FieldGridJsonLoader;
+impl AssetLoader for FieldGridJsonLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let grid: MagneticFieldGrid = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(grid));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["field.json"]
+    }
+}
+
+/// Loads [MagneticFieldGrid]s from `.field.bin` files.
+#[derive(Default)]
+pub struct FieldGridBinaryLoader;
+impl AssetLoader for FieldGridBinaryLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let grid = MagneticFieldGrid::from_binary(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(grid));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["field.bin"]
+    }
+}
+
+/// Inserts the [PrecalculatedMagneticFieldGrid] for entities with a `Handle<MagneticFieldGrid>`,
+/// replacing it whenever the asset is reloaded.
+///
+/// Runs once per frame in the [PresentationStage], so that field maps which load while the
+/// simulation is paused are not missed. Entities given a handle to a grid that has already loaded,
+/// for example when a demo is reset, receive no asset event, so are updated when the handle is
+/// added.
+pub fn update_field_grids(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<MagneticFieldGrid>>,
+    grids: Res<Assets<MagneticFieldGrid>>,
+    query: Query<(Entity, &Handle<MagneticFieldGrid>)>,
+    added: Query<(Entity, &Handle<MagneticFieldGrid>), Added<Handle<MagneticFieldGrid>>>,
+) {
+    for (entity, handle) in added.iter() {
+        if let Some(grid) = grids.get(handle) {
+            commands.entity(entity).insert(grid.to_component());
+        }
+    }
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(grid) = grids.get(handle) {
+                    for (entity, _) in query.iter().filter(|(_, h)| *h == handle) {
+                        commands.entity(entity).insert(grid.to_component());
+                    }
+                }
+            }
+            AssetEvent::Removed { handle } => {
+                for (entity, _) in query.iter().filter(|(_, h)| *h == handle) {
+                    commands
+                        .entity(entity)
+                        .remove::<PrecalculatedMagneticFieldGrid>();
+                }
+            }
+        }
+    }
+}
+
+pub struct FieldGridPlugin;
+impl Plugin for FieldGridPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MagneticFieldGrid>();
+        app.init_asset_loader::<FieldGridJsonLoader>();
+        app.init_asset_loader::<FieldGridBinaryLoader>();
+        add_presentation_stage(app);
+        app.add_system_to_stage(PresentationStage, update_field_grids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> MagneticFieldGrid {
+        MagneticFieldGrid {
+            extent_spatial: Vector3::new(0.1, 0.2, 0.3),
+            extent_cells: Vector3::new(2, 3, 1),
+            position: Vector3::new(-0.05, 0.0, 0.01),
+            grid: (0..6)
+                .map(|i| Vector3::new(i as f64, -1e-3 * i as f64, 0.5))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let grid = grid();
+        let bytes = grid.to_binary();
+        assert_eq!(&bytes[..8], BINARY_MAGIC);
+        let decoded = MagneticFieldGrid::from_binary(&bytes).unwrap();
+        assert_eq!(decoded.extent_spatial, grid.extent_spatial);
+        assert_eq!(decoded.extent_cells, grid.extent_cells);
+        assert_eq!(decoded.position, grid.position);
+        assert_eq!(decoded.grid, grid.grid);
+    }
+
+    #[test]
+    fn binary_rejects_malformed_grids() {
+        let bytes = grid().to_binary();
+        assert!(MagneticFieldGrid::from_binary(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.extend_from_slice(&[0; 24]);
+        assert!(MagneticFieldGrid::from_binary(&extra).is_err());
+        assert!(MagneticFieldGrid::from_binary(b"NOTAGRID").is_err());
+
+        // A header claiming more cells than fit in memory is rejected before allocating.
+        let mut huge = bytes[..8 + 24].to_vec();
+        for _ in 0..3 {
+            huge.extend_from_slice(&i32::MAX.to_le_bytes());
+        }
+        huge.extend_from_slice(&bytes[8 + 36..]);
+        assert!(MagneticFieldGrid::from_binary(&huge).is_err());
+    }
+}
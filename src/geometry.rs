@@ -0,0 +1,15 @@
+//! Geometric helpers shared by the beams, sources and coils.
+
+use nalgebra::Vector3;
+
+/// Returns two unit vectors perpendicular to `normal` and to each other, forming a right-handed set.
+pub fn perpendicular_basis(normal: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let trial = if normal.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let u = normal.cross(&trial).normalize();
+    let v = normal.cross(&u);
+    (u, v)
+}
@@ -0,0 +1,143 @@
+//! Heads-up display showing the state of the simulation.
+
+use atomecs::atom::Atom;
+use atomecs::integrator::Timestep;
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+
+use crate::stepping::{
+    add_presentation_stage, PresentationStage, SimulationClock, SimulationSpeed,
+};
+
+/// Corner of the window the HUD is drawn in.
+#[derive(Clone, Copy)]
+pub enum HudPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Appearance of the HUD.
+#[derive(Resource, Clone)]
+pub struct HudConfig {
+    pub position: HudPosition,
+    /// Path of the font, relative to the `assets` directory.
+    pub font: String,
+    pub font_size: f32,
+    pub color: Color,
+    /// Distance from the edges of the window, in units of px.
+    pub margin: f32,
+}
+impl Default for HudConfig {
+    fn default() -> Self {
+        HudConfig {
+            position: HudPosition::TopLeft,
+            font: "fonts/FiraSans-Bold.ttf".to_string(),
+            font_size: 18.0,
+            color: Color::WHITE,
+            margin: 10.0,
+        }
+    }
+}
+
+/// Marks the text of the HUD.
+#[derive(Component)]
+pub struct Hud;
+
+pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<HudConfig>) {
+    let margin = Val::Px(config.margin);
+    let position = match config.position {
+        HudPosition::TopLeft => UiRect {
+            top: margin,
+            left: margin,
+            ..default()
+        },
+        HudPosition::TopRight => UiRect {
+            top: margin,
+            right: margin,
+            ..default()
+        },
+        HudPosition::BottomLeft => UiRect {
+            bottom: margin,
+            left: margin,
+            ..default()
+        },
+        HudPosition::BottomRight => UiRect {
+            bottom: margin,
+            right: margin,
+            ..default()
+        },
+    };
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(config.font.as_str()),
+                    font_size: config.font_size,
+                    color: config.color,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position,
+                ..default()
+            }),
+        )
+        .insert(Hud);
+}
+
+pub fn update_hud(
+    mut query: Query<&mut Text, With<Hud>>,
+    clock: Res<SimulationClock>,
+    speed: Res<SimulationSpeed>,
+    timestep: Res<Timestep>,
+    diagnostics: Res<Diagnostics>,
+    atoms: Query<(), With<Atom>>,
+) {
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or(0.0);
+    let value = format!(
+        "Time: {}\nSteps: {}\nSpeed: {:.2e} x real time{}\nAtoms: {}\nTimestep: {}\nFPS: {:.0}",
+        format_time(clock.time),
+        clock.steps,
+        speed.measured_ratio,
+        if speed.is_paused() { " (paused)" } else { "" },
+        atoms.iter().count(),
+        format_time(timestep.delta),
+        fps
+    );
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+/// Formats a time in units of s with an SI prefix, eg `12.3 ms`.
+pub fn format_time(time: f64) -> String {
+    if time == 0.0 {
+        return "0 s".to_string();
+    }
+    let prefixes = [(1.0, "s"), (1e-3, "ms"), (1e-6, "μs"), (1e-9, "ns")];
+    let (scale, unit) = prefixes
+        .iter()
+        .find(|(scale, _)| time.abs() >= *scale)
+        .unwrap_or(&prefixes[prefixes.len() - 1]);
+    format!("{:.3} {}", time / scale, unit)
+}
+
+/// Shows the simulated time, steps, real-time ratio, atom count, timestep and frame rate.
+///
+/// Requires the `SteppingPlugin`. The appearance is set by the [HudConfig] resource.
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.init_resource::<HudConfig>();
+        app.add_plugin(FrameTimeDiagnosticsPlugin);
+        app.add_startup_system(spawn_hud);
+        app.add_system_to_stage(PresentationStage, update_hud);
+    }
+}
@@ -1,6 +1,33 @@
+pub mod aion_source;
 pub mod atoms;
+pub mod beams;
 pub mod camera;
+pub mod capture;
+pub mod coils;
+pub mod controls;
+pub mod detector;
+pub mod doppler;
+pub mod experiment;
+pub mod fields;
+pub mod geometry;
+pub mod hud;
 pub mod lasers;
+pub mod live_sweep;
+pub mod meshes;
+pub mod mot_2d;
+pub mod optimise;
+pub mod presets;
+pub mod random;
+pub mod scene;
+pub mod sequence;
+pub mod share;
+pub mod snapshot;
+pub mod statistics;
+pub mod sources;
+pub mod stepping;
+pub mod sweep;
+pub mod timeline;
+pub mod trajectory;
 
 use bevy::prelude::*;
 
@@ -9,6 +36,7 @@ impl Plugin for BevyAtomECSPlugin {
     fn build(&self, app: &mut App) {
         //app.add_system(atoms::add_meshes_to_atoms);
         app.init_resource::<atoms::MaterialColorConfig>();
+        app.init_resource::<random::SimulationRng>();
     }
 }
 
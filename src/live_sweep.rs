@@ -0,0 +1,335 @@
+//! Sweeps a parameter of the running demo, and plots a diagnostic as it is measured.
+//!
+//! Unlike the headless [crate::sweep], this runs in the simulation on screen, so it works in the
+//! browser. At each point the parameter is set, the simulation runs for the settling time, and the
+//! diagnostic is measured over the measurement window, both in simulated time. The parameters are
+//! restored when the sweep finishes or is stopped.
+
+use std::marker::PhantomData;
+
+use atomecs::atom::Atom;
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bevy_egui::egui::plot::{Line, Plot, PlotPoints, Points};
+use serde_json::Value;
+
+use crate::detector::Detector;
+use crate::experiment::{with_parameters, ExperimentControls};
+use crate::sources::{AtomWeight, ContinuousSource, Oven};
+use crate::stepping::{add_presentation_stage, PresentationStage, SimulationClock};
+use crate::sweep::{Diagnostic, SourceDiagnostics, Totals};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    /// Waiting for the simulation to settle at the current point.
+    Settling {
+        since: f64,
+    },
+    /// Measuring the current point.
+    Measuring {
+        since: f64,
+    },
+}
+
+/// Settings, progress and results of a sweep over one of the parameters `C`.
+#[derive(Resource)]
+pub struct LiveSweep<C> {
+    /// Name of the numeric field of `C` that is swept.
+    pub parameter: String,
+    pub min: f64,
+    pub max: f64,
+    pub points: usize,
+    /// Simulated time to wait at each point before measuring, in units of s.
+    pub settling_time: f64,
+    /// Simulated time to measure each point for, in units of s.
+    pub measurement_time: f64,
+    /// The diagnostic that is plotted.
+    pub diagnostic: Diagnostic,
+    /// Value of the parameter at each measured point, and the diagnostics measured there.
+    pub results: Vec<(f64, SourceDiagnostics)>,
+    phase: Phase,
+    index: usize,
+    start: Option<Totals>,
+    atom_sum: usize,
+    frames: usize,
+    /// Parameters before the sweep started.
+    original: Option<C>,
+    marker: PhantomData<C>,
+}
+
+impl<C: ExperimentControls> Default for LiveSweep<C> {
+    fn default() -> Self {
+        let mut sweep = LiveSweep {
+            parameter: String::new(),
+            min: 0.0,
+            max: 1.0,
+            points: 11,
+            settling_time: 0.01,
+            measurement_time: 0.02,
+            diagnostic: Diagnostic::Flux,
+            results: Vec::new(),
+            phase: Phase::Idle,
+            index: 0,
+            start: None,
+            atom_sum: 0,
+            frames: 0,
+            original: None,
+            marker: PhantomData,
+        };
+        if let Some(parameter) = Self::parameters().first() {
+            sweep.select(parameter);
+        }
+        sweep
+    }
+}
+
+impl<C: ExperimentControls> LiveSweep<C> {
+    /// Names of the numeric parameters that can be swept.
+    pub fn parameters() -> Vec<String> {
+        match serde_json::to_value(C::default()) {
+            Ok(Value::Object(fields)) => fields
+                .into_iter()
+                .filter(|(_, value)| value.is_number())
+                .map(|(name, _)| name)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Sweeps the named parameter, over a range either side of its default value.
+    pub fn select(&mut self, parameter: &str) {
+        let default = serde_json::to_value(C::default())
+            .ok()
+            .and_then(|fields| fields.get(parameter).and_then(Value::as_f64))
+            .unwrap_or(0.0);
+        let half_width = if default == 0.0 {
+            1.0
+        } else {
+            0.5 * default.abs()
+        };
+        self.parameter = parameter.to_string();
+        self.min = default - half_width;
+        self.max = default + half_width;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.phase != Phase::Idle
+    }
+
+    /// Value of the parameter at a point of the sweep.
+    fn value(&self, index: usize) -> f64 {
+        if self.points > 1 {
+            self.min + (self.max - self.min) * index as f64 / (self.points - 1) as f64
+        } else {
+            self.min
+        }
+    }
+
+    /// Starts the sweep from the current parameters.
+    pub fn start(&mut self, config: &mut C, time: f64) {
+        self.results.clear();
+        self.original = Some(config.clone());
+        self.index = 0;
+        self.phase = Phase::Settling { since: time };
+        self.set_parameter(config);
+    }
+
+    /// Stops the sweep, and restores the parameters from before it started.
+    pub fn stop(&mut self, config: &mut C) {
+        self.phase = Phase::Idle;
+        self.start = None;
+        if let Some(original) = self.original.take() {
+            *config = original;
+        }
+    }
+
+    fn set_parameter(&mut self, config: &mut C) {
+        let value = (self.parameter.clone(), self.value(self.index));
+        match with_parameters(&*config, &[value]) {
+            Ok(updated) => *config = updated,
+            Err(error) => {
+                warn!("Could not sweep {}: {}", self.parameter, error);
+                self.stop(config);
+            }
+        }
+    }
+
+    /// Measured values of the plotted diagnostic.
+    pub fn curve(&self) -> Vec<[f64; 2]> {
+        self.results
+            .iter()
+            .map(|(x, diagnostics)| [*x, self.diagnostic.value(diagnostics)])
+            .collect()
+    }
+}
+
+/// Advances the sweep through its points, as the simulation runs.
+#[allow(clippy::too_many_arguments)]
+pub fn run_live_sweep<C: ExperimentControls>(
+    mut sweep: ResMut<LiveSweep<C>>,
+    mut config: ResMut<C>,
+    clock: Res<SimulationClock>,
+    weight: Res<AtomWeight>,
+    ovens: Query<&Oven>,
+    sources: Query<&ContinuousSource>,
+    detectors: Query<&Detector>,
+    atoms: Query<(), With<Atom>>,
+) {
+    let totals = || Totals::new(ovens.iter(), sources.iter(), detectors.iter());
+    match sweep.phase {
+        Phase::Idle => {}
+        // The simulation has been reset.
+        Phase::Settling { since } | Phase::Measuring { since } if clock.time < since => {
+            sweep.stop(&mut config);
+        }
+        Phase::Settling { since } => {
+            if clock.time >= since + sweep.settling_time {
+                sweep.start = Some(totals());
+                sweep.atom_sum = 0;
+                sweep.frames = 0;
+                sweep.phase = Phase::Measuring { since: clock.time };
+            }
+        }
+        Phase::Measuring { since } => {
+            sweep.atom_sum += atoms.iter().count();
+            sweep.frames += 1;
+            if clock.time < since + sweep.measurement_time {
+                return;
+            }
+            if let Some(start) = sweep.start.take() {
+                let mean_atoms = sweep.atom_sum as f64 / sweep.frames as f64;
+                let measurement = totals().since(
+                    &start,
+                    detectors.iter(),
+                    mean_atoms,
+                    weight.0,
+                    clock.time - since,
+                );
+                let value = sweep.value(sweep.index);
+                sweep.results.push((value, measurement.diagnostics()));
+            }
+            sweep.index += 1;
+            if sweep.index < sweep.points {
+                sweep.phase = Phase::Settling { since: clock.time };
+                sweep.set_parameter(&mut config);
+            } else {
+                sweep.stop(&mut config);
+            }
+        }
+    }
+}
+
+/// Settings of the sweep, a button to start or stop it, and a plot of the results.
+pub fn live_sweep_ui<C: ExperimentControls>(
+    ui: &mut egui::Ui,
+    sweep: &mut LiveSweep<C>,
+    config: &mut ResMut<C>,
+    clock: &SimulationClock,
+) {
+    ui.add_enabled_ui(!sweep.is_running(), |ui| {
+        let mut parameter = sweep.parameter.clone();
+        egui::ComboBox::from_label("Parameter")
+            .selected_text(parameter.as_str())
+            .show_ui(ui, |ui| {
+                for name in LiveSweep::<C>::parameters() {
+                    ui.selectable_value(&mut parameter, name.clone(), name);
+                }
+            });
+        if parameter != sweep.parameter {
+            sweep.select(&parameter);
+        }
+        ui.horizontal(|ui| {
+            ui.label("From");
+            ui.add(egui::DragValue::new(&mut sweep.min).speed(0.1));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut sweep.max).speed(0.1));
+            ui.label("in");
+            ui.add(
+                egui::DragValue::new(&mut sweep.points)
+                    .clamp_range(2..=100)
+                    .suffix(" points"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Settle for");
+            ui.add(
+                egui::DragValue::new(&mut sweep.settling_time)
+                    .clamp_range(0.0..=1.0)
+                    .speed(1e-3)
+                    .suffix(" s"),
+            );
+            ui.label("then measure for");
+            ui.add(
+                egui::DragValue::new(&mut sweep.measurement_time)
+                    .clamp_range(1e-4..=1.0)
+                    .speed(1e-3)
+                    .suffix(" s"),
+            );
+        });
+    });
+    egui::ComboBox::from_label("Diagnostic")
+        .selected_text(sweep.diagnostic.label())
+        .show_ui(ui, |ui| {
+            for diagnostic in Diagnostic::ALL {
+                ui.selectable_value(&mut sweep.diagnostic, diagnostic, diagnostic.label());
+            }
+        });
+    ui.horizontal(|ui| {
+        if sweep.is_running() {
+            if ui.button("Stop sweep").clicked() {
+                sweep.stop(config);
+            }
+            let phase = match sweep.phase {
+                Phase::Measuring { .. } => "measuring",
+                _ => "settling",
+            };
+            ui.label(format!(
+                "Point {}/{}, {}",
+                sweep.index + 1,
+                sweep.points,
+                phase
+            ));
+        } else if ui.button("Start sweep").clicked() {
+            sweep.start(config, clock.time);
+        }
+    });
+    ui.label(format!(
+        "{} against {}",
+        sweep.diagnostic.label(),
+        sweep.parameter
+    ));
+    let curve = sweep.curve();
+    Plot::new("live_sweep")
+        .height(200.0)
+        .include_x(sweep.min)
+        .include_x(sweep.max)
+        .include_y(0.0)
+        .show(ui, |plot_ui| {
+            plot_ui.line(Line::new(PlotPoints::new(curve.clone())));
+            plot_ui.points(Points::new(PlotPoints::new(curve)).radius(3.0));
+        });
+}
+
+/// Adds a [LiveSweep] over the parameters `C`. Its settings and plot are drawn with
+/// [live_sweep_ui].
+pub struct LiveSweepPlugin<C> {
+    marker: PhantomData<C>,
+}
+
+impl<C> Default for LiveSweepPlugin<C> {
+    fn default() -> Self {
+        LiveSweepPlugin {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C: ExperimentControls + FromWorld> Plugin for LiveSweepPlugin<C> {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.init_resource::<C>();
+        app.init_resource::<LiveSweep<C>>();
+        app.add_system_to_stage(PresentationStage, run_live_sweep::<C>);
+    }
+}
@@ -0,0 +1,124 @@
+//! Procedural meshes for parametric geometry, such as wires and arrows.
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+
+/// Number of vertices around the circumference of tubes and cones.
+const SIDES: usize = 12;
+
+/// Accumulates triangles, in world coordinates, to build a single [Mesh].
+#[derive(Default)]
+pub struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// Adds a tube of the given radius that follows `path`.
+    ///
+    /// The cross section is carried along the path by parallel transport, so closed planar paths
+    /// join without a twist.
+    pub fn tube(&mut self, path: &[Vec3], radius: f32) -> &mut Self {
+        if path.len() < 2 {
+            return self;
+        }
+        let tangent = |i: usize| {
+            let next = path[(i + 1).min(path.len() - 1)];
+            let previous = path[i.saturating_sub(1)];
+            (next - previous).normalize()
+        };
+        let mut normal = tangent(0).any_orthonormal_vector();
+        for (i, point) in path.iter().enumerate() {
+            let t = tangent(i);
+            normal = (normal - t * normal.dot(t)).normalize();
+            let binormal = t.cross(normal);
+            for side in 0..=SIDES {
+                let phi = 2.0 * std::f32::consts::PI * side as f32 / SIDES as f32;
+                let direction = phi.cos() * normal + phi.sin() * binormal;
+                self.positions.push((*point + radius * direction).into());
+                self.normals.push(direction.into());
+            }
+        }
+        let first = (self.positions.len() - path.len() * (SIDES + 1)) as u32;
+        let ring = (SIDES + 1) as u32;
+        for i in 0..(path.len() - 1) as u32 {
+            for side in 0..SIDES as u32 {
+                let a = first + i * ring + side;
+                let b = a + ring;
+                self.indices
+                    .extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+        self
+    }
+
+    /// Adds a closed cone with its base centred on `base`, pointing along `direction`.
+    pub fn cone(&mut self, base: Vec3, direction: Vec3, radius: f32, height: f32) -> &mut Self {
+        let axis = direction.normalize();
+        let u = axis.any_orthonormal_vector();
+        let v = axis.cross(u);
+        let apex = base + axis * height;
+        let slope = radius / height;
+        for side in 0..SIDES {
+            let phi0 = 2.0 * std::f32::consts::PI * side as f32 / SIDES as f32;
+            let phi1 = 2.0 * std::f32::consts::PI * (side + 1) as f32 / SIDES as f32;
+            let r0 = phi0.cos() * u + phi0.sin() * v;
+            let r1 = phi1.cos() * u + phi1.sin() * v;
+            let first = self.positions.len() as u32;
+            // Sloping face.
+            self.positions.extend_from_slice(&[
+                (base + radius * r0).into(),
+                (base + radius * r1).into(),
+                apex.into(),
+            ]);
+            let mid = (0.5 * (r0 + r1)).normalize();
+            self.normals.extend_from_slice(&[
+                (r0 + slope * axis).normalize().into(),
+                (r1 + slope * axis).normalize().into(),
+                (mid + slope * axis).normalize().into(),
+            ]);
+            // Base.
+            self.positions.extend_from_slice(&[
+                (base + radius * r1).into(),
+                (base + radius * r0).into(),
+                base.into(),
+            ]);
+            self.normals.extend_from_slice(&[(-axis).into(); 3]);
+            self.indices.extend(first..first + 6);
+        }
+        self
+    }
+
+    /// Adds a flat quadrilateral. Corners are listed anticlockwise when viewed from the side `normal` points to.
+    pub fn quad(&mut self, corners: [Vec3; 4], normal: Vec3) -> &mut Self {
+        let first = self.positions.len() as u32;
+        self.positions
+            .extend(corners.iter().map(|corner| <[f32; 3]>::from(*corner)));
+        self.normals.extend_from_slice(&[normal.into(); 4]);
+        self.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        self
+    }
+
+    /// Adds an arrow from `start` along `direction`, of total `length`.
+    pub fn arrow(&mut self, start: Vec3, direction: Vec3, length: f32, radius: f32) -> &mut Self {
+        let axis = direction.normalize();
+        let head_length = (4.0 * radius).min(0.5 * length);
+        let shaft_end = start + axis * (length - head_length);
+        self.tube(&[start, shaft_end], radius);
+        self.cone(shaft_end, axis, 2.5 * radius, head_length)
+    }
+
+    pub fn build(&self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone());
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            vec![[0.0f32, 0.0]; self.positions.len()],
+        );
+        mesh.set_indices(Some(Indices::U32(self.indices.clone())));
+        mesh
+    }
+}
@@ -0,0 +1,174 @@
+//! A 2D+ MOT of strontium, loaded from a continuous source.
+//!
+//! Only the simulation is built here, so that it can be run both by the `2d_mot` demo and headless
+//! by the `optimise` runner.
+
+use atomecs::atom::Position;
+use atomecs::integrator::Timestep;
+use atomecs::laser::gaussian::GaussianBeam;
+use atomecs::laser::LaserPlugin;
+use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
+use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
+use atomecs::laser_cooling::{CoolingLight, LaserCoolingPlugin};
+use atomecs::magnetic::quadrupole::QuadrupoleField3D;
+use atomecs::shapes::Cuboid;
+use atomecs::sim_region::{SimulationRegionPlugin, SimulationVolume, VolumeType};
+use atomecs::species::Strontium88_461;
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+use crate::beams::{MotBeams, SingleBeam};
+use crate::controls::SetupAppExt;
+use crate::experiment::ExperimentControlsAppExt;
+use crate::experiment_controls;
+use crate::scene::PhaseSpaceDistribution;
+use crate::sources::{AtomWeight, ContinuousSource, SourcePlugin};
+
+pub const BEAM_NUMBER: usize = 6;
+
+experiment_controls! {
+    pub struct ExperimentConfiguration {
+        "Cooling Beams:" {
+            cooling_beam_detuning: f64 = -40.0 => slider(-120.0..=-15.0, "Cooling beam detuning", "MHz"),
+            cooling_beam_power: f64 = 230.0 => slider(0.0..=230.0, "Cooling beam power", "mW"),
+        }
+        "Push Beam:" {
+            push_beam_detuning: f64 = -103.0 => slider(-400.0..=100.0, "Push beam detuning", "MHz"),
+            push_beam_power: f64 = 20.0 => slider(0.0..=30.0, "Push beam power", "mW"),
+        }
+        "Magnetic fields:" {
+            quad_gradient: f64 = 27.0 => slider(0.0..=80.0, "Quadrupole gradient", "G/cm"),
+        }
+    }
+}
+
+#[derive(Component, Default, Clone)]
+pub struct MOTBeam;
+
+#[derive(Component, Default, Clone)]
+pub struct PushBeam;
+
+pub fn setup_world(mut commands: Commands) {
+    // Create magnetic field.
+    commands
+        .spawn(QuadrupoleField3D::gauss_per_cm(27.0, Vector3::z()))
+        .insert(Position::default());
+
+    // Push beam along z
+    let push_beam_radius = 1e-3;
+    let push_beam_power = 0.020;
+    let push_beam_detuning = -103.0;
+
+    SingleBeam::along(Vector3::z(), -1)
+        .e_radius(push_beam_radius)
+        .power(push_beam_power)
+        .detuning(push_beam_detuning)
+        .spawn::<Strontium88_461, _>(&mut commands, PushBeam);
+
+    // Create cooling lasers.
+    let detuning = -40.0;
+    let power = 0.23;
+    let radius = 17.0e-3; //33.0e-3 / (2.0 * 2.0_f64.sqrt()); // 33mm 1/e^2 diameter
+    MotBeams::two_d(Vector3::z(), Vector3::new(1.0, 1.0, 0.0), [1, 1])
+        .e_radius(radius)
+        .power(power)
+        .detuning(detuning)
+        .spawn::<Strontium88_461, _>(&mut commands, MOTBeam);
+
+    // Atoms loaded from the oven, 5e10 atoms/s.
+    commands.spawn(ContinuousSource::new(
+        5e10,
+        PhaseSpaceDistribution {
+            position: [-0.08, 0.0, 0.0],
+            position_spread: [0.0, 0.0, 0.0],
+            velocity: [50.0, 0.0, 0.0],
+            velocity_spread: [10.0, 4.0, 4.0],
+            mass: 88.0,
+            magnetic_dipole: None,
+        },
+    ));
+
+    // Use a simulation bound so that atoms that escape the capture region are deleted from the simulation.
+    commands
+        .spawn(Position {
+            pos: Vector3::new(0.0, 0.0, 0.0),
+        })
+        .insert(Cuboid {
+            half_width: Vector3::new(0.1, 0.01, 0.01),
+        })
+        .insert(SimulationVolume {
+            volume_type: VolumeType::Inclusive,
+        });
+
+    // The simulation bound also now includes a small pipe to capture the 2D MOT output properly.
+    commands
+        .spawn(Position {
+            pos: Vector3::new(0.0, 0.0, 0.1),
+        })
+        .insert(Cuboid {
+            half_width: Vector3::new(0.01, 0.01, 0.1),
+        })
+        .insert(SimulationVolume {
+            volume_type: VolumeType::Inclusive,
+        });
+}
+
+fn update_cooling_beams(
+    mut query: Query<(&mut CoolingLight, &mut GaussianBeam), With<MOTBeam>>,
+    config: Res<ExperimentConfiguration>,
+) {
+    for (mut light, mut gaussian) in query.iter_mut() {
+        let wavelength =
+            CoolingLight::for_transition::<Strontium88_461>(config.cooling_beam_detuning, 1)
+                .wavelength;
+        light.wavelength = wavelength;
+        gaussian.power = 1e-3 * config.cooling_beam_power;
+    }
+}
+
+fn update_push_beam(
+    mut query: Query<(&mut CoolingLight, &mut GaussianBeam), With<PushBeam>>,
+    config: Res<ExperimentConfiguration>,
+) {
+    for (mut light, mut gaussian) in query.iter_mut() {
+        let wavelength =
+            CoolingLight::for_transition::<Strontium88_461>(config.push_beam_detuning, 1)
+                .wavelength;
+        light.wavelength = wavelength;
+        gaussian.power = 1e-3 * config.push_beam_power;
+    }
+}
+
+fn update_magnetic_fields(
+    mut query: Query<&mut QuadrupoleField3D>,
+    config: Res<ExperimentConfiguration>,
+) {
+    for mut quad in query.iter_mut() {
+        quad.gradient = 0.01 * config.quad_gradient;
+    }
+}
+
+/// Adds the AtomECS plugins, the setup of the MOT and the systems that apply the
+/// [ExperimentConfiguration]. Does not add any rendering.
+pub struct Mot2DPlugin;
+impl Plugin for Mot2DPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(atomecs::integrator::IntegrationPlugin);
+        app.add_plugin(atomecs::initiate::InitiatePlugin);
+        app.add_plugin(atomecs::magnetic::MagneticsPlugin);
+        app.add_plugin(LaserPlugin::<{ BEAM_NUMBER }>);
+        app.add_plugin(LaserCoolingPlugin::<Strontium88_461, { BEAM_NUMBER }>::default());
+        app.add_plugin(SimulationRegionPlugin);
+        app.add_plugin(SourcePlugin::<Strontium88_461>::default());
+        app.insert_resource(AtomWeight(1e6));
+        app.insert_resource(Timestep { delta: 2.0e-5 });
+        app.insert_resource(EmissionForceOption::On(EmissionForceConfiguration {
+            explicit_threshold: 5,
+        }));
+        app.insert_resource(ScatteringFluctuationsOption::On);
+        app.add_setup_system(setup_world);
+        app.add_control_system::<ExperimentConfiguration, _>(update_cooling_beams);
+        app.add_control_system::<ExperimentConfiguration, _>(update_push_beam);
+        app.add_control_system::<ExperimentConfiguration, _>(update_magnetic_fields);
+    }
+}
@@ -0,0 +1,388 @@
+//! Optimises the parameters of an experiment, by running it headless.
+//!
+//! The optimiser is a Nelder-Mead simplex over the bounded parameters, scaled to the unit cube.
+//! Measurements are noisy because atoms are sampled at random, so each point is averaged over
+//! several runs, and the best vertex of the simplex is measured again on every iteration. A lucky
+//! measurement therefore cannot hold the simplex in place.
+
+use std::io::Write;
+
+use bevy::asset::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::experiment::{with_parameters, ExperimentControls};
+use crate::sweep::{run_points, BuildExperiment, Measurement, SourceDiagnostics};
+
+/// The quantity to maximise.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum Objective {
+    /// Flux through the detectors, in units of atoms/s.
+    Flux,
+    /// Flux of atoms slower than `max_velocity` through the detectors, in units of atoms/s.
+    SlowFlux { max_velocity: f64 },
+    /// Fraction of the emitted atoms that are detected.
+    CaptureFraction,
+    /// Mean number of atoms in the simulation, eg to load a MOT.
+    AtomNumber,
+}
+
+impl Objective {
+    pub fn evaluate(&self, measurement: &Measurement) -> f64 {
+        match self {
+            Objective::Flux => measurement.diagnostics().flux,
+            Objective::SlowFlux { max_velocity } => measurement.flux_below(*max_velocity),
+            Objective::CaptureFraction => measurement.diagnostics().capture_fraction,
+            Objective::AtomNumber => measurement.diagnostics().atom_number,
+        }
+    }
+}
+
+/// A parameter of the experiment varied by the optimiser, between bounds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OptimisedParameter {
+    /// Name of a numeric field of the experiment parameters.
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// An optimisation, loaded from a json file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OptimisationDescription {
+    /// Name of the experiment, eg `aion_source`.
+    pub experiment: String,
+    pub parameters: Vec<OptimisedParameter>,
+    pub objective: Objective,
+    /// Simulated time before measuring, in units of s.
+    pub settling_time: f64,
+    /// Simulated time over which the objective is measured, in units of s.
+    pub measurement_time: f64,
+    /// Maximum number of points to evaluate, not counting repeats.
+    #[serde(default = "default_max_evaluations")]
+    pub max_evaluations: usize,
+    /// Number of runs averaged at each point.
+    #[serde(default = "default_repeats")]
+    pub repeats: usize,
+    /// Size of the initial simplex, as a fraction of the range of each parameter.
+    #[serde(default = "default_initial_step")]
+    pub initial_step: f64,
+    /// The optimiser stops when the simplex is smaller than this, as a fraction of the range of
+    /// each parameter.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_max_evaluations() -> usize {
+    100
+}
+
+fn default_repeats() -> usize {
+    2
+}
+
+fn default_initial_step() -> f64 {
+    0.25
+}
+
+fn default_tolerance() -> f64 {
+    0.01
+}
+
+impl OptimisationDescription {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Values of the parameters at a point in the unit cube.
+    fn values(&self, x: &[f64]) -> Vec<(String, f64)> {
+        self.parameters
+            .iter()
+            .zip(x.iter())
+            .map(|(parameter, x)| {
+                let value = parameter.min + x.clamp(0.0, 1.0) * (parameter.max - parameter.min);
+                (parameter.name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+/// A single evaluation of the objective.
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    pub parameters: Vec<(String, f64)>,
+    pub objective: f64,
+    pub diagnostics: SourceDiagnostics,
+}
+
+/// A vertex of the simplex. Its value is the mean of every measurement made there.
+#[derive(Clone)]
+struct Vertex {
+    x: Vec<f64>,
+    total: f64,
+    samples: usize,
+}
+
+impl Vertex {
+    fn value(&self) -> f64 {
+        self.total / self.samples as f64
+    }
+}
+
+/// Runs the experiment and evaluates the objective, logging each point.
+struct Evaluator<'a, C, L> {
+    description: &'a OptimisationDescription,
+    build: BuildExperiment,
+    defaults: C,
+    workers: usize,
+    runs: u64,
+    log: L,
+    evaluations: usize,
+}
+
+impl<'a, C: ExperimentControls, L: FnMut(&Evaluation)> Evaluator<'a, C, L> {
+    /// Evaluates the objective at each point, running the repeats of every point in parallel.
+    fn evaluate(&mut self, points: &[Vec<f64>]) -> Result<Vec<f64>, Error> {
+        let repeats = self.description.repeats.max(1);
+        let mut configs = Vec::with_capacity(points.len() * repeats);
+        for x in points {
+            let config = with_parameters(&self.defaults, &self.description.values(x))?;
+            for _ in 0..repeats {
+                configs.push((
+                    config.clone(),
+                    self.description.seed.wrapping_add(self.runs),
+                ));
+                self.runs += 1;
+            }
+        }
+        let measurements = run_points(
+            self.build,
+            &configs,
+            self.description.settling_time,
+            self.description.measurement_time,
+            self.workers,
+            |_, _| {},
+        );
+        let mut values = Vec::with_capacity(points.len());
+        for (x, runs) in points.iter().zip(measurements.chunks(repeats)) {
+            let measurement = Measurement::combine(runs);
+            let evaluation = Evaluation {
+                parameters: self.description.values(x),
+                objective: self.description.objective.evaluate(&measurement),
+                diagnostics: measurement.diagnostics(),
+            };
+            (self.log)(&evaluation);
+            self.evaluations += 1;
+            values.push(evaluation.objective);
+        }
+        Ok(values)
+    }
+
+    fn vertex(&mut self, x: Vec<f64>) -> Result<Vertex, Error> {
+        let value = self.evaluate(&[x.clone()])?[0];
+        Ok(Vertex {
+            x,
+            total: value,
+            samples: 1,
+        })
+    }
+}
+
+/// Result of an optimisation.
+pub struct Optimum<C> {
+    pub config: C,
+    pub parameters: Vec<(String, f64)>,
+    /// Mean of the objective over every measurement of the optimum.
+    pub objective: f64,
+    pub evaluations: usize,
+}
+
+/// Maximises the objective, starting from the default parameters of the experiment.
+///
+/// `log` is called with every evaluation of the objective.
+pub fn optimise<C: ExperimentControls>(
+    description: &OptimisationDescription,
+    build: BuildExperiment,
+    workers: usize,
+    log: impl FnMut(&Evaluation),
+) -> Result<Optimum<C>, Error> {
+    let defaults = C::default();
+    let dimensions = description.parameters.len();
+    if dimensions == 0 {
+        return Err(Error::msg("No parameters to optimise."));
+    }
+
+    // Start from the default parameters, scaled to the unit cube.
+    let fields = serde_json::to_value(&defaults)?;
+    let mut start = Vec::with_capacity(dimensions);
+    for parameter in description.parameters.iter() {
+        let value = fields
+            .get(&parameter.name)
+            .and_then(|value| value.as_f64())
+            .ok_or_else(|| Error::msg(format!("No numeric parameter named {}.", parameter.name)))?;
+        start.push(((value - parameter.min) / (parameter.max - parameter.min)).clamp(0.0, 1.0));
+    }
+
+    let mut evaluator = Evaluator {
+        description,
+        build,
+        defaults,
+        workers,
+        runs: 0,
+        log,
+        evaluations: 0,
+    };
+
+    let mut points = vec![start.clone()];
+    for i in 0..dimensions {
+        let mut x = start.clone();
+        x[i] = if x[i] + description.initial_step <= 1.0 {
+            x[i] + description.initial_step
+        } else {
+            x[i] - description.initial_step
+        };
+        points.push(x);
+    }
+    let values = evaluator.evaluate(&points)?;
+    let mut simplex: Vec<Vertex> = points
+        .into_iter()
+        .zip(values)
+        .map(|(x, value)| Vertex {
+            x,
+            total: value,
+            samples: 1,
+        })
+        .collect();
+
+    let clamp = |x: Vec<f64>| -> Vec<f64> { x.into_iter().map(|x| x.clamp(0.0, 1.0)).collect() };
+    // Point a fraction `t` of the way from `a` to `b`, which may be outside the segment.
+    let along = |a: &[f64], b: &[f64], t: f64| -> Vec<f64> {
+        clamp(a.iter().zip(b).map(|(a, b)| a + t * (b - a)).collect())
+    };
+
+    while evaluator.evaluations < description.max_evaluations {
+        simplex.sort_by(|a, b| b.value().total_cmp(&a.value()));
+        let size = simplex[1..]
+            .iter()
+            .flat_map(|vertex| vertex.x.iter().zip(simplex[0].x.iter()))
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        if size < description.tolerance {
+            break;
+        }
+
+        let worst = simplex[dimensions].clone();
+        let mut centroid = vec![0.0; dimensions];
+        for vertex in simplex[..dimensions].iter() {
+            for (c, x) in centroid.iter_mut().zip(vertex.x.iter()) {
+                *c += x / dimensions as f64;
+            }
+        }
+
+        let reflected = evaluator.vertex(along(&centroid, &worst.x, -1.0))?;
+        if reflected.value() > simplex[0].value() {
+            let expanded = evaluator.vertex(along(&centroid, &worst.x, -2.0))?;
+            simplex[dimensions] = if expanded.value() > reflected.value() {
+                expanded
+            } else {
+                reflected
+            };
+        } else if reflected.value() > simplex[dimensions - 1].value() {
+            simplex[dimensions] = reflected;
+        } else {
+            let contracted = if reflected.value() > worst.value() {
+                evaluator.vertex(along(&centroid, &reflected.x, 0.5))?
+            } else {
+                evaluator.vertex(along(&centroid, &worst.x, 0.5))?
+            };
+            if contracted.value() > reflected.value().max(worst.value()) {
+                simplex[dimensions] = contracted;
+            } else {
+                // Shrink towards the best vertex.
+                let best = simplex[0].x.clone();
+                let points: Vec<Vec<f64>> = simplex[1..]
+                    .iter()
+                    .map(|vertex| along(&best, &vertex.x, 0.5))
+                    .collect();
+                let values = evaluator.evaluate(&points)?;
+                for (vertex, (x, value)) in
+                    simplex[1..].iter_mut().zip(points.into_iter().zip(values))
+                {
+                    *vertex = Vertex {
+                        x,
+                        total: value,
+                        samples: 1,
+                    };
+                }
+            }
+        }
+
+        // Measure the best vertex again, so that noise does not keep it in the simplex.
+        let best = simplex
+            .iter_mut()
+            .max_by(|a, b| a.value().total_cmp(&b.value()))
+            .unwrap();
+        best.total += evaluator.evaluate(&[best.x.clone()])?[0];
+        best.samples += 1;
+    }
+
+    simplex.sort_by(|a, b| b.value().total_cmp(&a.value()));
+    let best = &simplex[0];
+    let parameters = description.values(&best.x);
+    Ok(Optimum {
+        config: with_parameters(&evaluator.defaults, &parameters)?,
+        parameters,
+        objective: best.value(),
+        evaluations: evaluator.evaluations,
+    })
+}
+
+/// Writes each evaluation as a row of a csv file, as it is made.
+pub struct EvaluationLog<W> {
+    writer: W,
+    rows: usize,
+}
+
+impl<W: Write> EvaluationLog<W> {
+    pub fn new(writer: W) -> Self {
+        EvaluationLog { writer, rows: 0 }
+    }
+
+    pub fn write(&mut self, evaluation: &Evaluation) -> std::io::Result<()> {
+        if self.rows == 0 {
+            let mut header: Vec<&str> = vec!["evaluation"];
+            header.extend(evaluation.parameters.iter().map(|(name, _)| name.as_str()));
+            header.extend([
+                "objective",
+                "flux",
+                "mean_velocity",
+                "capture_fraction",
+                "atom_number",
+            ]);
+            writeln!(self.writer, "{}", header.join(","))?;
+        }
+        self.rows += 1;
+        let d = &evaluation.diagnostics;
+        let mut row: Vec<String> = vec![self.rows.to_string()];
+        row.extend(
+            evaluation
+                .parameters
+                .iter()
+                .map(|(_, value)| value.to_string()),
+        );
+        row.extend(
+            [
+                evaluation.objective,
+                d.flux,
+                d.mean_velocity,
+                d.capture_fraction,
+                d.atom_number,
+            ]
+            .iter()
+            .map(|value| value.to_string()),
+        );
+        writeln!(self.writer, "{}", row.join(","))?;
+        self.writer.flush()
+    }
+}
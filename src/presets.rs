@@ -0,0 +1,237 @@
+//! Named presets of experiment parameters.
+//!
+//! Presets are stored as json, in the `presets/<experiment>` directory natively, and in the
+//! browser's `localStorage` on wasm.
+
+use std::marker::PhantomData;
+
+use bevy::asset::Error;
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::experiment::ExperimentControls;
+
+/// Saves and loads presets for a single experiment.
+pub struct PresetStore {
+    experiment: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PresetStore {
+    fn directory(&self) -> std::path::PathBuf {
+        std::path::Path::new("presets").join(&self.experiment)
+    }
+
+    fn path(&self, name: &str) -> std::path::PathBuf {
+        self.directory().join(format!("{}.json", name))
+    }
+
+    /// Names of the saved presets, in alphabetical order.
+    pub fn list(&self) -> Result<Vec<String>, Error> {
+        let directory = self.directory();
+        if !directory.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == "json") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn read(&self, name: &str) -> Result<String, Error> {
+        Ok(std::fs::read_to_string(self.path(name))?)
+    }
+
+    pub fn write(&self, name: &str, json: &str) -> Result<(), Error> {
+        std::fs::create_dir_all(self.directory())?;
+        Ok(std::fs::write(self.path(name), json)?)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), Error> {
+        Ok(std::fs::remove_file(self.path(name))?)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl PresetStore {
+    fn storage(&self) -> Result<web_sys::Storage, Error> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| Error::msg("Local storage is not available."))
+    }
+
+    fn prefix(&self) -> String {
+        format!("atomecs_demos/presets/{}/", self.experiment)
+    }
+
+    /// Names of the saved presets, in alphabetical order.
+    pub fn list(&self) -> Result<Vec<String>, Error> {
+        let storage = self.storage()?;
+        let prefix = self.prefix();
+        let length = storage.length().map_err(js_error)?;
+        let mut names = Vec::new();
+        for i in 0..length {
+            if let Some(key) = storage.key(i).map_err(js_error)? {
+                if let Some(name) = key.strip_prefix(&prefix) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn read(&self, name: &str) -> Result<String, Error> {
+        self.storage()?
+            .get_item(&(self.prefix() + name))
+            .map_err(js_error)?
+            .ok_or_else(|| Error::msg(format!("No preset named {}.", name)))
+    }
+
+    pub fn write(&self, name: &str, json: &str) -> Result<(), Error> {
+        self.storage()?
+            .set_item(&(self.prefix() + name), json)
+            .map_err(js_error)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), Error> {
+        self.storage()?
+            .remove_item(&(self.prefix() + name))
+            .map_err(js_error)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn js_error(error: wasm_bindgen::JsValue) -> Error {
+    Error::msg(format!("{:?}", error))
+}
+
+/// Presets of the experiment parameters `C`, and the state of the widgets that manage them.
+#[derive(Resource)]
+pub struct Presets<C> {
+    store: PresetStore,
+    names: Vec<String>,
+    /// Name of the preset to load, or to save the current parameters as.
+    pub name: String,
+    /// Result of the last action, shown below the widgets.
+    status: String,
+    marker: PhantomData<C>,
+}
+
+impl<C: ExperimentControls> Presets<C> {
+    /// Presets for the named experiment, which must be a valid file name.
+    pub fn new(experiment: &str) -> Self {
+        let mut presets = Presets {
+            store: PresetStore {
+                experiment: experiment.to_string(),
+            },
+            names: Vec::new(),
+            name: String::new(),
+            status: String::new(),
+            marker: PhantomData,
+        };
+        presets.refresh();
+        presets
+    }
+
+    /// Names of the saved presets.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn refresh(&mut self) {
+        match self.store.list() {
+            Ok(names) => self.names = names,
+            Err(error) => self.report(format!("Could not list presets: {}", error)),
+        }
+    }
+
+    fn report(&mut self, status: String) {
+        warn!("{}", status);
+        self.status = status;
+    }
+
+    pub fn save(&mut self, name: &str, config: &C) -> Result<(), Error> {
+        if name.is_empty() || name.contains(['/', '\\', '.']) {
+            return Err(Error::msg(format!("Invalid preset name '{}'.", name)));
+        }
+        let json = serde_json::to_string_pretty(config)?;
+        self.store.write(name, &json)?;
+        self.refresh();
+        Ok(())
+    }
+
+    pub fn load(&self, name: &str) -> Result<C, Error> {
+        let json = self.store.read(name)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), Error> {
+        self.store.remove(name)?;
+        self.refresh();
+        Ok(())
+    }
+}
+
+/// Widgets to save, load and delete presets, and to reset the parameters to their defaults.
+pub fn presets_ui<C: ExperimentControls>(
+    ui: &mut egui::Ui,
+    config: &mut ResMut<C>,
+    presets: &mut Presets<C>,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Preset:");
+        ui.text_edit_singleline(&mut presets.name);
+        egui::ComboBox::from_id_source("presets")
+            .selected_text("Saved")
+            .show_ui(ui, |ui| {
+                for name in presets.names.clone() {
+                    if ui.selectable_label(presets.name == name, &name).clicked() {
+                        presets.name = name;
+                    }
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        let name = presets.name.clone();
+        let exists = presets.names.contains(&name);
+        if ui.button("Save").clicked() {
+            match presets.save(&name, &**config) {
+                Ok(()) => presets.status = format!("Saved preset {}.", name),
+                Err(error) => presets.report(format!("Could not save preset: {}", error)),
+            }
+        }
+        if ui.add_enabled(exists, egui::Button::new("Load")).clicked() {
+            match presets.load(&name) {
+                Ok(loaded) => {
+                    **config = loaded;
+                    presets.status = format!("Loaded preset {}.", name);
+                }
+                Err(error) => presets.report(format!("Could not load preset: {}", error)),
+            }
+        }
+        if ui
+            .add_enabled(exists, egui::Button::new("Delete"))
+            .clicked()
+        {
+            match presets.delete(&name) {
+                Ok(()) => presets.status = format!("Deleted preset {}.", name),
+                Err(error) => presets.report(format!("Could not delete preset: {}", error)),
+            }
+        }
+        if ui.button("Reset to defaults").clicked() {
+            **config = C::default();
+            presets.status = "Reset to defaults.".to_string();
+        }
+    });
+    if !presets.status.is_empty() {
+        ui.label(&presets.status);
+    }
+}
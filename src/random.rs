@@ -0,0 +1,86 @@
+//! Seeded random numbers, so that runs can be reproduced.
+//!
+//! All atom sources in this crate draw from the [SimulationRng] resource. The seed is taken from a
+//! `--seed <n>` command line argument or the scene file, and is otherwise chosen at random and
+//! logged so the run can be repeated.
+//!
+//! AtomECS draws its own random numbers for scattering fluctuations and the emission force, which
+//! cannot be seeded. Runs are only bit-identical when both are disabled.
+
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+/// The random number generator used to sample atoms.
+#[derive(Resource)]
+pub struct SimulationRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl SimulationRng {
+    pub fn seeded(seed: u64) -> Self {
+        SimulationRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// A generator with a random seed.
+    pub fn from_entropy() -> Self {
+        Self::seeded(rand::thread_rng().gen())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the sequence of random numbers from the seed.
+    pub fn reseed(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+    }
+}
+
+impl Default for SimulationRng {
+    /// Seeded from the command line if a seed is given, otherwise at random.
+    fn default() -> Self {
+        let rng = match seed_from_args() {
+            Some(seed) => Self::seeded(seed),
+            None => Self::from_entropy(),
+        };
+        info!("Random seed: {}", rng.seed);
+        rng
+    }
+}
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Reads the seed from a `--seed <n>` command line argument.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    let index = args.iter().position(|arg| arg == "--seed")?;
+    let value = args.get(index + 1)?;
+    match value.parse() {
+        Ok(seed) => Some(seed),
+        Err(_) => panic!("Could not parse seed {}", value),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn seed_from_args() -> Option<u64> {
+    None
+}
@@ -0,0 +1,567 @@
+//! Data-driven description of an experiment.
+//!
+//! A [SceneDescription] lists the atomic species, laser beams, magnetic fields, simulation regions
+//! and atom sources of an experiment, together with the timestep, render scale and camera. Scenes are
+//! written as json files (see `assets/scenes`) and spawned by the [ScenePlugin], so that new
+//! experiment geometries can be built without recompiling.
+
+use std::marker::PhantomData;
+
+use atomecs::atom::{Atom, Force, Mass, Position, Velocity};
+use atomecs::bevy_bridge::Scale;
+use atomecs::initiate::NewlyCreated;
+use atomecs::integrator::Timestep;
+use atomecs::laser::gaussian::GaussianBeam;
+use atomecs::laser::LaserPlugin;
+use atomecs::laser_cooling::force::{EmissionForceConfiguration, EmissionForceOption};
+use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::laser_cooling::{CoolingLight, LaserCoolingPlugin};
+use atomecs::magnetic::force::MagneticDipole;
+use atomecs::magnetic::quadrupole::{QuadrupoleField2D, QuadrupoleField3D};
+use atomecs::magnetic::top::UniformFieldRotator;
+use atomecs::magnetic::uniform::UniformMagneticField;
+use atomecs::shapes::{Cuboid, Sphere};
+use atomecs::sim_region::{SimulationRegionPlugin, SimulationVolume, VolumeType};
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use nalgebra::{Unit, Vector3};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::DemoCamera;
+use crate::controls::SetupAppExt;
+use crate::fields::{FieldGridPlugin, MagneticFieldGrid};
+use crate::random::{seed_from_args, SimulationRng};
+use crate::sequence::{Sequence, SequenceTag, Sequencer, SequencerPlugin};
+use crate::sources::{AtomWeight, ContinuousSource, SourcePlugin};
+use crate::stepping::SimulationSpeed;
+
+/// Atomic species (and cooling transition) simulated in a scene.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Species {
+    Strontium88_461,
+    Rubidium87_780D2,
+}
+
+/// Complete description of an experiment.
+#[derive(Serialize, Deserialize, Clone, Resource)]
+pub struct SceneDescription {
+    /// Name of the experiment, used for window titles.
+    #[serde(default)]
+    pub name: String,
+    pub species: Species,
+    /// Integration timestep, in units of s.
+    pub timestep: f64,
+    /// Conversion from simulation units (m) to rendered units.
+    pub scale: f64,
+    /// Target ratio of simulated time to real time.
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// Seed for the random numbers used to sample atoms. Overridden by a `--seed` command line
+    /// argument, and chosen at random if neither is given.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Number of real atoms represented by each simulated atom.
+    #[serde(default = "default_atom_weight")]
+    pub atom_weight: f64,
+    #[serde(default = "default_true")]
+    pub scattering_fluctuations: bool,
+    /// Threshold for the explicit calculation of emission forces. `None` disables emission forces.
+    #[serde(default = "default_emission_threshold")]
+    pub emission_force_threshold: Option<u64>,
+    #[serde(default)]
+    pub beams: Vec<BeamDescription>,
+    #[serde(default)]
+    pub fields: Vec<FieldDescription>,
+    #[serde(default)]
+    pub regions: Vec<RegionDescription>,
+    #[serde(default)]
+    pub sources: Vec<SourceDescription>,
+    /// Timing sequence that ramps the beams and fields.
+    #[serde(default)]
+    pub sequence: Option<Sequence>,
+    #[serde(default)]
+    pub camera: CameraDescription,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_speed() -> f64 {
+    SimulationSpeed::default().target_ratio
+}
+
+fn default_atom_weight() -> f64 {
+    1.0
+}
+
+fn default_emission_threshold() -> Option<u64> {
+    Some(5)
+}
+
+/// A gaussian cooling beam.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BeamDescription {
+    /// A point the beam passes through, in units of m.
+    #[serde(default)]
+    pub intersection: [f64; 3],
+    /// Propagation direction. Does not need to be normalised.
+    pub direction: [f64; 3],
+    /// 1/e radius of the beam, in units of m.
+    pub e_radius: f64,
+    /// Power of the beam, in units of W.
+    pub power: f64,
+    /// Detuning from the cooling transition, in units of MHz.
+    pub detuning: f64,
+    /// Circular polarisation, either +1 or -1.
+    pub polarization: i32,
+    /// Rayleigh range of the beam, in units of m. Collimated if not specified.
+    #[serde(default)]
+    pub rayleigh_range: Option<f64>,
+    /// Tag used to select the beam in a [Sequence].
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+/// A source of magnetic field.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum FieldDescription {
+    /// Three-dimensional quadrupole field, gradient in units of G/cm.
+    Quadrupole3D {
+        #[serde(default)]
+        position: [f64; 3],
+        gradient: f64,
+        axis: [f64; 3],
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// Two-dimensional quadrupole field, gradient in units of G/cm.
+    Quadrupole2D {
+        #[serde(default)]
+        position: [f64; 3],
+        gradient: f64,
+        axis: [f64; 3],
+        field_axis: [f64; 3],
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// Uniform bias field, in units of G.
+    Uniform {
+        field: [f64; 3],
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// Rotating bias field of a TOP trap, amplitude in units of G and frequency in units of Hz.
+    Rotating { amplitude: f64, frequency: f64 },
+    /// Precalculated field grid, loaded as a [MagneticFieldGrid] asset from a path relative to the `assets` directory.
+    Grid { path: String },
+}
+
+/// A region used to bound the simulation.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum RegionDescription {
+    Cuboid {
+        position: [f64; 3],
+        half_width: [f64; 3],
+        #[serde(default)]
+        exclusive: bool,
+    },
+    Sphere {
+        position: [f64; 3],
+        radius: f64,
+        #[serde(default)]
+        exclusive: bool,
+    },
+}
+
+/// A source of atoms.
+///
+/// Positions and velocities are drawn from normal distributions with the given means and standard deviations.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum SourceDescription {
+    /// A cloud of atoms created when the scene is spawned.
+    Cloud {
+        number: usize,
+        distribution: PhaseSpaceDistribution,
+    },
+    /// Atoms emitted continuously, at a rate in units of real atoms/s.
+    Continuous {
+        rate: f64,
+        distribution: PhaseSpaceDistribution,
+    },
+}
+
+/// Gaussian distribution of atoms in phase space.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PhaseSpaceDistribution {
+    /// Mean position, in units of m.
+    pub position: [f64; 3],
+    /// Standard deviation of position, in units of m.
+    #[serde(default)]
+    pub position_spread: [f64; 3],
+    /// Mean velocity, in units of m/s.
+    #[serde(default)]
+    pub velocity: [f64; 3],
+    /// Standard deviation of velocity, in units of m/s.
+    #[serde(default)]
+    pub velocity_spread: [f64; 3],
+    /// Mass of the atoms, in units of amu.
+    pub mass: f64,
+    /// Magnetic moment of the atoms, as the product of mF and gF. Atoms are not magnetically trapped if not specified.
+    #[serde(default)]
+    pub magnetic_dipole: Option<f64>,
+}
+
+/// Initial orbit of the [DemoCamera].
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CameraDescription {
+    pub orbit: f32,
+    pub delta: f32,
+    pub radius: f32,
+    /// Point the camera looks at, in units of m.
+    pub target: [f32; 3],
+    /// Scale of the orthographic projection.
+    pub projection_scale: f32,
+    pub near: f32,
+}
+impl Default for CameraDescription {
+    fn default() -> Self {
+        let camera = DemoCamera::default();
+        CameraDescription {
+            orbit: camera.orbit,
+            delta: camera.delta,
+            radius: camera.radius,
+            target: [0.0, 0.0, 0.0],
+            projection_scale: 0.01,
+            near: -10.0,
+        }
+    }
+}
+
+impl SceneDescription {
+    /// Parses a scene from a json string.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Loads a scene from a json file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Self {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Could not read scene file {}: {}", path.display(), e));
+        Self::from_json(&json)
+            .unwrap_or_else(|e| panic!("Could not parse scene file {}: {}", path.display(), e))
+    }
+}
+
+impl PhaseSpaceDistribution {
+    /// Spawns an atom drawn from the distribution.
+    pub fn spawn_atom<T, R>(&self, commands: &mut Commands, rng: &mut R) -> Entity
+    where
+        T: AtomicTransition + Component + Default,
+        R: rand::Rng + ?Sized,
+    {
+        let dist = Normal::new(0.0, 1.0).unwrap();
+        let mut sample = |mean: [f64; 3], spread: [f64; 3]| {
+            Vector3::new(
+                mean[0] + spread[0] * dist.sample(&mut *rng),
+                mean[1] + spread[1] * dist.sample(&mut *rng),
+                mean[2] + spread[2] * dist.sample(&mut *rng),
+            )
+        };
+        let pos = sample(self.position, self.position_spread);
+        let vel = sample(self.velocity, self.velocity_spread);
+        let mut atom = commands.spawn(Position { pos });
+        atom.insert(Velocity { vel })
+            .insert(Force::default())
+            .insert(Mass { value: self.mass })
+            .insert(T::default())
+            .insert(Atom)
+            .insert(NewlyCreated);
+        if let Some(m_f_g_f) = self.magnetic_dipole {
+            atom.insert(MagneticDipole { mFgF: m_f_g_f });
+        }
+        atom.id()
+    }
+}
+
+trait InsertTag {
+    /// Inserts a [SequenceTag], if a tag is given.
+    fn insert_tag(&mut self, tag: &Option<String>) -> &mut Self;
+}
+impl InsertTag for EntityCommands<'_, '_, '_> {
+    fn insert_tag(&mut self, tag: &Option<String>) -> &mut Self {
+        if let Some(tag) = tag {
+            self.insert(SequenceTag(tag.clone()));
+        }
+        self
+    }
+}
+
+fn vector(v: [f64; 3]) -> Vector3<f64> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+/// Maximum number of beams supported in a scene by the demos that load any scene.
+pub const BEAM_NUMBER: usize = 22;
+
+/// Adds the AtomECS plugins and resources required to simulate a [SceneDescription].
+///
+/// `N` is the maximum number of laser beams in the scene.
+pub struct ScenePlugin<T, const N: usize> {
+    pub scene: SceneDescription,
+    phantom: PhantomData<T>,
+}
+impl<T, const N: usize> ScenePlugin<T, N> {
+    pub fn new(scene: SceneDescription) -> Self {
+        assert!(
+            scene.beams.len() <= N,
+            "Scene contains {} beams, but only {} are supported.",
+            scene.beams.len(),
+            N
+        );
+        ScenePlugin {
+            scene,
+            phantom: PhantomData,
+        }
+    }
+}
+impl<T, const N: usize> Plugin for ScenePlugin<T, N>
+where
+    T: AtomicTransition + Component + Default + Copy,
+{
+    fn build(&self, app: &mut App) {
+        app.add_plugin(atomecs::integrator::IntegrationPlugin);
+        app.add_plugin(atomecs::initiate::InitiatePlugin);
+        app.add_plugin(atomecs::magnetic::MagneticsPlugin);
+        app.add_plugin(LaserPlugin::<{ N }>);
+        app.add_plugin(LaserCoolingPlugin::<T, { N }>::default());
+        app.add_plugin(SimulationRegionPlugin);
+        app.add_plugin(FieldGridPlugin);
+        if let Some(seed) = seed_from_args().or(self.scene.seed) {
+            app.insert_resource(SimulationRng::seeded(seed));
+            if self.scene.scattering_fluctuations || self.scene.emission_force_threshold.is_some() {
+                warn!("Scattering fluctuations and emission forces are not seeded, so runs will not be reproducible.");
+            }
+        }
+        app.insert_resource(AtomWeight(self.scene.atom_weight));
+        app.add_plugin(SourcePlugin::<T>::default());
+        app.insert_resource(Scale {
+            0: self.scene.scale,
+        });
+        app.insert_resource(Timestep {
+            delta: self.scene.timestep,
+        });
+        app.insert_resource(SimulationSpeed::with_target_ratio(self.scene.speed));
+        match self.scene.emission_force_threshold {
+            Some(threshold) => {
+                app.insert_resource(EmissionForceOption::On(EmissionForceConfiguration {
+                    explicit_threshold: threshold,
+                }))
+            }
+            None => app.insert_resource(EmissionForceOption::Off),
+        };
+        if self.scene.scattering_fluctuations {
+            app.insert_resource(ScatteringFluctuationsOption::On);
+        } else {
+            app.insert_resource(ScatteringFluctuationsOption::Off);
+        }
+        if let Some(sequence) = &self.scene.sequence {
+            app.add_plugin(SequencerPlugin::<T>::default());
+            app.insert_resource(Sequencer::new(sequence.clone()));
+        }
+        app.insert_resource(self.scene.clone());
+        app.add_setup_system(spawn_scene::<T>);
+    }
+}
+
+/// Spawns the beams, fields, regions and atom sources of the [SceneDescription].
+pub fn spawn_scene<T>(
+    mut commands: Commands,
+    scene: Res<SceneDescription>,
+    asset_server: Res<AssetServer>,
+    mut rng: ResMut<SimulationRng>,
+) where
+    T: AtomicTransition + Component + Default,
+{
+    for beam in scene.beams.iter() {
+        commands
+            .spawn(GaussianBeam {
+                intersection: vector(beam.intersection),
+                e_radius: beam.e_radius,
+                power: beam.power,
+                direction: vector(beam.direction).normalize(),
+                rayleigh_range: beam.rayleigh_range.unwrap_or(f64::INFINITY),
+                ellipticity: 0.0,
+            })
+            .insert(CoolingLight::for_transition::<T>(
+                beam.detuning,
+                beam.polarization,
+            ))
+            .insert_tag(&beam.tag);
+    }
+
+    for field in scene.fields.iter() {
+        match field {
+            FieldDescription::Quadrupole3D {
+                position,
+                gradient,
+                axis,
+                tag,
+            } => {
+                commands
+                    .spawn(QuadrupoleField3D::gauss_per_cm(
+                        *gradient,
+                        vector(*axis).normalize(),
+                    ))
+                    .insert(Position {
+                        pos: vector(*position),
+                    })
+                    .insert_tag(tag);
+            }
+            FieldDescription::Quadrupole2D {
+                position,
+                gradient,
+                axis,
+                field_axis,
+                tag,
+            } => {
+                commands
+                    .spawn(QuadrupoleField2D::gauss_per_cm(
+                        *gradient,
+                        Unit::new_normalize(vector(*axis)),
+                        Unit::new_normalize(vector(*field_axis)),
+                    ))
+                    .insert(Position {
+                        pos: vector(*position),
+                    })
+                    .insert_tag(tag);
+            }
+            FieldDescription::Uniform { field, tag } => {
+                commands
+                    .spawn(UniformMagneticField::gauss(vector(*field)))
+                    .insert_tag(tag);
+            }
+            FieldDescription::Rotating {
+                amplitude,
+                frequency,
+            } => {
+                commands
+                    .spawn(UniformFieldRotator {
+                        amplitude: *amplitude,
+                        frequency: *frequency,
+                    })
+                    .insert(UniformMagneticField {
+                        field: Vector3::zeros(),
+                    });
+            }
+            FieldDescription::Grid { path } => {
+                commands.spawn(asset_server.load::<MagneticFieldGrid, _>(path.as_str()));
+            }
+        }
+    }
+
+    for region in scene.regions.iter() {
+        let (position, exclusive) = match region {
+            RegionDescription::Cuboid {
+                position,
+                exclusive,
+                ..
+            } => (position, exclusive),
+            RegionDescription::Sphere {
+                position,
+                exclusive,
+                ..
+            } => (position, exclusive),
+        };
+        let mut entity = commands.spawn(Position {
+            pos: vector(*position),
+        });
+        entity.insert(SimulationVolume {
+            volume_type: if *exclusive {
+                VolumeType::Exclusive
+            } else {
+                VolumeType::Inclusive
+            },
+        });
+        match region {
+            RegionDescription::Cuboid { half_width, .. } => {
+                entity.insert(Cuboid {
+                    half_width: vector(*half_width),
+                });
+            }
+            RegionDescription::Sphere { radius, .. } => {
+                entity.insert(Sphere { radius: *radius });
+            }
+        }
+    }
+
+    for source in scene.sources.iter() {
+        match source {
+            SourceDescription::Cloud {
+                number,
+                distribution,
+            } => {
+                for _ in 0..*number {
+                    distribution.spawn_atom::<T, _>(&mut commands, &mut *rng);
+                }
+            }
+            SourceDescription::Continuous { rate, distribution } => {
+                commands.spawn(ContinuousSource::new(*rate, distribution.clone()));
+            }
+        }
+    }
+}
+
+/// Spawns the camera and lighting described by the [SceneDescription].
+pub fn spawn_scene_camera(mut commands: Commands, scene: Res<SceneDescription>, scale: Res<Scale>) {
+    let description = &scene.camera;
+    let demo_cam = DemoCamera {
+        orbit: description.orbit,
+        delta: description.delta,
+        radius: description.radius,
+        target: Vec3::from(description.target),
+        ..default()
+    };
+    commands
+        .spawn(Camera3dBundle {
+            projection: OrthographicProjection {
+                scale: description.projection_scale,
+                near: description.near,
+                ..default()
+            }
+            .into(),
+            transform: demo_cam.get_transform(scale.0 as f32),
+            ..default()
+        })
+        .insert(demo_cam);
+
+    const HALF_SIZE: f32 = 10.0;
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: 30000.0,
+            // Configure the projection to better fit the scene
+            shadow_projection: OrthographicProjection {
+                left: -HALF_SIZE,
+                right: HALF_SIZE,
+                bottom: -HALF_SIZE,
+                top: HALF_SIZE,
+                near: -20.0 * HALF_SIZE,
+                far: 20.0 * HALF_SIZE,
+                ..default()
+            },
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 2.0, 0.0),
+            rotation: Quat::from_rotation_y(2.2) * Quat::from_rotation_x(-1.2),
+            ..default()
+        },
+        ..default()
+    });
+}
@@ -0,0 +1,564 @@
+//! Experiment timing sequences.
+//!
+//! A [Sequence] is a list of phases, such as loading a MOT, compressing it, molasses, release
+//! and imaging. During each phase, [Ramp]s move the power and detuning of cooling beams, quadrupole
+//! gradients and bias fields to new values over simulated time. Ramps act on every entity of the
+//! right kind, or only on those with a matching [SequenceTag].
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use atomecs::constant::C;
+use atomecs::laser::gaussian::GaussianBeam;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use atomecs::laser_cooling::CoolingLight;
+use atomecs::magnetic::quadrupole::{QuadrupoleField2D, QuadrupoleField3D};
+use atomecs::magnetic::top::UniformFieldRotator;
+use atomecs::magnetic::uniform::UniformMagneticField;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::stepping::{add_presentation_stage, PresentationStage, SimulationClock};
+
+/// Labels an entity so that ramps can select it.
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct SequenceTag(pub String);
+
+/// A list of phases run one after another.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Sequence {
+    pub phases: Vec<Phase>,
+    /// Start again from the first phase once the sequence ends.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Phase {
+    pub name: String,
+    /// Duration of the phase, in units of s.
+    pub duration: f64,
+    #[serde(default)]
+    pub ramps: Vec<Ramp>,
+}
+
+/// Moves a quantity to a new value over the course of a phase.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ramp {
+    pub target: RampTarget,
+    /// Value at the end of the phase.
+    pub to: f64,
+    /// Value at the start of the phase. Continues from the current value if not specified.
+    #[serde(default)]
+    pub from: Option<f64>,
+    #[serde(default)]
+    pub shape: RampShape,
+}
+
+/// A quantity that can be ramped. Without a tag, all entities of the right kind are affected.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum RampTarget {
+    /// Power of cooling beams, in units of W.
+    BeamPower {
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// Detuning of cooling beams from the cooling transition, in units of MHz.
+    BeamDetuning {
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// Gradient of quadrupole fields, in units of G/cm.
+    QuadrupoleGradient {
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// One component of uniform bias fields, in units of G.
+    BiasField {
+        #[serde(default)]
+        tag: Option<String>,
+        axis: Axis,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+impl Axis {
+    fn index(&self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum RampShape {
+    Linear,
+    /// Exponential approach to the final value with the given time constant, in units of s.
+    ///
+    /// The ramp is rescaled so the final value is reached at the end of the phase.
+    Exponential {
+        time_constant: f64,
+    },
+    /// Jumps to the final value at the start of the phase.
+    Step,
+}
+impl Default for RampShape {
+    fn default() -> Self {
+        RampShape::Linear
+    }
+}
+
+impl RampShape {
+    /// Value at `time` (in s) into a phase of the given `duration`.
+    pub fn interpolate(&self, from: f64, to: f64, time: f64, duration: f64) -> f64 {
+        if duration <= 0.0 || time >= duration {
+            return to;
+        }
+        let fraction = match self {
+            RampShape::Linear => time / duration,
+            RampShape::Exponential { time_constant } => {
+                (1.0 - (-time / time_constant).exp()) / (1.0 - (-duration / time_constant).exp())
+            }
+            RampShape::Step => 1.0,
+        };
+        from + (to - from) * fraction
+    }
+}
+
+impl Sequence {
+    pub fn duration(&self) -> f64 {
+        self.phases.iter().map(|phase| phase.duration).sum()
+    }
+
+    /// Time within the sequence, after wrapping repeating sequences.
+    fn wrap(&self, time: f64) -> f64 {
+        let duration = self.duration();
+        if self.repeat && duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            time
+        }
+    }
+
+    /// Index of the phase running at `time` (in s) since the sequence started, and the time into that phase.
+    ///
+    /// Returns `None` once the sequence has finished.
+    pub fn phase_at(&self, time: f64) -> Option<(usize, f64)> {
+        let time = self.wrap(time);
+        let mut start = 0.0;
+        for (i, phase) in self.phases.iter().enumerate() {
+            if time < start + phase.duration {
+                return Some((i, time - start));
+            }
+            start += phase.duration;
+        }
+        None
+    }
+
+    /// Value of `target` at `time` (in s) since the sequence started, given its value when the sequence started.
+    pub fn value(&self, target: &RampTarget, initial: f64, time: f64) -> f64 {
+        let time = self.wrap(time);
+        let mut value = initial;
+        let mut start = 0.0;
+        for phase in self.phases.iter() {
+            if time < start {
+                break;
+            }
+            for ramp in phase.ramps.iter().filter(|ramp| &ramp.target == target) {
+                let from = ramp.from.unwrap_or(value);
+                value = ramp
+                    .shape
+                    .interpolate(from, ramp.to, time - start, phase.duration);
+            }
+            start += phase.duration;
+        }
+        value
+    }
+
+    fn targets(&self) -> Vec<RampTarget> {
+        let mut targets: Vec<RampTarget> = Vec::new();
+        for ramp in self.phases.iter().flat_map(|phase| phase.ramps.iter()) {
+            if !targets.contains(&ramp.target) {
+                targets.push(ramp.target.clone());
+            }
+        }
+        targets
+    }
+}
+
+/// Runs a [Sequence] against the [SimulationClock].
+#[derive(Resource)]
+pub struct Sequencer {
+    pub sequence: Sequence,
+    /// Simulated time the sequence started, in units of s.
+    start: Option<f64>,
+    /// Value of each target when the sequence started.
+    initial: HashMap<RampTarget, f64>,
+}
+
+impl Sequencer {
+    pub fn new(sequence: Sequence) -> Self {
+        Sequencer {
+            sequence,
+            start: None,
+            initial: HashMap::new(),
+        }
+    }
+
+    /// Time since the sequence started, in units of s.
+    pub fn elapsed(&self, clock: &SimulationClock) -> f64 {
+        self.start.map_or(0.0, |start| clock.time - start)
+    }
+
+    /// Starts the sequence again from the first phase, at the current simulated time.
+    pub fn restart(&mut self) {
+        self.start = None;
+    }
+}
+
+fn matches(tag: &Option<String>, entity_tag: Option<&SequenceTag>) -> bool {
+    match tag {
+        None => true,
+        Some(tag) => entity_tag.map_or(false, |entity_tag| &entity_tag.0 == tag),
+    }
+}
+
+fn detuning<T: AtomicTransition>(light: &CoolingLight) -> f64 {
+    (C / light.wavelength - T::frequency()) / 1e6
+}
+
+/// Sets beams and fields to the values given by the [Sequencer] at the current simulated time.
+pub fn apply_sequence<T>(
+    sequencer: Option<ResMut<Sequencer>>,
+    clock: Res<SimulationClock>,
+    mut beams: Query<(&mut GaussianBeam, &mut CoolingLight, Option<&SequenceTag>)>,
+    mut quadrupoles_3d: Query<(&mut QuadrupoleField3D, Option<&SequenceTag>)>,
+    mut quadrupoles_2d: Query<(&mut QuadrupoleField2D, Option<&SequenceTag>)>,
+    mut bias_fields: Query<
+        (&mut UniformMagneticField, Option<&SequenceTag>),
+        Without<UniformFieldRotator>,
+    >,
+) where
+    T: AtomicTransition,
+{
+    let mut sequencer = match sequencer {
+        Some(sequencer) => sequencer,
+        None => return,
+    };
+    // The clock goes backwards when the simulation is reset.
+    if sequencer.start.map_or(true, |start| clock.time < start) {
+        sequencer.start = Some(clock.time);
+    }
+
+    // Record the value of each target before the sequence first changes it.
+    for target in sequencer.sequence.targets() {
+        if sequencer.initial.contains_key(&target) {
+            continue;
+        }
+        let initial = match &target {
+            RampTarget::BeamPower { tag } => beams
+                .iter()
+                .find(|(_, _, entity_tag)| matches(tag, *entity_tag))
+                .map(|(beam, _, _)| beam.power),
+            RampTarget::BeamDetuning { tag } => beams
+                .iter()
+                .find(|(_, _, entity_tag)| matches(tag, *entity_tag))
+                .map(|(_, light, _)| detuning::<T>(light)),
+            RampTarget::QuadrupoleGradient { tag } => quadrupoles_3d
+                .iter()
+                .find(|(_, entity_tag)| matches(tag, *entity_tag))
+                .map(|(quadrupole, _)| quadrupole.gradient)
+                .or_else(|| {
+                    quadrupoles_2d
+                        .iter()
+                        .find(|(_, entity_tag)| matches(tag, *entity_tag))
+                        .map(|(quadrupole, _)| quadrupole.gradient)
+                })
+                .map(|gradient| gradient * 100.0),
+            RampTarget::BiasField { tag, axis } => bias_fields
+                .iter()
+                .find(|(_, entity_tag)| matches(tag, *entity_tag))
+                .map(|(field, _)| field.field[axis.index()] * 1e4),
+        };
+        if let Some(initial) = initial {
+            sequencer.initial.insert(target, initial);
+        }
+    }
+
+    let time = sequencer.elapsed(&clock);
+    for (target, initial) in sequencer.initial.iter() {
+        let value = sequencer.sequence.value(target, *initial, time);
+        match target {
+            RampTarget::BeamPower { tag } => {
+                for (mut beam, _, entity_tag) in beams.iter_mut() {
+                    if matches(tag, entity_tag) && beam.power != value {
+                        beam.power = value;
+                    }
+                }
+            }
+            RampTarget::BeamDetuning { tag } => {
+                let wavelength = CoolingLight::for_transition::<T>(value, 1).wavelength;
+                for (_, mut light, entity_tag) in beams.iter_mut() {
+                    if matches(tag, entity_tag) && light.wavelength != wavelength {
+                        light.wavelength = wavelength;
+                    }
+                }
+            }
+            RampTarget::QuadrupoleGradient { tag } => {
+                // G/cm to T/m.
+                let gradient = 0.01 * value;
+                for (mut quadrupole, entity_tag) in quadrupoles_3d.iter_mut() {
+                    if matches(tag, entity_tag) && quadrupole.gradient != gradient {
+                        quadrupole.gradient = gradient;
+                    }
+                }
+                for (mut quadrupole, entity_tag) in quadrupoles_2d.iter_mut() {
+                    if matches(tag, entity_tag) && quadrupole.gradient != gradient {
+                        quadrupole.gradient = gradient;
+                    }
+                }
+            }
+            RampTarget::BiasField { tag, axis } => {
+                // G to T.
+                let field = 1e-4 * value;
+                for (mut bias, entity_tag) in bias_fields.iter_mut() {
+                    if matches(tag, entity_tag) && bias.field[axis.index()] != field {
+                        bias.field[axis.index()] = field;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Window showing the phases of the sequence, with the current phase highlighted.
+pub fn sequence_timeline(
+    mut egui_context: ResMut<EguiContext>,
+    sequencer: Option<ResMut<Sequencer>>,
+    clock: Res<SimulationClock>,
+) {
+    let mut sequencer = match sequencer {
+        Some(sequencer) => sequencer,
+        None => return,
+    };
+    let duration = sequencer.sequence.duration();
+    if duration <= 0.0 {
+        return;
+    }
+    let time = sequencer.elapsed(&clock);
+    let current = sequencer.sequence.phase_at(time);
+    let mut restart = false;
+    egui::Window::new("Sequence").show(egui_context.ctx_mut(), |ui| {
+        match current {
+            Some((index, time_in_phase)) => {
+                let phase = &sequencer.sequence.phases[index];
+                ui.label(format!(
+                    "{}: {:.2} of {:.2} ms",
+                    phase.name,
+                    1e3 * time_in_phase,
+                    1e3 * phase.duration
+                ));
+            }
+            None => {
+                ui.label("Sequence finished");
+            }
+        }
+
+        let width = ui.available_width().max(300.0);
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(width, 28.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let x = |t: f64| rect.left() + rect.width() * (t / duration) as f32;
+        let mut start = 0.0;
+        for (i, phase) in sequencer.sequence.phases.iter().enumerate() {
+            let phase_rect = egui::Rect::from_x_y_ranges(
+                x(start)..=x(start + phase.duration),
+                rect.top()..=rect.bottom(),
+            );
+            let active = current.map_or(false, |(index, _)| index == i);
+            let fill = if active {
+                egui::Color32::from_rgb(70, 110, 170)
+            } else {
+                egui::Color32::from_gray(60)
+            };
+            painter.rect_filled(phase_rect.shrink(1.0), 2.0, fill);
+            painter.text(
+                phase_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                &phase.name,
+                egui::FontId::proportional(12.0),
+                egui::Color32::WHITE,
+            );
+            start += phase.duration;
+        }
+        let cursor = x(sequencer.sequence.wrap(time).min(duration));
+        painter.vline(
+            cursor,
+            rect.top()..=rect.bottom(),
+            egui::Stroke::new(2.0, egui::Color32::YELLOW),
+        );
+
+        if ui.button("Restart sequence").clicked() {
+            restart = true;
+        }
+    });
+    if restart {
+        sequencer.restart();
+    }
+}
+
+/// Runs the [Sequencer] resource, if one is inserted, for a cooling transition `T`.
+///
+/// Requires the `SteppingPlugin`. Add the [SequenceTimelinePlugin] to show the sequence.
+pub struct SequencerPlugin<T> {
+    phantom: PhantomData<T>,
+}
+impl<T> Default for SequencerPlugin<T> {
+    fn default() -> Self {
+        SequencerPlugin {
+            phantom: PhantomData,
+        }
+    }
+}
+impl<T> Plugin for SequencerPlugin<T>
+where
+    T: AtomicTransition + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        // Runs every step, so ramps are smooth in simulated time.
+        app.add_system(apply_sequence::<T>);
+    }
+}
+
+/// Adds the window showing the phases of the [Sequencer], if one is inserted.
+///
+/// Requires the `SteppingPlugin` and `EguiPlugin`.
+pub struct SequenceTimelinePlugin;
+impl Plugin for SequenceTimelinePlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.add_system_to_stage(PresentationStage, sequence_timeline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn power() -> RampTarget {
+        RampTarget::BeamPower { tag: None }
+    }
+
+    /// Ramps the power linearly to 2, holds it, then steps it to 5.
+    fn sequence(repeat: bool) -> Sequence {
+        Sequence {
+            phases: vec![
+                Phase {
+                    name: "ramp".to_string(),
+                    duration: 1.0,
+                    ramps: vec![Ramp {
+                        target: power(),
+                        to: 2.0,
+                        from: None,
+                        shape: RampShape::Linear,
+                    }],
+                },
+                Phase {
+                    name: "hold".to_string(),
+                    duration: 0.5,
+                    ramps: vec![],
+                },
+                Phase {
+                    name: "jump".to_string(),
+                    duration: 1.0,
+                    ramps: vec![Ramp {
+                        target: power(),
+                        to: 5.0,
+                        from: None,
+                        shape: RampShape::Step,
+                    }],
+                },
+            ],
+            repeat,
+        }
+    }
+
+    #[test]
+    fn linear_ramp() {
+        let shape = RampShape::Linear;
+        assert_eq!(shape.interpolate(1.0, 3.0, 0.0, 2.0), 1.0);
+        assert_eq!(shape.interpolate(1.0, 3.0, 0.5, 2.0), 1.5);
+        assert_eq!(shape.interpolate(1.0, 3.0, 2.0, 2.0), 3.0);
+        assert_eq!(shape.interpolate(1.0, 3.0, 5.0, 2.0), 3.0);
+    }
+
+    #[test]
+    fn exponential_ramp() {
+        let shape = RampShape::Exponential { time_constant: 0.5 };
+        assert_eq!(shape.interpolate(0.0, 1.0, 0.0, 1.0), 0.0);
+        let expected = (1.0 - (-1.0_f64).exp()) / (1.0 - (-2.0_f64).exp());
+        assert!((shape.interpolate(0.0, 1.0, 0.5, 1.0) - expected).abs() < 1e-12);
+        assert!(shape.interpolate(0.0, 1.0, 0.5, 1.0) > 0.5);
+        assert_eq!(shape.interpolate(0.0, 1.0, 1.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn step_ramp() {
+        let shape = RampShape::Step;
+        assert_eq!(shape.interpolate(0.0, 1.0, 0.0, 1.0), 1.0);
+        assert_eq!(shape.interpolate(0.0, 1.0, 0.5, 1.0), 1.0);
+        // Phases without a duration jump straight to the final value.
+        assert_eq!(RampShape::Linear.interpolate(0.0, 1.0, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn values_within_phases() {
+        let sequence = sequence(false);
+        assert_eq!(sequence.value(&power(), 1.0, 0.5), 1.5);
+        assert_eq!(sequence.value(&power(), 1.0, 1.25), 2.0);
+        assert_eq!(sequence.value(&power(), 1.0, 2.0), 5.0);
+        // Targets without ramps keep their initial value.
+        let detuning = RampTarget::BeamDetuning { tag: None };
+        assert_eq!(sequence.value(&detuning, -8.0, 2.0), -8.0);
+    }
+
+    #[test]
+    fn values_at_phase_boundaries() {
+        let sequence = sequence(false);
+        assert_eq!(sequence.value(&power(), 1.0, 0.0), 1.0);
+        assert_eq!(sequence.phase_at(0.0), Some((0, 0.0)));
+        assert_eq!(sequence.value(&power(), 1.0, 1.0), 2.0);
+        assert_eq!(sequence.phase_at(1.0), Some((1, 0.0)));
+        assert_eq!(sequence.value(&power(), 1.0, 1.5), 5.0);
+        assert_eq!(sequence.phase_at(1.5), Some((2, 0.0)));
+        assert_eq!(sequence.value(&power(), 1.0, 2.5), 5.0);
+        assert_eq!(sequence.phase_at(2.5), None);
+    }
+
+    #[test]
+    fn values_outside_the_sequence() {
+        let sequence = sequence(false);
+        assert_eq!(sequence.value(&power(), 1.0, -0.5), 1.0);
+        assert_eq!(sequence.value(&power(), 1.0, 10.0), 5.0);
+        assert_eq!(sequence.phase_at(10.0), None);
+    }
+
+    #[test]
+    fn repeating_sequences_wrap() {
+        let sequence = sequence(true);
+        assert_eq!(sequence.duration(), 2.5);
+        assert_eq!(sequence.value(&power(), 1.0, 2.5), 1.0);
+        assert_eq!(sequence.value(&power(), 1.0, 3.0), 1.5);
+        assert_eq!(sequence.phase_at(3.0), Some((0, 0.5)));
+        assert_eq!(sequence.value(&power(), 1.0, 6.0), 2.0);
+        assert_eq!(sequence.phase_at(6.0), Some((1, 0.0)));
+        assert_eq!(sequence.value(&power(), 1.0, 9.5), 5.0);
+    }
+}
@@ -0,0 +1,307 @@
+//! Shareable links to a configuration of a web demo.
+//!
+//! Parameters of the experiment and the view of the camera are read from the query string of the
+//! page URL when the demo starts, eg `aion_source.html?cooling_beam_detuning=-60&camera=top`. The
+//! camera is set either by a named view, see [DemoCamera::set_view], or by the `camera_orbit`,
+//! `camera_elevation` and `camera_radius` parameters.
+//!
+//! Natively the query string can be given with a `--query <query>` command line argument.
+
+use std::marker::PhantomData;
+
+use atomecs::bevy_bridge::Scale;
+use bevy::prelude::*;
+use bevy_egui::egui;
+use serde_json::Value;
+
+use crate::camera::DemoCamera;
+use crate::experiment::ExperimentControls;
+use crate::stepping::{add_presentation_stage, PresentationStage};
+
+const CAMERA_PARAMETERS: [&str; 4] = [
+    "camera",
+    "camera_orbit",
+    "camera_elevation",
+    "camera_radius",
+];
+
+/// Parameters read from the query string of the page URL.
+#[derive(Resource, Default)]
+pub struct QueryParameters(pub Vec<(String, String)>);
+
+impl QueryParameters {
+    /// Parses a query string, with or without the leading `?`.
+    pub fn parse(query: &str) -> Self {
+        let query = query.strip_prefix('?').unwrap_or(query);
+        QueryParameters(
+            query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    (decode(key), decode(value))
+                })
+                .collect(),
+        )
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_page() -> Self {
+        let search = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+        Self::parse(&search)
+    }
+
+    /// Reads the query string from a `--query <query>` command line argument.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_page() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        match args.iter().position(|arg| arg == "--query") {
+            Some(index) => Self::parse(args.get(index + 1).map_or("", |query| query.as_str())),
+            None => Self::default(),
+        }
+    }
+
+    /// The last value given for the parameter.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the fields of the experiment parameters named in the query.
+    ///
+    /// Returns the names of parameters that are not fields of `C`, or whose values could not be
+    /// parsed.
+    pub fn apply_to<C: ExperimentControls>(&self, config: &mut C) -> Vec<String> {
+        let mut fields = match serde_json::to_value(&*config) {
+            Ok(Value::Object(fields)) => fields,
+            _ => return Vec::new(),
+        };
+        let mut rejected = Vec::new();
+        for (key, value) in self.0.iter() {
+            if CAMERA_PARAMETERS.contains(&key.as_str()) {
+                continue;
+            }
+            let parsed = match fields.get(key) {
+                Some(Value::Bool(_)) => value.parse::<bool>().ok().map(Value::Bool),
+                Some(Value::Number(_)) => value
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(Value::Number),
+                Some(Value::String(_)) => Some(Value::String(value.clone())),
+                _ => None,
+            };
+            match parsed {
+                Some(parsed) => {
+                    fields.insert(key.clone(), parsed);
+                }
+                None => rejected.push(key.clone()),
+            }
+        }
+        match serde_json::from_value(Value::Object(fields)) {
+            Ok(updated) => *config = updated,
+            Err(error) => warn!("Could not apply query parameters: {}", error),
+        }
+        rejected
+    }
+
+    /// Sets the view of the camera from the query.
+    pub fn apply_to_camera(&self, camera: &mut DemoCamera) {
+        if let Some(view) = self.get("camera") {
+            if !camera.set_view(view) {
+                warn!("Unknown camera view '{}'.", view);
+            }
+        }
+        let number = |key: &str| self.get(key).and_then(|value| value.parse::<f32>().ok());
+        if let Some(orbit) = number("camera_orbit") {
+            camera.orbit = orbit;
+        }
+        if let Some(elevation) = number("camera_elevation") {
+            camera.delta = elevation.clamp(-1.4, 1.4);
+        }
+        if let Some(radius) = number("camera_radius") {
+            camera.radius = radius;
+        }
+    }
+}
+
+/// Builds a link to the page that restores the experiment parameters and the camera view.
+///
+/// Only parameters that differ from their defaults are included.
+pub fn shareable_link<C: ExperimentControls>(
+    page_url: &str,
+    config: &C,
+    camera: Option<&DemoCamera>,
+) -> String {
+    let mut parameters = Vec::new();
+    if let (Ok(Value::Object(fields)), Ok(Value::Object(defaults))) = (
+        serde_json::to_value(config),
+        serde_json::to_value(C::default()),
+    ) {
+        for (key, value) in fields.iter() {
+            if defaults.get(key) != Some(value) {
+                let value = match value {
+                    Value::String(string) => string.clone(),
+                    other => other.to_string(),
+                };
+                parameters.push(format!("{}={}", encode(key), encode(&value)));
+            }
+        }
+    }
+    if let Some(camera) = camera {
+        parameters.push(format!("camera_orbit={:.3}", camera.orbit));
+        parameters.push(format!("camera_elevation={:.3}", camera.delta));
+        parameters.push(format!("camera_radius={:.3}", camera.radius));
+    }
+    if parameters.is_empty() {
+        page_url.to_string()
+    } else {
+        format!("{}?{}", page_url, parameters.join("&"))
+    }
+}
+
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(high), Some(low)) => {
+                    decoded.push(16 * high + low);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+fn encode(component: &str) -> String {
+    component
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The page of a demo, used as the base of shareable links.
+#[derive(Resource)]
+pub struct SharePage {
+    pub url: String,
+    /// The last link that was built, shown so it can be copied by hand.
+    pub link: String,
+}
+
+impl SharePage {
+    /// The page of the named demo on GitHub Pages. On wasm, the address of the current page is
+    /// used instead.
+    pub fn new(demo: &str) -> Self {
+        SharePage {
+            url: page_url().unwrap_or_else(|| {
+                format!("https://teamatomecs.github.io/AtomECSDemos/{}.html", demo)
+            }),
+            link: String::new(),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn page_url() -> Option<String> {
+    let location = web_sys::window()?.location();
+    Some(location.origin().ok()? + &location.pathname().ok()?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn page_url() -> Option<String> {
+    None
+}
+
+/// A button that copies a shareable link to the clipboard.
+pub fn share_ui<C: ExperimentControls>(
+    ui: &mut egui::Ui,
+    config: &C,
+    camera: Option<&DemoCamera>,
+    page: &mut SharePage,
+) {
+    if ui.button("Copy shareable link").clicked() {
+        page.link = shareable_link(&page.url, config, camera);
+        ui.output().copied_text = page.link.clone();
+    }
+    if !page.link.is_empty() {
+        // Not all browsers allow the clipboard to be written, so the link can also be copied from here.
+        let mut link = page.link.as_str();
+        ui.text_edit_singleline(&mut link);
+    }
+}
+
+fn apply_query_to_config<C: ExperimentControls>(
+    parameters: Res<QueryParameters>,
+    mut config: ResMut<C>,
+) {
+    if parameters.0.is_empty() {
+        return;
+    }
+    let mut updated = config.clone();
+    for key in parameters.apply_to(&mut updated) {
+        warn!("Ignoring unknown or invalid query parameter '{}'.", key);
+    }
+    *config = updated;
+}
+
+fn apply_query_to_camera(
+    parameters: Res<QueryParameters>,
+    scale: Res<Scale>,
+    mut query: Query<(&mut DemoCamera, &mut Transform), Added<DemoCamera>>,
+) {
+    for (mut camera, mut transform) in query.iter_mut() {
+        parameters.apply_to_camera(&mut camera);
+        let t = camera.get_transform(scale.0 as f32);
+        transform.translation = t.translation;
+        transform.rotation = t.rotation;
+    }
+}
+
+/// Reads the experiment parameters `C` and the camera view from the page URL at startup.
+pub struct SharePlugin<C> {
+    demo: String,
+    marker: PhantomData<C>,
+}
+
+impl<C> SharePlugin<C> {
+    /// Shares the named demo, which is the name of its page in `pages`.
+    pub fn new(demo: &str) -> Self {
+        SharePlugin {
+            demo: demo.to_string(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C: ExperimentControls + FromWorld> Plugin for SharePlugin<C> {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.init_resource::<C>();
+        app.insert_resource(QueryParameters::from_page());
+        app.insert_resource(SharePage::new(&self.demo));
+        app.add_startup_system(apply_query_to_config::<C>);
+        app.add_system_to_stage(PresentationStage, apply_query_to_camera);
+    }
+}
@@ -0,0 +1,487 @@
+//! Snapshots of the state of every atom, written at chosen simulated times.
+//!
+//! Snapshots are written as csv, NumPy `.npy` or `.npz`, or, with the `parquet` feature, Parquet
+//! files, so that results can be loaded straight into an analysis notebook:
+//!
+//! - A `.csv` or `.parquet` file is a table with a row per atom.
+//! - A `.npy` file holds a structured array with a record per atom, with the same fields as the
+//!   columns of the table.
+//! - A `.npz` archive holds an array per quantity: `id`, `position` and `velocity` of shape
+//!   (atoms, 3), `species`, `photons_scattered`, and the scalar `time`.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use atomecs::atom::{Atom, Position, Velocity};
+use atomecs::laser_cooling::photons_scattered::TotalPhotonsScattered;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use bevy::asset::Error;
+use bevy::prelude::*;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::stepping::SimulationClock;
+
+/// State of a single atom.
+#[derive(Clone, Debug)]
+pub struct AtomState {
+    /// Identifies the atom, and is unique within a run.
+    pub id: u64,
+    /// Position, in units of m.
+    pub position: [f64; 3],
+    /// Velocity, in units of m/s.
+    pub velocity: [f64; 3],
+    /// Name of the atomic species, eg `Strontium88_461`.
+    pub species: String,
+    /// Number of photons scattered during the last step.
+    pub photons_scattered: f64,
+}
+
+/// State of every atom at a simulated time.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// Simulated time, in units of s.
+    pub time: f64,
+    pub atoms: Vec<AtomState>,
+}
+
+/// Names of the columns of a snapshot table.
+const COLUMNS: [&str; 10] = [
+    "id",
+    "time",
+    "x",
+    "y",
+    "z",
+    "vx",
+    "vy",
+    "vz",
+    "species",
+    "photons_scattered",
+];
+
+/// Name of the species with transition `T`, eg `Strontium88_461`.
+pub fn species_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+impl Snapshot {
+    /// Snapshot of the atoms of the species with transition `T` in a world.
+    pub fn capture<T>(world: &mut World) -> Snapshot
+    where
+        T: AtomicTransition + Component,
+    {
+        let time = world
+            .get_resource::<SimulationClock>()
+            .map_or(0.0, |clock| clock.time);
+        let mut query = world.query_filtered::<(
+            Entity,
+            &Position,
+            &Velocity,
+            Option<&TotalPhotonsScattered<T>>,
+        ), (With<Atom>, With<T>)>();
+        Snapshot {
+            time,
+            atoms: query
+                .iter(world)
+                .map(|(entity, position, velocity, photons)| {
+                    atom_state::<T>(entity, position, velocity, photons)
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the snapshot, in the format given by the extension of the path.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        if !SnapshotSchedule::FORMATS.contains(&extension) {
+            return Err(Error::msg(format!(
+                "Unknown snapshot format {}. Snapshots are written as {}.",
+                path.display(),
+                SnapshotSchedule::FORMATS.join(", ")
+            )));
+        }
+        let file = File::create(path)?;
+        match extension {
+            "csv" => self.write_csv(BufWriter::new(file)),
+            "npy" => Ok(BufWriter::new(file).write_all(&self.to_npy())?),
+            #[cfg(feature = "parquet")]
+            "parquet" => self.write_parquet(file),
+            _ => self.write_npz(file),
+        }
+    }
+
+    fn write_csv(&self, mut writer: impl Write) -> Result<(), Error> {
+        writeln!(writer, "{}", COLUMNS.join(","))?;
+        for atom in self.atoms.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{}",
+                atom.id,
+                self.time,
+                atom.position[0],
+                atom.position[1],
+                atom.position[2],
+                atom.velocity[0],
+                atom.velocity[1],
+                atom.velocity[2],
+                atom.species,
+                atom.photons_scattered
+            )?;
+        }
+        Ok(writer.flush()?)
+    }
+
+    /// Length of the longest species name, which sets the width of the species strings.
+    fn species_width(&self) -> usize {
+        self.atoms
+            .iter()
+            .map(|atom| atom.species.len())
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    /// The snapshot as a NumPy structured array, with a record per atom.
+    fn to_npy(&self) -> Vec<u8> {
+        let width = self.species_width();
+        let descr = format!(
+            "[('id', '<u8'), ('time', '<f8'), ('x', '<f8'), ('y', '<f8'), ('z', '<f8'), \
+             ('vx', '<f8'), ('vy', '<f8'), ('vz', '<f8'), ('species', '|S{}'), \
+             ('photons_scattered', '<f8')]",
+            width
+        );
+        let mut data = Vec::with_capacity(self.atoms.len() * (9 * 8 + width));
+        for atom in self.atoms.iter() {
+            data.extend_from_slice(&atom.id.to_le_bytes());
+            data.extend_from_slice(&self.time.to_le_bytes());
+            for value in atom.position.iter().chain(atom.velocity.iter()) {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            push_padded(&mut data, &atom.species, width);
+            data.extend_from_slice(&atom.photons_scattered.to_le_bytes());
+        }
+        npy(&descr, &[self.atoms.len()], &data)
+    }
+
+    fn write_npz(&self, file: File) -> Result<(), Error> {
+        let n = self.atoms.len();
+        let width = self.species_width();
+        let mut ids = Vec::with_capacity(n * 8);
+        let mut positions = Vec::with_capacity(n * 24);
+        let mut velocities = Vec::with_capacity(n * 24);
+        let mut species = Vec::with_capacity(n * width);
+        let mut photons = Vec::with_capacity(n * 8);
+        for atom in self.atoms.iter() {
+            ids.extend_from_slice(&atom.id.to_le_bytes());
+            for value in atom.position.iter() {
+                positions.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in atom.velocity.iter() {
+                velocities.extend_from_slice(&value.to_le_bytes());
+            }
+            push_padded(&mut species, &atom.species, width);
+            photons.extend_from_slice(&atom.photons_scattered.to_le_bytes());
+        }
+
+        let arrays = [
+            ("id", npy("<u8", &[n], &ids)),
+            ("time", npy("<f8", &[], &self.time.to_le_bytes())),
+            ("position", npy("<f8", &[n, 3], &positions)),
+            ("velocity", npy("<f8", &[n, 3], &velocities)),
+            ("species", npy(&format!("|S{}", width), &[n], &species)),
+            ("photons_scattered", npy("<f8", &[n], &photons)),
+        ];
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, array) in arrays.iter() {
+            zip.start_file(format!("{}.npy", name), options)?;
+            zip.write_all(array)?;
+        }
+        zip.finish()?.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    fn write_parquet(&self, file: File) -> Result<(), Error> {
+        use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+        use std::sync::Arc;
+
+        let schema = parse_message_type(
+            "message snapshot {
+                REQUIRED INT64 id;
+                REQUIRED DOUBLE time;
+                REQUIRED DOUBLE x;
+                REQUIRED DOUBLE y;
+                REQUIRED DOUBLE z;
+                REQUIRED DOUBLE vx;
+                REQUIRED DOUBLE vy;
+                REQUIRED DOUBLE vz;
+                REQUIRED BYTE_ARRAY species (UTF8);
+                REQUIRED DOUBLE photons_scattered;
+            }",
+        )?;
+        let properties = WriterProperties::builder().build();
+        let mut writer = SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties))?;
+        let mut row_group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match COLUMNS[index] {
+                "id" => {
+                    let ids: Vec<i64> = self.atoms.iter().map(|atom| atom.id as i64).collect();
+                    column.typed::<Int64Type>().write_batch(&ids, None, None)?;
+                }
+                "species" => {
+                    let species: Vec<ByteArray> = self
+                        .atoms
+                        .iter()
+                        .map(|atom| ByteArray::from(atom.species.as_str()))
+                        .collect();
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&species, None, None)?;
+                }
+                name => {
+                    let values: Vec<f64> = self
+                        .atoms
+                        .iter()
+                        .map(|atom| match name {
+                            "time" => self.time,
+                            "x" => atom.position[0],
+                            "y" => atom.position[1],
+                            "z" => atom.position[2],
+                            "vx" => atom.velocity[0],
+                            "vy" => atom.velocity[1],
+                            "vz" => atom.velocity[2],
+                            _ => atom.photons_scattered,
+                        })
+                        .collect();
+                    column
+                        .typed::<DoubleType>()
+                        .write_batch(&values, None, None)?;
+                }
+            }
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+fn atom_state<T: AtomicTransition + Component>(
+    entity: Entity,
+    position: &Position,
+    velocity: &Velocity,
+    photons: Option<&TotalPhotonsScattered<T>>,
+) -> AtomState {
+    AtomState {
+        id: entity.to_bits(),
+        position: [position.pos[0], position.pos[1], position.pos[2]],
+        velocity: [velocity.vel[0], velocity.vel[1], velocity.vel[2]],
+        species: species_name::<T>().to_string(),
+        photons_scattered: photons.map_or(0.0, |photons| photons.total),
+    }
+}
+
+/// Appends a string as a fixed width, zero padded, byte string.
+fn push_padded(data: &mut Vec<u8>, value: &str, width: usize) {
+    let bytes = value.as_bytes();
+    let length = bytes.len().min(width);
+    data.extend_from_slice(&bytes[..length]);
+    data.resize(data.len() + width - length, 0);
+}
+
+/// An array in the NumPy `.npy` format, version 1.0.
+///
+/// `data` holds the little endian elements in C order.
+fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': {}, 'fortran_order': False, 'shape': {}, }}",
+        if descr.starts_with('[') {
+            descr.to_string()
+        } else {
+            format!("'{}'", descr)
+        },
+        shape
+    );
+    // The magic string, version and header length take 10 bytes, and the data is aligned to 64.
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + data.len());
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Simulated times at which snapshots are written.
+#[derive(Resource, Clone, Debug)]
+pub struct SnapshotSchedule {
+    /// Simulated times, in units of s, in increasing order.
+    pub times: Vec<f64>,
+    /// Directory the snapshots are written to, as `snapshot_<index>.<format>`.
+    pub directory: PathBuf,
+    /// Extension of the snapshot files, which sets their format.
+    pub format: String,
+    /// Index of the next snapshot to write.
+    next: usize,
+    /// Simulated time when the schedule was last checked.
+    last_time: f64,
+}
+
+impl SnapshotSchedule {
+    #[cfg(not(feature = "parquet"))]
+    pub const FORMATS: &'static [&'static str] = &["csv", "npy", "npz"];
+    #[cfg(feature = "parquet")]
+    pub const FORMATS: &'static [&'static str] = &["csv", "npy", "npz", "parquet"];
+
+    pub fn new(mut times: Vec<f64>, directory: impl Into<PathBuf>, format: &str) -> Self {
+        times.sort_by(f64::total_cmp);
+        SnapshotSchedule {
+            times,
+            directory: directory.into(),
+            format: format.to_string(),
+            next: 0,
+            last_time: 0.0,
+        }
+    }
+
+    /// Reads the schedule from the command line arguments, if they contain
+    /// `--snapshots <t1,t2,...>`. The format and directory are set by `--snapshot-format`, which
+    /// defaults to csv, and `--snapshot-dir`, which defaults to `snapshots`.
+    pub fn from_args() -> Result<Option<Self>, Error> {
+        let args: Vec<String> = std::env::args().collect();
+        let value = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| args.get(index + 1))
+        };
+        let times = match value("--snapshots") {
+            Some(times) => times
+                .split(',')
+                .map(|time| {
+                    time.trim()
+                        .parse::<f64>()
+                        .map_err(|_| Error::msg(format!("Invalid snapshot time {}.", time)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => return Ok(None),
+        };
+        let format = value("--snapshot-format").map_or("csv", |format| format.as_str());
+        if !Self::FORMATS.contains(&format) {
+            return Err(Error::msg(format!(
+                "Unknown snapshot format {}. Snapshots are written as {}.",
+                format,
+                Self::FORMATS.join(", ")
+            )));
+        }
+        let directory = value("--snapshot-dir").map_or("snapshots", |dir| dir.as_str());
+        Ok(Some(SnapshotSchedule::new(times, directory, format)))
+    }
+
+    /// Path of the snapshot with the given index.
+    pub fn path(&self, index: usize) -> PathBuf {
+        self.directory
+            .join(format!("snapshot_{:03}.{}", index, self.format))
+    }
+}
+
+/// Writes a snapshot of the atoms of the species with transition `T` at each time in the
+/// [SnapshotSchedule].
+pub fn take_snapshots<T>(
+    mut schedule: ResMut<SnapshotSchedule>,
+    clock: Res<SimulationClock>,
+    atoms: Query<
+        (
+            Entity,
+            &Position,
+            &Velocity,
+            Option<&TotalPhotonsScattered<T>>,
+        ),
+        (With<Atom>, With<T>),
+    >,
+) where
+    T: AtomicTransition + Component,
+{
+    if clock.time < schedule.last_time {
+        // The simulation was reset, so the snapshots are written again.
+        schedule.next = schedule.times.iter().filter(|t| **t < clock.time).count();
+    }
+    schedule.last_time = clock.time;
+
+    while schedule.next < schedule.times.len() && schedule.times[schedule.next] <= clock.time {
+        let snapshot = Snapshot {
+            time: clock.time,
+            atoms: atoms
+                .iter()
+                .map(|(entity, position, velocity, photons)| {
+                    atom_state::<T>(entity, position, velocity, photons)
+                })
+                .collect(),
+        };
+        let path = schedule.path(schedule.next);
+        let written = std::fs::create_dir_all(&schedule.directory)
+            .map_err(Error::from)
+            .and_then(|_| snapshot.write(&path));
+        match written {
+            Ok(()) => info!(
+                "Wrote snapshot of {} atoms to {}",
+                snapshot.atoms.len(),
+                path.display()
+            ),
+            Err(error) => warn!("Could not write snapshot {}: {}", path.display(), error),
+        }
+        schedule.next += 1;
+    }
+}
+
+/// Writes snapshots of the atoms of the species with transition `T`, on the simulation steps
+/// given by the [SnapshotSchedule].
+pub struct SnapshotPlugin<T> {
+    schedule: SnapshotSchedule,
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<T> SnapshotPlugin<T> {
+    pub fn new(schedule: SnapshotSchedule) -> Self {
+        SnapshotPlugin {
+            schedule,
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T> Plugin for SnapshotPlugin<T>
+where
+    T: AtomicTransition + Component,
+{
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationClock>();
+        app.insert_resource(self.schedule.clone());
+        app.add_system(take_snapshots::<T>);
+    }
+}
@@ -0,0 +1,418 @@
+//! Sources of atoms.
+//!
+//! Sources emit atoms at rates given in physical units, accumulated against the simulated
+//! [Timestep] rather than the rendered frame rate. Each simulated atom stands for [AtomWeight]
+//! real atoms, so that fluxes measured in the simulation can be compared between machines.
+
+use std::marker::PhantomData;
+
+use atomecs::atom::{Atom, Force, Mass, Position, Velocity};
+use atomecs::constant::{AMU, BOLTZCONST};
+use atomecs::initiate::NewlyCreated;
+use atomecs::integrator::Timestep;
+use atomecs::laser_cooling::transition::AtomicTransition;
+use bevy::prelude::*;
+use nalgebra::Vector3;
+use rand::Rng;
+
+use crate::geometry::perpendicular_basis;
+use crate::random::SimulationRng;
+use crate::scene::PhaseSpaceDistribution;
+
+/// Number of real atoms represented by each simulated atom.
+#[derive(Resource, Clone, Copy)]
+pub struct AtomWeight(pub f64);
+impl Default for AtomWeight {
+    fn default() -> Self {
+        AtomWeight(1.0)
+    }
+}
+
+/// Converts an emission rate into a whole number of simulated atoms each timestep, and counts
+/// the atoms emitted by a source.
+#[derive(Clone, Default)]
+pub struct EmissionAccumulator {
+    /// Fractional number of simulated atoms not yet emitted.
+    remainder: f64,
+    /// Total number of simulated atoms emitted.
+    pub emitted: u64,
+    /// Simulated time the source has been emitting for, in units of s.
+    pub elapsed: f64,
+}
+
+impl EmissionAccumulator {
+    /// Number of simulated atoms to emit during a time `dt`, for a `rate` of real atoms/s.
+    pub fn atoms_to_emit(&mut self, rate: f64, dt: f64, weight: &AtomWeight) -> u64 {
+        self.remainder += rate * dt / weight.0;
+        let number = self.remainder.floor();
+        self.remainder -= number;
+        self.emitted += number as u64;
+        self.elapsed += dt;
+        number as u64
+    }
+
+    /// Average flux of real atoms emitted so far, in units of atoms/s.
+    pub fn flux(&self, weight: &AtomWeight) -> f64 {
+        if self.elapsed > 0.0 {
+            self.emitted as f64 * weight.0 / self.elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Angular distribution of atoms leaving an [Oven].
+#[derive(Clone, Copy)]
+pub enum Collimation {
+    /// A thin aperture, emitting with a cosine distribution.
+    Effusive,
+    /// An array of long capillaries of the given length and radius, in units of m.
+    ///
+    /// Atoms are transmitted without striking the capillary walls, which narrows the beam to a
+    /// maximum angle of `atan(2 radius / length)`.
+    Capillary { length: f64, radius: f64 },
+}
+
+/// A thermal oven that emits an effusive beam of atoms.
+///
+/// Atoms leave the aperture with the flux-weighted speed distribution `v^3 exp(-m v^2 / 2 k T)`, and
+/// are emitted at a rate set by `flux` in simulated time, independent of the rendering frame rate.
+/// The oven is positioned at the centre of its aperture by a [Position] component.
+#[derive(Component, Clone)]
+pub struct Oven {
+    /// Mass of the emitted atoms, in units of amu.
+    pub mass: f64,
+    /// Temperature of the oven, in units of K.
+    pub temperature: f64,
+    /// Radius of the oven aperture, in units of m.
+    pub aperture_radius: f64,
+    /// Direction of the oven nozzle.
+    pub direction: Vector3<f64>,
+    /// Maximum angle between emitted atoms and the nozzle direction, in units of rad.
+    pub max_angle: f64,
+    pub collimation: Collimation,
+    /// Total flux of atoms leaving the oven, in units of atoms/s.
+    ///
+    /// With [Collimation::Capillary], this is the flux transmitted by the capillaries: directions
+    /// that would strike the capillary walls are drawn again, so collimation does not reduce the
+    /// rate of emission.
+    pub flux: f64,
+    /// Only atoms slower than this speed are simulated, in units of m/s.
+    ///
+    /// Faster atoms are not captured by most sources, so are discarded to save computation.
+    pub max_speed: Option<f64>,
+    /// Simulated atoms emitted by the oven.
+    pub emission: EmissionAccumulator,
+}
+
+impl Oven {
+    /// An effusive oven emitting `flux` atoms/s of the given mass (in amu) at `temperature` (in K),
+    /// along `direction`.
+    pub fn new(mass: f64, temperature: f64, flux: f64, direction: Vector3<f64>) -> Self {
+        Oven {
+            mass,
+            temperature,
+            aperture_radius: 1e-3,
+            direction: direction.normalize(),
+            max_angle: std::f64::consts::FRAC_PI_2,
+            collimation: Collimation::Effusive,
+            flux,
+            max_speed: None,
+            emission: EmissionAccumulator::default(),
+        }
+    }
+
+    /// Sets the radius of the oven aperture, in units of m.
+    pub fn aperture_radius(mut self, aperture_radius: f64) -> Self {
+        self.aperture_radius = aperture_radius;
+        self
+    }
+
+    /// Sets the maximum angle between emitted atoms and the nozzle direction, in units of rad.
+    pub fn max_angle(mut self, max_angle: f64) -> Self {
+        self.max_angle = max_angle;
+        self
+    }
+
+    /// Collimates the beam with an array of capillaries.
+    pub fn capillaries(mut self, length: f64, radius: f64) -> Self {
+        self.collimation = Collimation::Capillary { length, radius };
+        self
+    }
+
+    /// Only simulate atoms slower than `max_speed`, in units of m/s.
+    pub fn max_speed(mut self, max_speed: f64) -> Self {
+        self.max_speed = Some(max_speed);
+        self
+    }
+
+    /// Ratio of m / 2kT for the emitted atoms, in units of s^2/m^2.
+    fn beta(&self) -> f64 {
+        self.mass * AMU / (2.0 * BOLTZCONST * self.temperature)
+    }
+
+    /// Fraction of emitted atoms that are simulated.
+    pub fn simulated_fraction(&self) -> f64 {
+        match self.max_speed {
+            None => 1.0,
+            Some(max_speed) => flux_weighted_cdf(self.beta() * max_speed.powi(2)),
+        }
+    }
+
+    /// Samples a speed from the flux-weighted distribution, in units of m/s.
+    pub fn sample_speed<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        // With x = m v^2 / 2kT, the flux-weighted distribution is x exp(-x) dx.
+        let u = rng.gen::<f64>() * self.simulated_fraction();
+        (inverse_flux_weighted_cdf(u) / self.beta()).sqrt()
+    }
+
+    /// Samples a direction of emission.
+    ///
+    /// Through capillaries, directions are drawn until one is transmitted, so every call returns
+    /// the direction of an emitted atom.
+    pub fn sample_direction<R: Rng + ?Sized>(&self, rng: &mut R) -> Vector3<f64> {
+        let max_angle = match self.collimation {
+            Collimation::Effusive => self.max_angle,
+            Collimation::Capillary { length, radius } => {
+                self.max_angle.min((2.0 * radius / length).atan())
+            }
+        };
+        let theta = loop {
+            // Cosine distribution, p(theta) ~ cos(theta) sin(theta), truncated at max_angle.
+            let theta = (rng.gen::<f64>().sqrt() * max_angle.sin()).asin();
+            let transmission = match self.collimation {
+                Collimation::Effusive => 1.0,
+                Collimation::Capillary { length, radius } => {
+                    capillary_transmission(theta.tan() * length / (2.0 * radius))
+                }
+            };
+            if rng.gen::<f64>() < transmission {
+                break theta;
+            }
+        };
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let (u, v) = perpendicular_basis(&self.direction);
+        theta.cos() * self.direction + theta.sin() * (phi.cos() * u + phi.sin() * v)
+    }
+
+    /// Samples a position within the oven aperture, relative to its centre.
+    pub fn sample_offset<R: Rng + ?Sized>(&self, rng: &mut R) -> Vector3<f64> {
+        let r = self.aperture_radius * rng.gen::<f64>().sqrt();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let (u, v) = perpendicular_basis(&self.direction);
+        r * (phi.cos() * u + phi.sin() * v)
+    }
+
+    /// Number of simulated atoms to emit during a time `dt`, in units of s.
+    fn atoms_to_emit(&mut self, dt: f64, weight: &AtomWeight) -> u64 {
+        let rate = self.flux * self.simulated_fraction();
+        self.emission.atoms_to_emit(rate, dt, weight)
+    }
+}
+
+/// Fraction of the flux-weighted distribution `x exp(-x)` below `x`.
+fn flux_weighted_cdf(x: f64) -> f64 {
+    1.0 - (-x).exp() * (1.0 + x)
+}
+
+/// Inverts [flux_weighted_cdf] by Newton's method.
+fn inverse_flux_weighted_cdf(u: f64) -> f64 {
+    let mut x: f64 = if u < 0.3 { (2.0 * u).sqrt() } else { 2.0 };
+    for _ in 0..50 {
+        let density = x * (-x).exp();
+        if density <= 0.0 {
+            break;
+        }
+        let step = (flux_weighted_cdf(x) - u) / density;
+        x = (x - step).max(0.5 * x);
+        if step.abs() < 1e-12 * x.max(1.0) {
+            break;
+        }
+    }
+    x
+}
+
+/// Probability that an atom passes through a long capillary without striking the walls.
+///
+/// `q` is `tan(theta) length / (2 radius)`, and the probability is the overlap of the entrance and
+/// exit apertures projected along the direction of travel.
+fn capillary_transmission(q: f64) -> f64 {
+    if q >= 1.0 {
+        return 0.0;
+    }
+    2.0 / std::f64::consts::PI * (q.acos() - q * (1.0 - q * q).sqrt())
+}
+
+/// Emits atoms from each [Oven], according to the time elapsed in the simulation.
+pub fn emit_from_ovens<T>(
+    mut commands: Commands,
+    timestep: Res<Timestep>,
+    weight: Res<AtomWeight>,
+    mut rng: ResMut<SimulationRng>,
+    mut query: Query<(&mut Oven, &Position)>,
+) where
+    T: AtomicTransition + Component + Default,
+{
+    for (mut oven, oven_position) in query.iter_mut() {
+        for _ in 0..oven.atoms_to_emit(timestep.delta, &weight) {
+            let speed = oven.sample_speed(&mut *rng);
+            let direction = oven.sample_direction(&mut *rng);
+            commands
+                .spawn(Position {
+                    pos: oven_position.pos + oven.sample_offset(&mut *rng),
+                })
+                .insert(Velocity {
+                    vel: speed * direction,
+                })
+                .insert(Force::default())
+                .insert(Mass { value: oven.mass })
+                .insert(T::default())
+                .insert(Atom)
+                .insert(NewlyCreated);
+        }
+    }
+}
+
+/// Emits atoms drawn from a [PhaseSpaceDistribution] at a constant rate.
+#[derive(Component, Clone)]
+pub struct ContinuousSource {
+    /// Rate of emission, in units of real atoms/s.
+    pub rate: f64,
+    pub distribution: PhaseSpaceDistribution,
+    /// Simulated atoms emitted by the source.
+    pub emission: EmissionAccumulator,
+}
+
+impl ContinuousSource {
+    pub fn new(rate: f64, distribution: PhaseSpaceDistribution) -> Self {
+        ContinuousSource {
+            rate,
+            distribution,
+            emission: EmissionAccumulator::default(),
+        }
+    }
+}
+
+/// Emits atoms from each [ContinuousSource], according to the time elapsed in the simulation.
+pub fn emit_continuous_sources<T>(
+    mut commands: Commands,
+    timestep: Res<Timestep>,
+    weight: Res<AtomWeight>,
+    mut rng: ResMut<SimulationRng>,
+    mut query: Query<&mut ContinuousSource>,
+) where
+    T: AtomicTransition + Component + Default,
+{
+    for mut source in query.iter_mut() {
+        let rate = source.rate;
+        for _ in 0..source.emission.atoms_to_emit(rate, timestep.delta, &weight) {
+            source
+                .distribution
+                .spawn_atom::<T, _>(&mut commands, &mut *rng);
+        }
+    }
+}
+
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SourceSystems {
+    EmitFromOvens,
+    EmitContinuous,
+}
+
+/// Emits atoms of species `T` from [Oven] and [ContinuousSource] entities.
+pub struct SourcePlugin<T> {
+    phantom: PhantomData<T>,
+}
+impl<T> Default for SourcePlugin<T> {
+    fn default() -> Self {
+        SourcePlugin {
+            phantom: PhantomData,
+        }
+    }
+}
+impl<T> Plugin for SourcePlugin<T>
+where
+    T: AtomicTransition + Component + Default,
+{
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AtomWeight>();
+        app.init_resource::<SimulationRng>();
+        // Sources draw from the same generator, so run in a fixed order to be reproducible.
+        app.add_system(emit_from_ovens::<T>.label(SourceSystems::EmitFromOvens));
+        app.add_system(
+            emit_continuous_sources::<T>
+                .label(SourceSystems::EmitContinuous)
+                .after(SourceSystems::EmitFromOvens),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn oven() -> Oven {
+        Oven::new(88.0, 800.0, 1e12, Vector3::x())
+    }
+
+    #[test]
+    fn inverse_cdf_round_trips() {
+        for u in [1e-9, 1e-4, 0.01, 0.2, 0.3, 0.5, 0.9, 0.999, 1.0 - 1e-9] {
+            let x = inverse_flux_weighted_cdf(u);
+            assert!(
+                (flux_weighted_cdf(x) - u).abs() < 1e-9,
+                "cdf(inverse({})) = {}",
+                u,
+                flux_weighted_cdf(x)
+            );
+        }
+    }
+
+    #[test]
+    fn mean_speed_is_flux_weighted_mean() {
+        let oven = oven();
+        let mut rng = StdRng::seed_from_u64(1);
+        let samples = 200_000;
+        let mean = (0..samples)
+            .map(|_| oven.sample_speed(&mut rng))
+            .sum::<f64>()
+            / samples as f64;
+        let expected = (9.0 * std::f64::consts::PI * BOLTZCONST * oven.temperature
+            / (8.0 * oven.mass * AMU))
+            .sqrt();
+        assert!(
+            (mean / expected - 1.0).abs() < 0.01,
+            "mean speed {} m/s, expected {} m/s",
+            mean,
+            expected
+        );
+    }
+
+    #[test]
+    fn max_speed_truncates_samples() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for max_speed in [20.0, 300.0, 600.0] {
+            let oven = oven().max_speed(max_speed);
+            assert!(oven.simulated_fraction() < 1.0);
+            for _ in 0..10_000 {
+                assert!(oven.sample_speed(&mut rng) <= max_speed);
+            }
+        }
+    }
+
+    #[test]
+    fn capillary_transmission_limits() {
+        assert!((capillary_transmission(0.0) - 1.0).abs() < 1e-12);
+        assert_eq!(capillary_transmission(1.0), 0.0);
+        assert_eq!(capillary_transmission(2.5), 0.0);
+        let mut previous = 1.0;
+        for i in 1..100 {
+            let transmission = capillary_transmission(i as f64 / 100.0);
+            assert!(transmission < previous);
+            previous = transmission;
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Summary statistics of the atoms in a simulation.
+
+use atomecs::atom::{Atom, Mass, Velocity};
+use atomecs::constant::{AMU, BOLTZCONST};
+use bevy::prelude::*;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+/// Number, speed and temperature of the simulated atoms.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct CloudStatistics {
+    /// Number of simulated atoms.
+    pub atom_number: usize,
+    /// Mean speed of the atoms, in units of m/s.
+    pub mean_speed: f64,
+    /// Temperature of the atoms, from the spread of their velocities about the mean velocity, in
+    /// units of K.
+    pub temperature: f64,
+}
+
+impl CloudStatistics {
+    pub fn from_atoms<'a>(atoms: impl Iterator<Item = (&'a Velocity, &'a Mass)>) -> Self {
+        let atoms: Vec<(Vector3<f64>, f64)> = atoms
+            .map(|(velocity, mass)| (velocity.vel, mass.value * AMU))
+            .collect();
+        if atoms.is_empty() {
+            return CloudStatistics::default();
+        }
+        let number = atoms.len() as f64;
+        let mean_velocity = atoms
+            .iter()
+            .fold(Vector3::zeros(), |sum, (velocity, _)| sum + velocity)
+            / number;
+        let mean_speed = atoms
+            .iter()
+            .map(|(velocity, _)| velocity.norm())
+            .sum::<f64>()
+            / number;
+        // Each of the three degrees of freedom holds k_B T / 2 of kinetic energy.
+        let twice_kinetic_energy = atoms
+            .iter()
+            .map(|(velocity, mass)| mass * (velocity - mean_velocity).norm_squared())
+            .sum::<f64>()
+            / number;
+        CloudStatistics {
+            atom_number: atoms.len(),
+            mean_speed,
+            temperature: twice_kinetic_energy / (3.0 * BOLTZCONST),
+        }
+    }
+
+    /// Statistics of every [Atom] in the world.
+    pub fn measure(world: &mut World) -> Self {
+        let mut atoms = world.query_filtered::<(&Velocity, &Mass), With<Atom>>();
+        CloudStatistics::from_atoms(atoms.iter(world))
+    }
+}
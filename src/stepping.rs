@@ -0,0 +1,225 @@
+//! Decouples simulation steps from rendered frames.
+//!
+//! The AtomECS systems in [CoreStage::Update] are run several times each frame, enough to advance
+//! the simulation at a target ratio of simulated time to real time. Rendering, camera and UI
+//! systems belong in the [PresentationStage], which runs once per frame after the simulation.
+
+use atomecs::integrator::Timestep;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::utils::Instant;
+
+use crate::random::SimulationRng;
+
+/// Stage for systems that should run once per rendered frame, after the simulation has stepped.
+#[derive(StageLabel)]
+pub struct PresentationStage;
+
+/// Time elapsed in the simulation.
+#[derive(Resource, Default)]
+pub struct SimulationClock {
+    /// Simulated time, in units of s.
+    pub time: f64,
+    /// Number of integration steps.
+    pub steps: u64,
+}
+
+/// Advances the [SimulationClock] by one timestep.
+pub fn advance_simulation_clock(timestep: Res<Timestep>, mut clock: ResMut<SimulationClock>) {
+    clock.time += timestep.delta;
+    clock.steps += 1;
+}
+
+/// Controls how many simulation steps are run each frame.
+#[derive(Resource)]
+pub struct SimulationSpeed {
+    /// Target ratio of simulated time to real time.
+    pub target_ratio: f64,
+    /// Maximum real time to spend stepping the simulation each frame, in units of s.
+    ///
+    /// Limits the number of steps when the simulation cannot keep up with the target ratio, so
+    /// the frame rate stays interactive.
+    pub frame_budget: f64,
+    /// Maximum number of steps in a single frame.
+    pub max_steps_per_frame: u32,
+    /// Measured ratio of simulated time to real time, averaged over recent frames.
+    pub measured_ratio: f64,
+    /// Number of steps run in the last frame.
+    pub steps_last_frame: u32,
+    /// When paused, the simulation only runs steps requested by [SimulationSpeed::step].
+    paused: bool,
+    /// Steps requested while paused.
+    pending_steps: u32,
+    steps_remaining: u32,
+    steps_this_frame: u32,
+    /// Fractional step carried over to the next frame.
+    remainder: f64,
+    /// Average real time taken by each step, in units of s.
+    step_duration: Option<f64>,
+    stepping_started: Option<Instant>,
+}
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        SimulationSpeed {
+            target_ratio: 1e-3,
+            frame_budget: 0.012,
+            max_steps_per_frame: 1000,
+            measured_ratio: 0.0,
+            steps_last_frame: 0,
+            paused: false,
+            pending_steps: 0,
+            steps_remaining: 0,
+            steps_this_frame: 0,
+            remainder: 0.0,
+            step_duration: None,
+            stepping_started: None,
+        }
+    }
+}
+
+impl SimulationSpeed {
+    pub fn with_target_ratio(target_ratio: f64) -> Self {
+        SimulationSpeed {
+            target_ratio,
+            ..default()
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes the simulation, discarding any steps requested while paused.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
+
+    /// Pauses the simulation, and runs `steps` more steps.
+    ///
+    /// The steps are spread over several frames if they exceed the frame budget.
+    pub fn step(&mut self, steps: u32) {
+        self.paused = true;
+        self.pending_steps += steps;
+    }
+
+    /// Whether steps requested by [SimulationSpeed::step] are still to be run.
+    pub fn is_stepping(&self) -> bool {
+        self.pending_steps > 0 || self.steps_remaining > 0
+    }
+}
+
+/// Chooses the number of simulation steps to run this frame.
+pub fn plan_simulation_steps(
+    time: Res<Time>,
+    timestep: Res<Timestep>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    let frame = time.delta_seconds_f64();
+    if frame > 0.0 {
+        let ratio = speed.steps_last_frame as f64 * timestep.delta / frame;
+        speed.measured_ratio = 0.9 * speed.measured_ratio + 0.1 * ratio;
+    }
+
+    if speed.paused {
+        let steps = speed.pending_steps.min(limit_steps(&speed));
+        speed.pending_steps -= steps;
+        speed.steps_remaining = steps;
+        speed.remainder = 0.0;
+        speed.steps_this_frame = 0;
+        return;
+    }
+
+    let desired = speed.target_ratio * frame / timestep.delta + speed.remainder;
+    let limit = limit_steps(&speed) as f64;
+    if desired > limit {
+        // The simulation cannot keep up, so do not accumulate a backlog of steps.
+        speed.steps_remaining = limit as u32;
+        speed.remainder = 0.0;
+    } else {
+        speed.steps_remaining = desired.floor() as u32;
+        speed.remainder = desired - desired.floor();
+    }
+    speed.steps_this_frame = 0;
+}
+
+/// Maximum number of steps that fit in the frame budget.
+fn limit_steps(speed: &SimulationSpeed) -> u32 {
+    match speed.step_duration {
+        Some(duration) if duration > 0.0 => {
+            ((speed.frame_budget / duration).floor() as u32).clamp(1, speed.max_steps_per_frame)
+        }
+        _ => speed.max_steps_per_frame,
+    }
+}
+
+/// Run criteria for [CoreStage::Update], which repeats the stage once per planned step.
+pub fn run_simulation_steps(mut speed: ResMut<SimulationSpeed>) -> ShouldRun {
+    if speed.steps_remaining > 0 {
+        if speed.stepping_started.is_none() {
+            speed.stepping_started = Some(Instant::now());
+        }
+        speed.steps_remaining -= 1;
+        speed.steps_this_frame += 1;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        if let Some(started) = speed.stepping_started.take() {
+            let duration = started.elapsed().as_secs_f64() / speed.steps_this_frame as f64;
+            speed.step_duration = Some(match speed.step_duration {
+                Some(average) => 0.8 * average + 0.2 * duration,
+                None => duration,
+            });
+        }
+        speed.steps_last_frame = speed.steps_this_frame;
+        ShouldRun::No
+    }
+}
+
+/// Adds the [PresentationStage] after [CoreStage::Update], if it does not already exist.
+pub fn add_presentation_stage(app: &mut App) {
+    if app
+        .schedule
+        .get_stage::<SystemStage>(PresentationStage)
+        .is_none()
+    {
+        app.add_stage_after(
+            CoreStage::Update,
+            PresentationStage,
+            SystemStage::parallel(),
+        );
+    }
+}
+
+/// Creates an app that runs the simulation headless, with the plugins added by `build` and atoms
+/// sampled from `seed`.
+///
+/// There is no [SteppingPlugin], so each update runs a single step of the simulation and advances
+/// the [SimulationClock].
+pub fn headless_app(seed: u64, build: impl FnOnce(&mut App)) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    build(&mut app);
+    app.insert_resource(SimulationRng::seeded(seed));
+    app.init_resource::<SimulationClock>();
+    app.add_system(advance_simulation_clock);
+    app
+}
+
+/// Runs a variable number of simulation steps each frame, set by the [SimulationSpeed].
+pub struct SteppingPlugin;
+impl Plugin for SteppingPlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.init_resource::<SimulationSpeed>();
+        app.init_resource::<SimulationClock>();
+        app.add_system(advance_simulation_clock);
+        app.add_system_to_stage(CoreStage::PreUpdate, plan_simulation_steps);
+        app.stage(CoreStage::Update, |stage: &mut SystemStage| {
+            stage.set_run_criteria(run_simulation_steps)
+        });
+    }
+}
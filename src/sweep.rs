@@ -0,0 +1,638 @@
+//! Headless sweeps over the parameters of an experiment.
+//!
+//! Each point of a sweep runs in its own Bevy [App], without rendering. The simulation is run for
+//! a settling time, then the [Detector]s of the experiment are read over a measurement window.
+//! Points are shared between worker threads, one app per thread at a time.
+//!
+//! Each point is seeded with the sweep seed plus its index, so individual points can be repeated.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use atomecs::atom::Atom;
+use bevy::asset::Error;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::detector::{mean, Detector};
+use crate::experiment::{with_parameters, ExperimentControls};
+use crate::sources::{AtomWeight, ContinuousSource, Oven};
+use crate::stepping::{headless_app, SimulationClock};
+
+/// A parameter of the experiment varied by the sweep.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SweepParameter {
+    /// Name of a numeric field of the experiment parameters.
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    /// Number of values in a grid sweep.
+    #[serde(default = "default_points")]
+    pub points: usize,
+}
+
+fn default_points() -> usize {
+    5
+}
+
+impl SweepParameter {
+    /// The value a fraction of the way from `min` to `max`.
+    fn value(&self, fraction: f64) -> f64 {
+        self.min + fraction * (self.max - self.min)
+    }
+}
+
+/// How points are chosen from the space of parameters.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Sampling {
+    /// Every combination of evenly spaced values of each parameter.
+    Grid,
+    /// A Latin hypercube of the given number of samples, which covers the range of every
+    /// parameter with far fewer points than a grid.
+    LatinHypercube { samples: usize },
+}
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling::Grid
+    }
+}
+
+/// A sweep, loaded from a json file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SweepDescription {
+    /// Name of the experiment, eg `aion_source`.
+    pub experiment: String,
+    pub parameters: Vec<SweepParameter>,
+    #[serde(default)]
+    pub sampling: Sampling,
+    /// Simulated time before measuring, in units of s.
+    pub settling_time: f64,
+    /// Simulated time over which the diagnostics are measured, in units of s.
+    pub measurement_time: f64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl SweepDescription {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The values of the parameters at each point of the sweep.
+    pub fn points(&self) -> Vec<Vec<(String, f64)>> {
+        match self.sampling {
+            Sampling::Grid => {
+                let mut points = vec![Vec::new()];
+                for parameter in self.parameters.iter() {
+                    let values: Vec<f64> = (0..parameter.points)
+                        .map(|i| match parameter.points {
+                            1 => parameter.min,
+                            n => parameter.value(i as f64 / (n - 1) as f64),
+                        })
+                        .collect();
+                    points = points
+                        .into_iter()
+                        .flat_map(|point: Vec<(String, f64)>| {
+                            values.iter().map(move |value| {
+                                let mut point = point.clone();
+                                point.push((parameter.name.clone(), *value));
+                                point
+                            })
+                        })
+                        .collect();
+                }
+                points
+            }
+            Sampling::LatinHypercube { samples } => {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                // Each parameter takes one value from each of `samples` equal strata, in a random order.
+                let columns: Vec<Vec<f64>> = self
+                    .parameters
+                    .iter()
+                    .map(|parameter| {
+                        let mut strata: Vec<usize> = (0..samples).collect();
+                        strata.shuffle(&mut rng);
+                        strata
+                            .into_iter()
+                            .map(|stratum| {
+                                let fraction = (stratum as f64 + rng.gen::<f64>()) / samples as f64;
+                                parameter.value(fraction)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                (0..samples)
+                    .map(|i| {
+                        self.parameters
+                            .iter()
+                            .zip(columns.iter())
+                            .map(|(parameter, column)| (parameter.name.clone(), column[i]))
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Output of a source, measured by its [Detector]s.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct SourceDiagnostics {
+    /// Flux of real atoms through the detectors, in units of atoms/s.
+    pub flux: f64,
+    /// Mean velocity of detected atoms along the detector normal, in units of m/s.
+    pub mean_velocity: f64,
+    /// Fraction of the simulated atoms emitted by the sources that were detected.
+    pub capture_fraction: f64,
+    /// Mean number of real atoms in the simulation.
+    pub atom_number: f64,
+    /// Number of simulated atoms detected.
+    pub detected: u64,
+    /// Number of simulated atoms emitted.
+    pub emitted: u64,
+}
+
+/// A single quantity of the [SourceDiagnostics].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    Flux,
+    MeanVelocity,
+    CaptureFraction,
+    AtomNumber,
+}
+
+impl Diagnostic {
+    pub const ALL: [Diagnostic; 4] = [
+        Diagnostic::Flux,
+        Diagnostic::MeanVelocity,
+        Diagnostic::CaptureFraction,
+        Diagnostic::AtomNumber,
+    ];
+
+    pub fn value(&self, diagnostics: &SourceDiagnostics) -> f64 {
+        match self {
+            Diagnostic::Flux => diagnostics.flux,
+            Diagnostic::MeanVelocity => diagnostics.mean_velocity,
+            Diagnostic::CaptureFraction => diagnostics.capture_fraction,
+            Diagnostic::AtomNumber => diagnostics.atom_number,
+        }
+    }
+
+    /// Name of the quantity, with its unit.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Diagnostic::Flux => "Flux (atoms/s)",
+            Diagnostic::MeanVelocity => "Mean velocity (m/s)",
+            Diagnostic::CaptureFraction => "Capture fraction",
+            Diagnostic::AtomNumber => "Atom number",
+        }
+    }
+}
+
+/// Everything recorded during the measurement window of a run.
+#[derive(Clone, Debug, Default)]
+pub struct Measurement {
+    /// Number of simulated atoms detected.
+    pub detected: u64,
+    /// Velocity along the detector normal of each simulated atom detected, in units of m/s.
+    ///
+    /// Only the velocities still in the window of each [Detector] are kept, so there may be fewer
+    /// than `detected`.
+    pub velocities: Vec<f64>,
+    /// Number of simulated atoms emitted.
+    pub emitted: u64,
+    /// Mean number of simulated atoms in the simulation.
+    pub mean_atoms: f64,
+    /// Real atoms represented by each simulated atom.
+    pub weight: f64,
+    /// Simulated time measured over, in units of s.
+    pub duration: f64,
+}
+
+impl Measurement {
+    /// Flux of real atoms slower than `max_velocity` through the detectors, in units of atoms/s.
+    pub fn flux_below(&self, max_velocity: f64) -> f64 {
+        let slow = self
+            .velocities
+            .iter()
+            .filter(|v| **v <= max_velocity)
+            .count();
+        if self.velocities.is_empty() {
+            return 0.0;
+        }
+        self.flux() * slow as f64 / self.velocities.len() as f64
+    }
+
+    fn flux(&self) -> f64 {
+        if self.duration > 0.0 {
+            self.detected as f64 * self.weight / self.duration
+        } else {
+            0.0
+        }
+    }
+
+    pub fn diagnostics(&self) -> SourceDiagnostics {
+        SourceDiagnostics {
+            flux: self.flux(),
+            mean_velocity: mean(&self.velocities),
+            capture_fraction: if self.emitted > 0 {
+                self.detected as f64 / self.emitted as f64
+            } else {
+                0.0
+            },
+            atom_number: self.mean_atoms * self.weight,
+            detected: self.detected,
+            emitted: self.emitted,
+        }
+    }
+
+    /// Combines the measurements of repeated runs of the same point, as if they were one long run.
+    pub fn combine(measurements: &[Measurement]) -> Measurement {
+        let mut combined = Measurement::default();
+        for measurement in measurements {
+            combined.detected += measurement.detected;
+            combined.velocities.extend(measurement.velocities.iter());
+            combined.emitted += measurement.emitted;
+            combined.mean_atoms += measurement.mean_atoms / measurements.len() as f64;
+            combined.weight = measurement.weight;
+            combined.duration += measurement.duration;
+        }
+        combined
+    }
+}
+
+/// Number of atoms emitted and detected so far, read at the start and end of a measurement.
+pub struct Totals {
+    emitted: u64,
+    /// Number of atoms detected by each detector.
+    detected: Vec<u64>,
+}
+
+impl Totals {
+    pub fn new<'a>(
+        ovens: impl Iterator<Item = &'a Oven>,
+        sources: impl Iterator<Item = &'a ContinuousSource>,
+        detectors: impl Iterator<Item = &'a Detector>,
+    ) -> Self {
+        Totals {
+            emitted: ovens.map(|oven| oven.emission.emitted).sum::<u64>()
+                + sources.map(|source| source.emission.emitted).sum::<u64>(),
+            detected: detectors.map(|detector| detector.detected).collect(),
+        }
+    }
+
+    fn read(world: &mut World) -> Self {
+        let mut ovens = world.query::<&Oven>();
+        let mut sources = world.query::<&ContinuousSource>();
+        let mut detectors = world.query::<&Detector>();
+        Totals::new(
+            ovens.iter(world),
+            sources.iter(world),
+            detectors.iter(world),
+        )
+    }
+
+    /// Measurement of the atoms emitted and detected between the `start` totals and these.
+    ///
+    /// The velocities of the atoms detected are read from the windows of the `detectors`, which
+    /// must be the detectors these totals were read from.
+    pub fn since<'a>(
+        &self,
+        start: &Totals,
+        detectors: impl Iterator<Item = &'a Detector>,
+        mean_atoms: f64,
+        weight: f64,
+        duration: f64,
+    ) -> Measurement {
+        let mut detected = 0;
+        let mut velocities = Vec::new();
+        for ((before, after), detector) in start.detected.iter().zip(&self.detected).zip(detectors)
+        {
+            let count = after.saturating_sub(*before);
+            detected += count;
+            velocities.extend(detector.recent(count));
+        }
+        Measurement {
+            detected,
+            velocities,
+            emitted: self.emitted.saturating_sub(start.emitted),
+            mean_atoms,
+            weight,
+            duration,
+        }
+    }
+}
+
+/// Builds a headless app for the experiment, by adding its plugins.
+pub type BuildExperiment = fn(&mut App);
+
+/// Runs the experiment with the given parameters, and measures the output of its sources.
+pub fn run_point<C: ExperimentControls>(
+    build: BuildExperiment,
+    config: C,
+    seed: u64,
+    settling_time: f64,
+    measurement_time: f64,
+) -> Measurement {
+    let mut app = headless_app(seed, build);
+    app.insert_resource(config);
+
+    let time = |app: &App| app.world.resource::<SimulationClock>().time;
+    while time(&app) < settling_time {
+        app.update();
+    }
+    let start = Totals::read(&mut app.world);
+    let mut atoms = app.world.query_filtered::<(), With<Atom>>();
+    let (mut atom_sum, mut steps) = (0, 0);
+    while time(&app) < settling_time + measurement_time {
+        app.update();
+        atom_sum += atoms.iter(&app.world).count();
+        steps += 1;
+    }
+    let end = Totals::read(&mut app.world);
+    let mean_atoms = if steps > 0 {
+        atom_sum as f64 / steps as f64
+    } else {
+        0.0
+    };
+    let mut detectors = app.world.query::<&Detector>();
+    end.since(
+        &start,
+        detectors.iter(&app.world),
+        mean_atoms,
+        app.world.resource::<AtomWeight>().0,
+        measurement_time,
+    )
+}
+
+/// Calls `f` on each of the items, spread over `workers` threads.
+///
+/// `f` is given the index of each item with it. Results are returned in the order of `items`, and
+/// `progress` is called as each finishes, with its index.
+pub fn run_parallel<T: Sync, R: Send>(
+    items: &[T],
+    workers: usize,
+    f: impl Fn(usize, &T) -> R + Sync,
+    mut progress: impl FnMut(usize, &R),
+) -> Vec<R> {
+    let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            let sender = sender.clone();
+            let (next, f) = (&next, &f);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(index) {
+                    Some(item) => item,
+                    None => break,
+                };
+                if sender.send((index, f(index, item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        for (index, result) in receiver.iter() {
+            progress(index, &result);
+            results[index] = Some(result);
+        }
+    });
+    // A worker that panics takes the whole scope down with it, so every item has finished.
+    results.into_iter().flatten().collect()
+}
+
+/// Runs the experiment at each of the parameters, spread over `workers` threads.
+///
+/// Measurements are returned in the order of `configs`. `progress` is called as each run finishes,
+/// with its index.
+pub fn run_points<C: ExperimentControls>(
+    build: BuildExperiment,
+    configs: &[(C, u64)],
+    settling_time: f64,
+    measurement_time: f64,
+    workers: usize,
+    progress: impl FnMut(usize, &Measurement),
+) -> Vec<Measurement> {
+    run_parallel(
+        configs,
+        workers,
+        |_, (config, seed)| {
+            run_point(
+                build,
+                config.clone(),
+                *seed,
+                settling_time,
+                measurement_time,
+            )
+        },
+        progress,
+    )
+}
+
+/// A point of the sweep, and the diagnostics measured there.
+#[derive(Clone, Debug)]
+pub struct SweepResult {
+    pub parameters: Vec<(String, f64)>,
+    pub diagnostics: SourceDiagnostics,
+}
+
+/// Runs every point of the sweep, spread over `workers` threads.
+///
+/// Results are returned in the order of [SweepDescription::points]. `progress` is called as each
+/// point finishes, with the number of points finished.
+pub fn run_sweep<C: ExperimentControls>(
+    description: &SweepDescription,
+    build: BuildExperiment,
+    workers: usize,
+    mut progress: impl FnMut(usize, &SweepResult),
+) -> Result<Vec<SweepResult>, Error> {
+    let points = description.points();
+    let defaults = C::default();
+    let configs = points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            let seed = description.seed.wrapping_add(index as u64);
+            Ok((with_parameters(&defaults, point)?, seed))
+        })
+        .collect::<Result<Vec<(C, u64)>, Error>>()?;
+
+    let mut finished = 0;
+    let measurements = run_points(
+        build,
+        &configs,
+        description.settling_time,
+        description.measurement_time,
+        workers,
+        |index, measurement| {
+            finished += 1;
+            let result = SweepResult {
+                parameters: points[index].clone(),
+                diagnostics: measurement.diagnostics(),
+            };
+            progress(finished, &result);
+        },
+    );
+    Ok(points
+        .into_iter()
+        .zip(measurements.iter())
+        .map(|(parameters, measurement)| SweepResult {
+            parameters,
+            diagnostics: measurement.diagnostics(),
+        })
+        .collect())
+}
+
+/// Writes the results as a table, in csv or json format depending on the extension of the path.
+pub fn write_results(path: &str, results: &[SweepResult]) -> Result<(), Error> {
+    let contents = if path.ends_with(".csv") {
+        results_to_csv(results)
+    } else if path.ends_with(".json") {
+        results_to_json(results)?
+    } else {
+        return Err(Error::msg("Output file must end in .csv or .json"));
+    };
+    Ok(std::fs::write(path, contents)?)
+}
+
+const DIAGNOSTIC_COLUMNS: [&str; 6] = [
+    "flux",
+    "mean_velocity",
+    "capture_fraction",
+    "atom_number",
+    "detected",
+    "emitted",
+];
+
+pub fn results_to_csv(results: &[SweepResult]) -> String {
+    let mut csv = String::new();
+    if let Some(first) = results.first() {
+        let mut header: Vec<&str> = first
+            .parameters
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        header.extend(DIAGNOSTIC_COLUMNS);
+        csv.push_str(&header.join(","));
+        csv.push('\n');
+    }
+    for result in results {
+        let d = &result.diagnostics;
+        let mut row: Vec<String> = result
+            .parameters
+            .iter()
+            .map(|(_, value)| value.to_string())
+            .collect();
+        row.push(d.flux.to_string());
+        row.push(d.mean_velocity.to_string());
+        row.push(d.capture_fraction.to_string());
+        row.push(d.atom_number.to_string());
+        row.push(d.detected.to_string());
+        row.push(d.emitted.to_string());
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Each result is an object with a field for each parameter and diagnostic.
+pub fn results_to_json(results: &[SweepResult]) -> Result<String, serde_json::Error> {
+    let mut rows = Vec::with_capacity(results.len());
+    for result in results {
+        let mut row = Map::new();
+        for (name, value) in result.parameters.iter() {
+            row.insert(name.clone(), serde_json::to_value(value)?);
+        }
+        if let Value::Object(diagnostics) = serde_json::to_value(result.diagnostics)? {
+            row.extend(diagnostics);
+        }
+        rows.push(Value::Object(row));
+    }
+    serde_json::to_string_pretty(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(parameters: &[(&str, f64, f64, usize)], sampling: Sampling) -> SweepDescription {
+        SweepDescription {
+            experiment: "test".to_string(),
+            parameters: parameters
+                .iter()
+                .map(|&(name, min, max, points)| SweepParameter {
+                    name: name.to_string(),
+                    min,
+                    max,
+                    points,
+                })
+                .collect(),
+            sampling,
+            settling_time: 0.0,
+            measurement_time: 0.0,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn grid_is_cartesian_product_with_last_parameter_fastest() {
+        let sweep = description(&[("a", 0.0, 1.0, 2), ("b", -1.0, 1.0, 3)], Sampling::Grid);
+        let values: Vec<Vec<f64>> = sweep
+            .points()
+            .into_iter()
+            .map(|point| {
+                let names: Vec<&str> = point.iter().map(|(name, _)| name.as_str()).collect();
+                assert_eq!(names, ["a", "b"]);
+                point.into_iter().map(|(_, value)| value).collect()
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                [0.0, -1.0],
+                [0.0, 0.0],
+                [0.0, 1.0],
+                [1.0, -1.0],
+                [1.0, 0.0],
+                [1.0, 1.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn grid_of_single_point_uses_min() {
+        let sweep = description(&[("a", 2.0, 3.0, 1)], Sampling::Grid);
+        assert_eq!(sweep.points(), [[("a".to_string(), 2.0)]]);
+    }
+
+    #[test]
+    fn latin_hypercube_has_one_sample_per_stratum() {
+        let samples = 10;
+        let sweep = description(
+            &[("a", 0.0, 1.0, 0), ("b", -5.0, 5.0, 0)],
+            Sampling::LatinHypercube { samples },
+        );
+        let points = sweep.points();
+        assert_eq!(points.len(), samples);
+        for (i, parameter) in sweep.parameters.iter().enumerate() {
+            let mut strata: Vec<usize> = points
+                .iter()
+                .map(|point| {
+                    let fraction = (point[i].1 - parameter.min) / (parameter.max - parameter.min);
+                    assert!((0.0..1.0).contains(&fraction));
+                    (fraction * samples as f64) as usize
+                })
+                .collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..samples).collect::<Vec<_>>());
+        }
+        // The same seed gives the same points.
+        assert_eq!(points, sweep.points());
+    }
+}
@@ -0,0 +1,379 @@
+//! A timeline to scrub through a replayed trajectory, with a plot of its diagnostics and bookmarked
+//! camera views.
+//!
+//! The timeline sets the [SimulationClock], which selects the frame shown by the replay, so pausing
+//! and the playback speed work as they do for a running simulation. Bookmarks store a time and
+//! the view of the [DemoCamera], so a presentation can jump straight to an interesting moment.
+
+use std::path::PathBuf;
+
+use atomecs::bevy_bridge::Scale;
+use bevy::prelude::*;
+use bevy_egui::egui::plot::{Line, Plot, PlotPoints, VLine};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::DemoCamera;
+use crate::controls::speed_slider;
+use crate::hud::format_time;
+use crate::stepping::{
+    add_presentation_stage, PresentationStage, SimulationClock, SimulationSpeed,
+};
+use crate::trajectory::{replay_trajectory, Frame, Replay, Trajectory};
+
+/// A quantity plotted against time on the timeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayDiagnostic {
+    AtomNumber,
+    MeanSpeed,
+    CloudSize,
+}
+
+impl ReplayDiagnostic {
+    pub const ALL: [ReplayDiagnostic; 3] = [
+        ReplayDiagnostic::AtomNumber,
+        ReplayDiagnostic::MeanSpeed,
+        ReplayDiagnostic::CloudSize,
+    ];
+
+    pub fn value(&self, frame: &Frame) -> f64 {
+        let n = frame.atoms.len() as f64;
+        if n == 0.0 {
+            return 0.0;
+        }
+        match self {
+            ReplayDiagnostic::AtomNumber => n,
+            ReplayDiagnostic::MeanSpeed => {
+                frame
+                    .atoms
+                    .iter()
+                    .map(|atom| norm(atom.velocity))
+                    .sum::<f64>()
+                    / n
+            }
+            ReplayDiagnostic::CloudSize => {
+                let mut centre = [0.0; 3];
+                for atom in frame.atoms.iter() {
+                    for (c, x) in centre.iter_mut().zip(atom.position.iter()) {
+                        *c += *x as f64 / n;
+                    }
+                }
+                let variance = frame
+                    .atoms
+                    .iter()
+                    .map(|atom| {
+                        atom.position
+                            .iter()
+                            .zip(centre.iter())
+                            .map(|(x, c)| (*x as f64 - c).powi(2))
+                            .sum::<f64>()
+                    })
+                    .sum::<f64>()
+                    / n;
+                variance.sqrt() * 1e3
+            }
+        }
+    }
+
+    /// Name of the quantity, with its unit.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReplayDiagnostic::AtomNumber => "Simulated atoms",
+            ReplayDiagnostic::MeanSpeed => "Mean speed (m/s)",
+            ReplayDiagnostic::CloudSize => "RMS cloud size (mm)",
+        }
+    }
+}
+
+fn norm(v: [f32; 3]) -> f64 {
+    v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt()
+}
+
+/// A moment of the replay, with the view of the camera.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    /// Simulated time, in units of s.
+    pub time: f64,
+    pub orbit: f32,
+    pub elevation: f32,
+    pub radius: f32,
+    pub target: [f32; 3],
+}
+
+impl Bookmark {
+    pub fn new(name: &str, time: f64, camera: &DemoCamera) -> Self {
+        Bookmark {
+            name: name.to_string(),
+            time,
+            orbit: camera.orbit,
+            elevation: camera.delta,
+            radius: camera.radius,
+            target: camera.target.to_array(),
+        }
+    }
+
+    pub fn apply_to_camera(&self, camera: &mut DemoCamera) {
+        camera.orbit = self.orbit;
+        camera.delta = self.elevation;
+        camera.radius = self.radius;
+        camera.target = Vec3::from(self.target);
+    }
+}
+
+/// State of the timeline.
+#[derive(Resource)]
+pub struct Timeline {
+    /// The plotted diagnostic.
+    pub diagnostic: ReplayDiagnostic,
+    /// Values of each diagnostic against time, in the order of [ReplayDiagnostic::ALL].
+    series: Vec<Vec<[f64; 2]>>,
+    pub bookmarks: Vec<Bookmark>,
+    /// File the bookmarks are loaded from and saved to.
+    bookmarks_path: Option<PathBuf>,
+    /// Name of the next bookmark.
+    bookmark_name: String,
+}
+
+impl Timeline {
+    pub fn new(trajectory: &Trajectory, bookmarks_path: Option<PathBuf>) -> Self {
+        let series = ReplayDiagnostic::ALL
+            .iter()
+            .map(|diagnostic| {
+                trajectory
+                    .frames
+                    .iter()
+                    .map(|frame| [frame.time, diagnostic.value(frame)])
+                    .collect()
+            })
+            .collect();
+        let bookmarks = bookmarks_path
+            .as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+                    .map_err(|e| warn!("Could not load bookmarks {}: {}", path.display(), e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Timeline {
+            diagnostic: ReplayDiagnostic::AtomNumber,
+            series,
+            bookmarks,
+            bookmarks_path,
+            bookmark_name: String::new(),
+        }
+    }
+
+    fn save_bookmarks(&self) {
+        if let Some(path) = &self.bookmarks_path {
+            let written = serde_json::to_string_pretty(&self.bookmarks)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(error) = written {
+                warn!("Could not save bookmarks {}: {}", path.display(), error);
+            }
+        }
+    }
+}
+
+/// Moves the replay to the frame of the trajectory at the given index.
+fn seek(clock: &mut SimulationClock, trajectory: &Trajectory, index: usize) {
+    if let Some(frame) = trajectory.frames.get(index) {
+        clock.time = frame.time;
+        clock.steps = (frame.time / trajectory.frame_interval).round() as u64;
+    }
+}
+
+/// A panel along the bottom of the window to play, step and scrub through the replay.
+#[allow(clippy::too_many_arguments)]
+pub fn timeline_ui(
+    mut egui_context: ResMut<EguiContext>,
+    mut timeline: ResMut<Timeline>,
+    replay: Res<Replay>,
+    mut clock: ResMut<SimulationClock>,
+    mut speed: ResMut<SimulationSpeed>,
+    keys: Res<Input<KeyCode>>,
+    scale: Res<Scale>,
+    mut cameras: Query<(&mut DemoCamera, &mut Transform)>,
+) {
+    let trajectory = &replay.trajectory;
+    if trajectory.frames.is_empty() {
+        return;
+    }
+    let last = trajectory.frames.len() - 1;
+    let index = trajectory.frame_at(clock.time).unwrap_or(0);
+    let mut target = None;
+    let mut view = None;
+
+    let ctx = egui_context.ctx_mut();
+    if !ctx.wants_keyboard_input() {
+        if keys.just_pressed(KeyCode::Space) {
+            if speed.is_paused() {
+                speed.resume();
+            } else {
+                speed.pause();
+            }
+        }
+        if keys.just_pressed(KeyCode::Left) {
+            target = Some(index.saturating_sub(1));
+        }
+        if keys.just_pressed(KeyCode::Right) {
+            target = Some((index + 1).min(last));
+        }
+    }
+
+    egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("⏮").on_hover_text("First frame").clicked() {
+                target = Some(0);
+            }
+            if ui.button("◀").on_hover_text("Previous frame").clicked() {
+                target = Some(index.saturating_sub(1));
+            }
+            if speed.is_paused() {
+                if ui.button("Play").clicked() {
+                    if index == last {
+                        seek(&mut clock, trajectory, 0);
+                    }
+                    speed.resume();
+                }
+            } else if ui.button("Pause").clicked() {
+                speed.pause();
+            }
+            if ui.button("▶").on_hover_text("Next frame").clicked() {
+                target = Some((index + 1).min(last));
+            }
+            if ui.button("⏭").on_hover_text("Last frame").clicked() {
+                target = Some(last);
+            }
+            let mut scrubbed = index;
+            if ui
+                .add(egui::Slider::new(&mut scrubbed, 0..=last).show_value(false))
+                .changed()
+            {
+                target = Some(scrubbed);
+            }
+            ui.label(format!(
+                "{} / {}, frame {}/{}",
+                format_time(trajectory.frames[index].time),
+                format_time(trajectory.duration()),
+                index + 1,
+                last + 1
+            ));
+            ui.separator();
+            speed_slider(ui, &mut speed, "Playback speed");
+        });
+
+        ui.horizontal(|ui| {
+            let mut diagnostic = timeline.diagnostic;
+            egui::ComboBox::from_id_source("timeline_diagnostic")
+                .selected_text(diagnostic.label())
+                .show_ui(ui, |ui| {
+                    for option in ReplayDiagnostic::ALL {
+                        ui.selectable_value(&mut diagnostic, option, option.label());
+                    }
+                });
+            if diagnostic != timeline.diagnostic {
+                timeline.diagnostic = diagnostic;
+            }
+            ui.separator();
+            ui.label("Bookmarks:");
+            let mut remove = None;
+            for (i, bookmark) in timeline.bookmarks.iter().enumerate() {
+                let button = ui.button(bookmark.name.as_str()).on_hover_text(format!(
+                    "{}. Right click to remove.",
+                    format_time(bookmark.time)
+                ));
+                if button.clicked() {
+                    view = Some(bookmark.clone());
+                }
+                if button.secondary_clicked() {
+                    remove = Some(i);
+                }
+            }
+            if let Some(i) = remove {
+                timeline.bookmarks.remove(i);
+                timeline.save_bookmarks();
+            }
+            ui.add(egui::TextEdit::singleline(&mut timeline.bookmark_name).desired_width(100.0));
+            if ui.button("Add bookmark").clicked() {
+                if let Some((camera, _)) = cameras.iter().next() {
+                    let name = if timeline.bookmark_name.trim().is_empty() {
+                        format_time(trajectory.frames[index].time)
+                    } else {
+                        timeline.bookmark_name.trim().to_string()
+                    };
+                    let bookmark = Bookmark::new(&name, trajectory.frames[index].time, camera);
+                    timeline.bookmarks.push(bookmark);
+                    timeline.bookmark_name.clear();
+                    timeline.save_bookmarks();
+                }
+            }
+        });
+
+        let position = ReplayDiagnostic::ALL
+            .iter()
+            .position(|diagnostic| *diagnostic == timeline.diagnostic)
+            .unwrap_or(0);
+        let series = timeline.series[position].clone();
+        let clicked = Plot::new("timeline_plot")
+            .height(120.0)
+            .allow_drag(false)
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(series)));
+                plot_ui.vline(VLine::new(trajectory.frames[index].time));
+                if plot_ui.plot_clicked() {
+                    plot_ui.pointer_coordinate().map(|point| point.x)
+                } else {
+                    None
+                }
+            })
+            .inner;
+        if let Some(time) = clicked {
+            target = trajectory.frame_at(time);
+        }
+    });
+
+    if let Some(bookmark) = view {
+        target = trajectory.frame_at(bookmark.time);
+        for (mut camera, mut transform) in cameras.iter_mut() {
+            bookmark.apply_to_camera(&mut camera);
+            let t = camera.get_transform(scale.0 as f32);
+            transform.translation = t.translation;
+            transform.rotation = t.rotation;
+        }
+    }
+    if let Some(index) = target {
+        seek(&mut clock, trajectory, index);
+        speed.pause();
+    }
+}
+
+/// Adds the [Timeline] of a replay. Requires the `TrajectoryReplayPlugin`, `SteppingPlugin` and
+/// `EguiPlugin`, and replaces the control bar.
+pub struct TimelinePlugin {
+    bookmarks_path: Option<PathBuf>,
+}
+
+impl TimelinePlugin {
+    /// Bookmarks are loaded from, and saved to, the given file.
+    pub fn new(bookmarks_path: Option<PathBuf>) -> Self {
+        TimelinePlugin { bookmarks_path }
+    }
+}
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        let timeline = Timeline::new(
+            &app.world.resource::<Replay>().trajectory,
+            self.bookmarks_path.clone(),
+        );
+        app.insert_resource(timeline);
+        app.add_system_to_stage(PresentationStage, timeline_ui.before(replay_trajectory));
+    }
+}
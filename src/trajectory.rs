@@ -0,0 +1,535 @@
+//! Records the trajectories of atoms to a file, and replays them without running the physics.
+//!
+//! A trajectory file starts with a header, followed by a frame for each recorded step. All values
+//! are little endian:
+//!
+//! - Header: the magic bytes `ATRJ`, the version as a `u32`, the time between frames in units of s
+//!   as an `f64`, and the name of the [Species] as a `u16` length followed by its bytes.
+//! - Frame: the simulated time in units of s as an `f64`, the number of atoms as a `u32`, then for
+//!   each atom its id as a `u32`, and its position in units of m and velocity in units of m/s as
+//!   six `f32`s.
+//!
+//! Each atom keeps its id for the whole run, even after others are despawned.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use atomecs::atom::{Atom, Position, Velocity};
+use atomecs::integrator::Timestep;
+use bevy::asset::Error;
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+use crate::controls::ResetSimulation;
+use crate::scene::Species;
+use crate::stepping::{
+    add_presentation_stage, advance_simulation_clock, PresentationStage, SimulationClock,
+    SimulationSpeed,
+};
+
+const MAGIC: &[u8; 4] = b"ATRJ";
+const VERSION: u32 = 1;
+/// Size of each atom in a frame: an id and six values.
+const ATOM_SIZE: usize = 4 + 6 * 4;
+
+/// State of an atom in a recorded frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordedAtom {
+    pub id: u32,
+    /// Position, in units of m.
+    pub position: [f32; 3],
+    /// Velocity, in units of m/s.
+    pub velocity: [f32; 3],
+}
+
+/// The atoms at a recorded step.
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    /// Simulated time, in units of s.
+    pub time: f64,
+    pub atoms: Vec<RecordedAtom>,
+}
+
+/// A recorded run, loaded from a trajectory file.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    pub species: Species,
+    /// Simulated time between frames, in units of s.
+    pub frame_interval: f64,
+    pub frames: Vec<Frame>,
+}
+
+/// Reads little endian values from a byte buffer.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < length {
+            return Err(Error::msg("Trajectory file ends unexpectedly."));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+impl Trajectory {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(Error::msg("Not a trajectory file."));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(Error::msg(format!(
+                "Unsupported trajectory version {}.",
+                version
+            )));
+        }
+        let frame_interval = reader.f64()?;
+        let length = reader.u16()? as usize;
+        let species = String::from_utf8(reader.take(length)?.to_vec())?;
+        let species = serde_json::from_value(serde_json::Value::String(species))?;
+
+        let mut frames = Vec::new();
+        while !reader.bytes.is_empty() {
+            let time = reader.f64()?;
+            let count = reader.u32()? as usize;
+            // The count is untrusted, so check the atoms are all there before allocating.
+            match count.checked_mul(ATOM_SIZE) {
+                Some(length) if length <= reader.bytes.len() => {}
+                _ => return Err(Error::msg("Trajectory file ends unexpectedly.")),
+            }
+            let mut atoms = Vec::with_capacity(count);
+            for _ in 0..count {
+                atoms.push(RecordedAtom {
+                    id: reader.u32()?,
+                    position: [reader.f32()?, reader.f32()?, reader.f32()?],
+                    velocity: [reader.f32()?, reader.f32()?, reader.f32()?],
+                });
+            }
+            frames.push(Frame { time, atoms });
+        }
+        Ok(Trajectory {
+            species,
+            frame_interval,
+            frames,
+        })
+    }
+
+    /// Simulated time of the last frame, in units of s.
+    pub fn duration(&self) -> f64 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+
+    /// Index of the last frame at or before the given time.
+    pub fn frame_at(&self, time: f64) -> Option<usize> {
+        match self.frames.partition_point(|frame| frame.time <= time) {
+            0 if self.frames.is_empty() => None,
+            0 => Some(0),
+            index => Some(index - 1),
+        }
+    }
+}
+
+/// Writes the atoms of the simulation to a trajectory file, every `interval` steps.
+#[derive(Resource)]
+pub struct TrajectoryRecorder {
+    pub path: PathBuf,
+    pub species: Species,
+    /// Number of simulation steps between recorded frames.
+    pub interval: u64,
+    writer: Option<BufWriter<File>>,
+    /// Ids given to the atoms recorded so far.
+    ids: HashMap<Entity, u32>,
+    next_id: u32,
+    last_time: f64,
+    finished: bool,
+}
+
+impl TrajectoryRecorder {
+    pub fn new(path: impl Into<PathBuf>, species: Species, interval: u64) -> Self {
+        TrajectoryRecorder {
+            path: path.into(),
+            species,
+            interval: interval.max(1),
+            writer: None,
+            ids: HashMap::new(),
+            next_id: 0,
+            last_time: 0.0,
+            finished: false,
+        }
+    }
+
+    fn write_header(&mut self, frame_interval: f64) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        let species = format!("{:?}", self.species);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&frame_interval.to_le_bytes())?;
+        writer.write_all(&(species.len() as u16).to_le_bytes())?;
+        writer.write_all(species.as_bytes())?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write_frame<'a>(
+        &mut self,
+        time: f64,
+        atoms: impl ExactSizeIterator<Item = (Entity, &'a Position, &'a Velocity)>,
+    ) -> Result<(), Error> {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        writer.write_all(&time.to_le_bytes())?;
+        writer.write_all(&(atoms.len() as u32).to_le_bytes())?;
+        for (entity, position, velocity) in atoms {
+            let next_id = &mut self.next_id;
+            let id = *self.ids.entry(entity).or_insert_with(|| {
+                *next_id += 1;
+                *next_id - 1
+            });
+            writer.write_all(&id.to_le_bytes())?;
+            for value in position.pos.iter().chain(velocity.vel.iter()) {
+                writer.write_all(&(*value as f32).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes any buffered frames to the file.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Stops recording, and closes the file.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        self.flush()?;
+        self.writer = None;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+/// Records the atoms on every `interval` steps of the simulation.
+pub fn record_trajectory(
+    mut recorder: ResMut<TrajectoryRecorder>,
+    clock: Res<SimulationClock>,
+    timestep: Res<Timestep>,
+    atoms: Query<(Entity, &Position, &Velocity), With<Atom>>,
+) {
+    if recorder.finished {
+        return;
+    }
+    if clock.time < recorder.last_time {
+        // The simulation was reset, which would restart the trajectory.
+        info!(
+            "Simulation reset, so stopped recording {}",
+            recorder.path.display()
+        );
+        if let Err(error) = recorder.finish() {
+            warn!(
+                "Could not write trajectory {}: {}",
+                recorder.path.display(),
+                error
+            );
+        }
+        return;
+    }
+    recorder.last_time = clock.time;
+    if clock.steps % recorder.interval != 0 {
+        return;
+    }
+
+    let mut written = Ok(());
+    if recorder.writer.is_none() {
+        let frame_interval = timestep.delta * recorder.interval as f64;
+        written = recorder.write_header(frame_interval);
+    }
+    written = written.and_then(|_| recorder.write_frame(clock.time, atoms.iter()));
+    if let Err(error) = written {
+        warn!(
+            "Could not write trajectory {}: {}",
+            recorder.path.display(),
+            error
+        );
+        recorder.finished = true;
+        recorder.writer = None;
+    }
+}
+
+/// Writes buffered frames once per rendered frame, so the file is complete when the app closes.
+fn flush_trajectory(mut recorder: ResMut<TrajectoryRecorder>) {
+    if let Err(error) = recorder.flush() {
+        warn!(
+            "Could not write trajectory {}: {}",
+            recorder.path.display(),
+            error
+        );
+    }
+}
+
+/// Records the trajectories of the atoms with a [TrajectoryRecorder].
+pub struct TrajectoryRecorderPlugin {
+    path: PathBuf,
+    species: Species,
+    interval: u64,
+}
+
+impl TrajectoryRecorderPlugin {
+    pub fn new(path: impl Into<PathBuf>, species: Species, interval: u64) -> Self {
+        TrajectoryRecorderPlugin {
+            path: path.into(),
+            species,
+            interval,
+        }
+    }
+
+    /// Reads the recorder from the command line arguments, if they contain `--record <file>`. The
+    /// number of steps between frames is set by `--record-interval`, which defaults to 1.
+    pub fn from_args(species: Species) -> Result<Option<Self>, Error> {
+        let args: Vec<String> = std::env::args().collect();
+        let value = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| args.get(index + 1))
+        };
+        let path = match value("--record") {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let interval = match value("--record-interval") {
+            Some(interval) => interval
+                .parse()
+                .map_err(|_| Error::msg(format!("Invalid record interval {}.", interval)))?,
+            None => 1,
+        };
+        Ok(Some(TrajectoryRecorderPlugin::new(path, species, interval)))
+    }
+}
+
+impl Plugin for TrajectoryRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.init_resource::<SimulationClock>();
+        app.insert_resource(TrajectoryRecorder::new(
+            self.path.clone(),
+            self.species,
+            self.interval,
+        ));
+        app.add_system(record_trajectory.after(advance_simulation_clock));
+        app.add_system_to_stage(PresentationStage, flush_trajectory);
+    }
+}
+
+/// A recorded trajectory, shown in place of a running simulation.
+#[derive(Resource)]
+pub struct Replay {
+    pub trajectory: Trajectory,
+}
+
+/// Marks an atom spawned by the [Replay], with its recorded id.
+#[derive(Component)]
+pub struct ReplayedAtom(pub u32);
+
+/// Moves the replayed atoms to the recorded frame at the current simulated time, spawning and
+/// despawning atoms as they appear and leave.
+///
+/// The replayed atoms have a `Position` like simulated atoms, so are rendered in the same way.
+pub fn replay_trajectory(
+    mut commands: Commands,
+    replay: Res<Replay>,
+    clock: Res<SimulationClock>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut atoms: Query<(Entity, &ReplayedAtom, &mut Position, &mut Velocity)>,
+) {
+    let trajectory = &replay.trajectory;
+    let index = match trajectory.frame_at(clock.time) {
+        Some(index) => index,
+        None => return,
+    };
+    if clock.time >= trajectory.duration() && !speed.is_paused() {
+        speed.pause();
+    }
+
+    let mut recorded: HashMap<u32, &RecordedAtom> = trajectory.frames[index]
+        .atoms
+        .iter()
+        .map(|atom| (atom.id, atom))
+        .collect();
+    for (entity, replayed, mut position, mut velocity) in atoms.iter_mut() {
+        match recorded.remove(&replayed.0) {
+            Some(atom) => {
+                position.pos = to_vector(atom.position);
+                velocity.vel = to_vector(atom.velocity);
+            }
+            None => commands.entity(entity).despawn_recursive(),
+        }
+    }
+    for atom in recorded.values() {
+        commands.spawn((
+            Atom,
+            Position {
+                pos: to_vector(atom.position),
+            },
+            Velocity {
+                vel: to_vector(atom.velocity),
+            },
+            ReplayedAtom(atom.id),
+        ));
+    }
+}
+
+fn to_vector(value: [f32; 3]) -> Vector3<f64> {
+    Vector3::new(value[0] as f64, value[1] as f64, value[2] as f64)
+}
+
+/// Starts the replay again when the simulation is reset.
+fn restart_replay(
+    mut reset: EventReader<ResetSimulation>,
+    mut clock: ResMut<SimulationClock>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    if reset.iter().count() > 0 {
+        *clock = SimulationClock::default();
+        speed.resume();
+    }
+}
+
+/// Replays a [Trajectory], one recorded frame per simulation step.
+///
+/// Requires the `SteppingPlugin`, which then sets the speed of the replay. No physics plugins
+/// should be added.
+pub struct TrajectoryReplayPlugin {
+    trajectory: Trajectory,
+}
+
+impl TrajectoryReplayPlugin {
+    pub fn new(trajectory: Trajectory) -> Self {
+        TrajectoryReplayPlugin { trajectory }
+    }
+}
+
+impl Plugin for TrajectoryReplayPlugin {
+    fn build(&self, app: &mut App) {
+        add_presentation_stage(app);
+        app.add_event::<ResetSimulation>();
+        app.insert_resource(Timestep {
+            delta: self.trajectory.frame_interval,
+        });
+        app.insert_resource(Replay {
+            trajectory: self.trajectory.clone(),
+        });
+        app.add_system_to_stage(CoreStage::PreUpdate, restart_replay);
+        app.add_system_to_stage(PresentationStage, replay_trajectory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("round_trip_{}.traj", std::process::id()));
+        let mut recorder = TrajectoryRecorder::new(&path, Species::Strontium88_461, 2);
+        recorder.write_header(2e-5).unwrap();
+        let atoms = [
+            (
+                Entity::from_raw(3),
+                Position {
+                    pos: Vector3::new(1e-3, -2e-3, 0.5),
+                },
+                Velocity {
+                    vel: Vector3::new(10.0, 0.0, -4.5),
+                },
+            ),
+            (
+                Entity::from_raw(7),
+                Position {
+                    pos: Vector3::new(0.0, 0.25, 0.0),
+                },
+                Velocity {
+                    vel: Vector3::new(-1.0, 2.0, 3.0),
+                },
+            ),
+        ];
+        recorder
+            .write_frame(0.0, atoms.iter().map(|(e, p, v)| (*e, p, v)))
+            .unwrap();
+        recorder
+            .write_frame(4e-5, atoms[1..].iter().map(|(e, p, v)| (*e, p, v)))
+            .unwrap();
+        recorder.finish().unwrap();
+
+        let trajectory = Trajectory::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trajectory.species, Species::Strontium88_461);
+        assert_eq!(trajectory.frame_interval, 2e-5);
+        assert_eq!(trajectory.frames.len(), 2);
+        assert_eq!(trajectory.frames[0].time, 0.0);
+        assert_eq!(
+            trajectory.frames[0].atoms,
+            vec![
+                RecordedAtom {
+                    id: 0,
+                    position: [1e-3, -2e-3, 0.5],
+                    velocity: [10.0, 0.0, -4.5],
+                },
+                RecordedAtom {
+                    id: 1,
+                    position: [0.0, 0.25, 0.0],
+                    velocity: [-1.0, 2.0, 3.0],
+                },
+            ]
+        );
+        // Atoms keep their id in later frames.
+        assert_eq!(trajectory.frames[1].time, 4e-5);
+        assert_eq!(trajectory.frames[1].atoms.len(), 1);
+        assert_eq!(trajectory.frames[1].atoms[0].id, 1);
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1e-5f64.to_le_bytes());
+        let species = "Strontium88_461";
+        bytes.extend_from_slice(&(species.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(species.as_bytes());
+        bytes.extend_from_slice(&0.0f64.to_le_bytes());
+        // A frame claiming more atoms than the file holds is rejected before allocating.
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; ATOM_SIZE]);
+        assert!(Trajectory::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Physics validation of the 3D MOT against the Doppler limit.
+//!
+//! The detuning is swept over the configuration in `assets/doppler_validation.json`, and the
+//! minimum temperature of the cloud is compared with ħΓ/2k_B. The beams are weak, so that the
+//! heating from saturation is small. The sweep is slow in debug builds, so run it with
+//!
+//! cargo test --release --test doppler
+
+use std::path::PathBuf;
+
+use atomecs::species::Rubidium87_780D2;
+use atomecs_demos::doppler::{
+    doppler_limit, minimum_temperature, run_detuning_sweep, DopplerConfiguration,
+};
+
+const SEED: u64 = 42;
+/// Largest relative difference between the minimum temperature and the Doppler limit. This allows
+/// for the residual saturation of the beams and the statistical error of the temperature.
+const TOLERANCE: f64 = 0.25;
+
+#[test]
+fn doppler_limit_of_rubidium() {
+    // Γ = 2π × 6.07 MHz for the Rb87 D2 line.
+    let limit = doppler_limit::<Rubidium87_780D2>();
+    assert!((limit - 146e-6).abs() < 2e-6, "Doppler limit {} K", limit);
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    ignore = "too slow without optimisations, run with --release"
+)]
+fn minimum_temperature_is_doppler_limit() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/doppler_validation.json");
+    let configuration = DopplerConfiguration::load(&path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e));
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let results = run_detuning_sweep(&configuration, SEED, workers, |_, _| {});
+
+    let (detuning, cloud) =
+        minimum_temperature(&results).expect("Every atom escaped at every detuning.");
+    let limit = doppler_limit::<Rubidium87_780D2>();
+    let difference = cloud.temperature / limit - 1.0;
+    assert!(
+        difference.abs() < TOLERANCE,
+        "Minimum temperature {:.1} μK at {} MHz differs from the Doppler limit {:.1} μK by {:+.1}%.",
+        cloud.temperature * 1e6,
+        detuning,
+        limit * 1e6,
+        difference * 100.0
+    );
+}
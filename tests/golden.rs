@@ -0,0 +1,240 @@
+//! Golden-output regression tests for the demos.
+//!
+//! Each demo is set up headless, with a fixed seed, and run for a fixed number of steps. Summary
+//! statistics of its atoms, and the flux through its detectors if it has any, are then compared
+//! with the golden values in `tests/golden/<demo>.json`, so that changes to the demos or to AtomECS
+//! that alter the physics are caught.
+//!
+//! Scattering fluctuations and emission forces draw on AtomECS's own random numbers, so they are
+//! turned off to make the runs reproducible. After a change that is meant to alter the physics,
+//! check the new values and regenerate the golden files with
+//!
+//! BLESS=1 cargo test --release --test golden
+//!
+//! A demo without a golden file fails, so new demos must be blessed, and their golden files
+//! committed, before the test passes.
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+use atomecs::laser_cooling::force::EmissionForceOption;
+use atomecs::laser_cooling::photons_scattered::ScatteringFluctuationsOption;
+use atomecs::magnetic::grid::PrecalculatedMagneticFieldGrid;
+use atomecs::species::{Rubidium87_780D2, Strontium88_461};
+use atomecs_demos::aion_source::{AionSourcePlugin, ExperimentConfiguration};
+use atomecs_demos::detector::Detector;
+use atomecs_demos::fields::MagneticFieldGrid;
+use atomecs_demos::random::SimulationRng;
+use atomecs_demos::scene::{SceneDescription, ScenePlugin, Species, BEAM_NUMBER};
+use atomecs_demos::sources::AtomWeight;
+use atomecs_demos::statistics::CloudStatistics;
+use atomecs_demos::stepping::{SimulationClock, SimulationSpeed, SteppingPlugin};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const SEED: u64 = 42;
+const STEPS: u32 = 400;
+/// Time to wait for field maps to load before giving up, in units of s.
+const LOADING_TIMEOUT: f64 = 60.0;
+
+/// Summary of a demo after running it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct Summary {
+    /// Number of simulated atoms.
+    atom_number: usize,
+    /// Mean speed of the atoms, in units of m/s.
+    mean_speed: f64,
+    /// Temperature of the atoms, in units of K.
+    temperature: f64,
+    /// Flux of real atoms through the detectors, in units of atoms/s. Only demos with a detector
+    /// measure a flux.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flux: Option<f64>,
+}
+
+/// Relative and absolute tolerances of each quantity, so that runs that differ only by rounding
+/// still match.
+const TOLERANCES: [(&str, f64, f64); 4] = [
+    ("atom_number", 0.0, 0.0),
+    ("mean_speed", 1e-3, 1e-9),
+    ("temperature", 1e-3, 1e-12),
+    ("flux", 1e-3, 1e-6),
+];
+
+impl Summary {
+    fn quantities(&self) -> [Option<f64>; 4] {
+        [
+            Some(self.atom_number as f64),
+            Some(self.mean_speed),
+            Some(self.temperature),
+            self.flux,
+        ]
+    }
+
+    /// Quantities that differ from the golden values by more than their tolerance.
+    fn differences(&self, golden: &Summary) -> Vec<String> {
+        let mut differences = Vec::new();
+        for (i, (name, relative, absolute)) in TOLERANCES.iter().enumerate() {
+            let (value, expected) = (self.quantities()[i], golden.quantities()[i]);
+            let matches = match (value, expected) {
+                (Some(value), Some(expected)) => {
+                    (value - expected).abs() <= relative * expected.abs() + absolute
+                }
+                (value, expected) => value == expected,
+            };
+            if !matches {
+                differences.push(format!(
+                    "{}: {:?} (golden value {:?})",
+                    name, value, expected
+                ));
+            }
+        }
+        differences
+    }
+}
+
+/// Runs a demo, built by `build`, and summarises its atoms.
+fn run_demo(build: impl FnOnce(&mut App)) -> Summary {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    app.add_plugin(AssetPlugin::default());
+    app.add_plugin(SteppingPlugin);
+    build(&mut app);
+    app.insert_resource(SimulationRng::seeded(SEED));
+    app.insert_resource(ScatteringFluctuationsOption::Off);
+    app.insert_resource(EmissionForceOption::Off);
+
+    // Set up the demo, and wait for its field maps to load before stepping.
+    app.world.resource_mut::<SimulationSpeed>().pause();
+    let mut loading = app.world.query_filtered::<(), (
+        With<Handle<MagneticFieldGrid>>,
+        Without<PrecalculatedMagneticFieldGrid>,
+    )>();
+    let started = Instant::now();
+    loop {
+        app.update();
+        if loading.iter(&app.world).next().is_none() {
+            break;
+        }
+        assert!(
+            started.elapsed().as_secs_f64() < LOADING_TIMEOUT,
+            "Field maps did not load."
+        );
+    }
+
+    {
+        let mut speed = app.world.resource_mut::<SimulationSpeed>();
+        speed.frame_budget = f64::INFINITY;
+        speed.step(STEPS);
+    }
+    while app.world.resource::<SimulationSpeed>().is_stepping() {
+        app.update();
+    }
+    assert_eq!(app.world.resource::<SimulationClock>().steps, STEPS as u64);
+
+    let cloud = CloudStatistics::measure(&mut app.world);
+    let mut detectors = app.world.query::<&Detector>();
+    let detected: Option<u64> = detectors
+        .iter(&app.world)
+        .map(|detector| detector.detected)
+        .reduce(|a, b| a + b);
+    let weight = app
+        .world
+        .get_resource::<AtomWeight>()
+        .map_or(1.0, |weight| weight.0);
+    Summary {
+        atom_number: cloud.atom_number,
+        mean_speed: cloud.mean_speed,
+        temperature: cloud.temperature,
+        flux: detected.map(|detected| {
+            detected as f64 * weight / app.world.resource::<SimulationClock>().time
+        }),
+    }
+}
+
+fn run_scene(name: &str) -> Summary {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("assets/scenes")
+        .join(name)
+        .with_extension("json");
+    let scene = SceneDescription::load(path);
+    run_demo(|app| match scene.species {
+        Species::Strontium88_461 => {
+            app.add_plugin(ScenePlugin::<Strontium88_461, { BEAM_NUMBER }>::new(scene));
+        }
+        Species::Rubidium87_780D2 => {
+            app.add_plugin(ScenePlugin::<Rubidium87_780D2, { BEAM_NUMBER }>::new(scene));
+        }
+    })
+}
+
+/// Compares the summary with the golden values of the demo, or writes them when blessing.
+fn check_golden(name: &str, summary: Summary) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+        .with_extension("json");
+    let bless = std::env::var_os("BLESS").map_or(false, |bless| bless != "0");
+    if bless {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let json = serde_json::to_string_pretty(&summary).unwrap();
+        std::fs::write(&path, json + "\n").unwrap();
+        eprintln!("Wrote golden values for {} to {}.", name, path.display());
+        return;
+    }
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "Could not read golden values {}: {}\nRun with BLESS=1 to write them.",
+            path.display(),
+            e
+        )
+    });
+    let golden: Summary = serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("Could not parse {}: {}", path.display(), e));
+    let differences = summary.differences(&golden);
+    assert!(
+        differences.is_empty(),
+        "{} no longer matches its golden values:\n{}\nRun with BLESS=1 if the change is intended.",
+        name,
+        differences.join("\n")
+    );
+}
+
+#[test]
+fn aion_source() {
+    let summary = run_demo(|app| {
+        app.add_plugin(AionSourcePlugin);
+        app.init_resource::<ExperimentConfiguration>();
+    });
+    check_golden("aion_source", summary);
+}
+
+#[test]
+fn scene_2d_mot() {
+    check_golden("scene_2d_mot", run_scene("2d_mot"));
+}
+
+#[test]
+fn scene_3d_mot() {
+    check_golden("scene_3d_mot", run_scene("3d_mot"));
+}
+
+#[test]
+fn scene_aion_source() {
+    check_golden("scene_aion_source", run_scene("aion_source"));
+}
+
+#[test]
+fn scene_mot_sequence() {
+    check_golden("scene_mot_sequence", run_scene("mot_sequence"));
+}
+
+#[test]
+fn scene_schreck() {
+    check_golden("scene_schreck", run_scene("schreck"));
+}
+
+#[test]
+fn scene_top_trap() {
+    check_golden("scene_top_trap", run_scene("top_trap"));
+}